@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+use aethaum_core::code_generator::AethaumTranspiler;
+use aethaum_core::code_generator::project::DEFAULT_OUTPUT_DIR;
+use aethaum_core::code_generator::tool_schema::{event_tools, ToolFormat};
+use aethaum_core::ecs::checker::AethaumChecker;
+use aethaum_core::ecs::loader::ProjectLoader;
+use aethaum_core::ecs::module::AethaumProject;
+use aethaum_core::toml_parser::parsed::WorldBuild;
+use miette::{bail, IntoDiagnostic, WrapErr};
+use crate::template::{DEFAULT_TEMPLATE, NAME_PLACEHOLDER};
+
+///加载并检查项目
+pub fn check(dir: &Path) -> miette::Result<AethaumProject> {
+    let project = ProjectLoader::new(dir.to_path_buf()).load()?;
+    Ok(AethaumChecker::run_check(project)?)
+}
+///检查并转译项目，返回输出目录；runtime 覆盖 world.toml 中的 [build].runtime
+pub fn build(dir: &Path, runtime: Option<&Path>) -> miette::Result<PathBuf> {
+    let mut project = check(dir)?;
+    if let Some(runtime) = runtime {
+        let runtime = std::path::absolute(runtime).into_diagnostic()?;
+        project.world.build.get_or_insert_with(|| WorldBuild { output_dir: DEFAULT_OUTPUT_DIR.into(), runtime: None }).runtime = Some(runtime);
+    }
+    let output_dir = project.world.build.as_ref()
+        .map(|build| build.output_dir.as_str())
+        .unwrap_or(DEFAULT_OUTPUT_DIR);
+    let output_dir = dir.join(output_dir);
+    for file in AethaumTranspiler::run(&project)? {
+        file.write_to(&output_dir)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write {}", output_dir.join(&file.path).display()))?;
+    }
+    Ok(output_dir)
+}
+///检查项目并将事件导出为 LLM 的工具定义，tag 不为空时只导出带有该标签的事件
+pub fn tools(dir: &Path, tag: Option<&str>, format: ToolFormat) -> miette::Result<String> {
+    let project = check(dir)?;
+    let tools = event_tools(&project, tag, format)?;
+    serde_json::to_string_pretty(&tools).into_diagnostic()
+}
+///在 parent 下创建名为 name 的项目，返回项目根目录
+pub fn new(name: &str, parent: &Path) -> miette::Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\', '"']) || name == "." || name == ".." {
+        bail!("'{}' is not a valid project name", name);
+    }
+    let root = parent.join(name);
+    if root.exists() {
+        bail!("{} already exists", root.display());
+    }
+    for (path, content) in DEFAULT_TEMPLATE {
+        let path = root.join(path);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).into_diagnostic()?;
+        }
+        std::fs::write(&path, content.replace(NAME_PLACEHOLDER, name))
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(root)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const RUNTIME_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../aethaum-runtime");
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    #[test]
+    fn test_new_check_build() {
+        let parent = temp_dir("aethaum_cli_test_new_check_build");
+        let root = new("demo", &parent).unwrap();
+        assert!(new("demo", &parent).is_err());
+        let project = check(&root).unwrap();
+        assert_eq!(project.world.normal.name, "demo");
+        // 模板没有指定运行时库的位置
+        assert!(build(&root, None).is_err());
+        let output_dir = build(&root, Some(Path::new(RUNTIME_DIR))).unwrap();
+        assert_eq!(output_dir, root.join("generated"));
+        assert!(output_dir.join("Cargo.toml").exists());
+        assert!(output_dir.join("src/lib.rs").exists());
+        assert!(output_dir.join("src/game.rs").exists());
+        std::fs::remove_dir_all(parent).unwrap();
+    }
+    #[test]
+    fn test_check_reports_errors() {
+        let parent = temp_dir("aethaum_cli_test_check_reports_errors");
+        let root = new("broken", &parent).unwrap();
+        std::fs::write(root.join("modules/game/entity_protos/walker.toml"), "[[entity_protos]]\nname = \"Walker\"\ncomponents = [\"Health\"]\n").unwrap();
+        let report = check(&root).err().unwrap();
+        let labels = report.labels().map(|labels| labels.count()).unwrap_or_default();
+        assert_eq!(labels, 1);
+        assert!(report.to_string().contains("walker.toml:1:1"), "{}", report);
+        std::fs::remove_dir_all(parent).unwrap();
+    }
+    #[test]
+    fn test_tools() {
+        let parent = temp_dir("aethaum_cli_test_tools");
+        let root = new("demo", &parent).unwrap();
+        let exported = serde_json::from_str::<serde_json::Value>(&tools(&root, None, ToolFormat::OpenAi).unwrap()).unwrap();
+        assert_eq!(exported[0]["function"]["name"], "Teleport");
+        assert_eq!(tools(&root, Some("agent-action"), ToolFormat::Anthropic).unwrap(), "[]");
+        std::fs::remove_dir_all(parent).unwrap();
+    }
+    fn copy_dir(from: &Path, to: &Path) {
+        std::fs::create_dir_all(to).unwrap();
+        for entry in std::fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                copy_dir(&entry.path(), &to.join(entry.file_name()));
+            } else {
+                std::fs::copy(entry.path(), to.join(entry.file_name())).unwrap();
+            }
+        }
+    }
+    ///构建示例项目并通过 --env 运行，HealthSystem 的脚本不应报错
+    #[test]
+    fn test_build_and_run_sample_project() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::process::{Command, Stdio};
+        use serde_json::{json, Value};
+        let parent = temp_dir("aethaum_cli_test_build_and_run_sample_project");
+        let root = parent.join("test_project");
+        copy_dir(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_project")), &root);
+        let output_dir = build(&root, Some(Path::new(RUNTIME_DIR))).unwrap();
+        // 生成的项目单独使用一个目标目录，重复运行时只需要重新编译生成的代码
+        let mut child = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+            .args(["run", "--quiet", "--", "--env"])
+            .current_dir(&output_dir)
+            .env("CARGO_TARGET_DIR", concat!(env!("CARGO_MANIFEST_DIR"), "/../../target/sample_project"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut request = move |id: u64, method: &str, params: Value| -> Value {
+            writeln!(stdin, "{}", json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })).unwrap();
+            let mut line = String::new();
+            stdout.read_line(&mut line).unwrap();
+            let response = serde_json::from_str::<Value>(&line).unwrap_or_else(|_| panic!("unexpected output: {:?}", line));
+            assert!(response.get("error").is_none(), "{}", response);
+            response["result"].clone()
+        };
+        let observation = request(1, "reset", json!({ "seed": 1 }));
+        assert_eq!(observation.as_array().unwrap().len(), 3);
+        let attacker = observation[0]["entity"].clone();
+        let action = json!({ "event_name": "explore::EntityDamaged", "fields": { "damage": 10, "attacker": attacker } });
+        let transition = request(2, "step", json!([[action]]));
+        assert!((transition["reward"].as_f64().unwrap() - 2.2).abs() < 1e-6, "{}", transition);
+        // 治疗事件由 logic_file 处理，脚本相对于项目根目录而不是运行目录加载
+        let heal = json!({ "event_name": "explore::EntityHealed", "fields": { "amount": 5.0, "healer": attacker } });
+        request(3, "step", json!([[heal]]));
+        // 多推进几帧，使间隔为 0.1 秒的 update 至少执行一次
+        for id in 4..10 {
+            request(id, "step", json!([]));
+        }
+        // 关闭标准输入后进程退出
+        drop(request);
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.trim().is_empty(), "{}", stderr);
+        std::fs::remove_dir_all(parent).unwrap();
+    }
+    #[test]
+    fn test_new_rejects_invalid_name() {
+        assert!(new("", Path::new(".")).is_err());
+        assert!(new("a/b", Path::new(".")).is_err());
+    }
+}
@@ -1,3 +1,89 @@
-fn main() {
-    println!("Hello, world!");
+use std::path::PathBuf;
+use std::process::ExitCode;
+use aethaum_core::code_generator::tool_schema::ToolFormat;
+use clap::{Parser, Subcommand, ValueEnum};
+
+mod commands;
+mod template;
+
+#[derive(Parser)]
+#[command(name = "aethaum", version, about = "Check, build and scaffold Aethaum worlds")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+#[derive(Subcommand)]
+enum Command {
+    /// Load the project and run all checks, exit with a non-zero code on errors
+    Check {
+        /// Project root containing world.toml
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Check the project and transpile it into [build].output_dir
+    Build {
+        /// Project root containing world.toml
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// Directory of the aethaum-runtime crate, overrides [build].runtime
+        #[arg(long, env = "AETHAUM_RUNTIME")]
+        runtime: Option<PathBuf>,
+    },
+    /// Export declared events as LLM tool definitions (JSON) on stdout
+    Tools {
+        /// Project root containing world.toml
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// Only export events with this tag, e.g. "agent-action"
+        #[arg(long)]
+        tag: Option<String>,
+        /// Tool definition format
+        #[arg(long, value_enum, default_value_t = Format::Openai)]
+        format: Format,
+    },
+    /// Create a new project from the default template
+    New {
+        /// Project name, also used as the directory name
+        name: String,
+    },
+}
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// {"type": "function", "function": {"name", "description", "parameters"}}
+    Openai,
+    /// {"name", "description", "input_schema"}
+    Anthropic,
+}
+impl From<Format> for ToolFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Openai => ToolFormat::OpenAi,
+            Format::Anthropic => ToolFormat::Anthropic,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Check { dir } => commands::check(&dir).map(|project| {
+            println!("World '{}' passed all checks.", project.world.normal.name);
+        }),
+        Command::Build { dir, runtime } => commands::build(&dir, runtime.as_deref()).map(|output_dir| {
+            println!("Generated code written to {}.", output_dir.display());
+        }),
+        Command::Tools { dir, tag, format } => commands::tools(&dir, tag.as_deref(), format.into()).map(|tools| {
+            println!("{}", tools);
+        }),
+        Command::New { name } => commands::new(&name, &PathBuf::from(".")).map(|root| {
+            println!("Created world '{}' in {}.", name, root.display());
+        }),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(report) => {
+            eprintln!("{:?}", report);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,9 @@
+///默认模板，路径相对于项目根目录，内容中的 {{name}} 会被替换为项目名
+pub const DEFAULT_TEMPLATE: &[(&str, &str)] = &[
+    ("world.toml", include_str!("../../../templates/default/world.toml")),
+    ("modules/game/components/movement.toml", include_str!("../../../templates/default/modules/game/components/movement.toml")),
+    ("modules/game/events/movement.toml", include_str!("../../../templates/default/modules/game/events/movement.toml")),
+    ("modules/game/entity_protos/walker.toml", include_str!("../../../templates/default/modules/game/entity_protos/walker.toml")),
+    ("modules/game/systems/movement.toml", include_str!("../../../templates/default/modules/game/systems/movement.toml")),
+];
+pub const NAME_PLACEHOLDER: &str = "{{name}}";
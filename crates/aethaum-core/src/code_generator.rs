@@ -1,15 +1,139 @@
-mod transpile;
-mod lua_binding;
-mod aethaum_predefined;
-mod utils;
+pub mod transpile;
+pub mod lua_binding;
+pub mod aethaum_predefined;
+pub mod utils;
+pub mod project;
+pub mod tool_schema;
 
-use bevy_ecs::event::Events;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use miette::Diagnostic;
 use thiserror::Error;
+use crate::code_generator::project::{cargo_manifest, crate_name, transpile_lib, transpile_main};
+use crate::code_generator::transpile::Transpile;
+use crate::code_generator::utils::format_rust_code;
+use crate::ecs::module::{AethaumProject, CustomTypes, EcsModule};
 
-#[derive(Debug,Error)]
+#[derive(Debug,Error,Diagnostic)]
 pub enum TranspileError {
     #[error("Error to write generated code, {0}")]
     WriteError(#[from] core::fmt::Error),
     #[error("Error to format generated code, {0}")]
-    FormatError(#[from] syn::Error)
-}
\ No newline at end of file
+    FormatError(#[from] syn::Error),
+    #[error("Cannot resolve type '{0}' when generating default value")]
+    UnresolvedType(String),
+    #[error("Integer {0} is out of range for int, check the project before transpiling")]
+    IntOutOfRange(i64),
+    #[error("Prototype '{0}' still extends another prototype, check the project before transpiling")]
+    UnflattenedPrototype(String),
+    #[error("Query '{0}' is not defined in system '{1}', check the project before transpiling")]
+    UnknownQuery(String, String),
+    #[error("Cannot locate aethaum-runtime, set [build].runtime in world.toml, pass --runtime to aethaum build (or set AETHAUM_RUNTIME), or declare aethaum-runtime in [cargo.dependencies]")]
+    RuntimeUnresolved,
+    #[error("aethaum-runtime not found at '{}', [build].runtime and --runtime must point at the aethaum-runtime crate directory", .0.display())]
+    RuntimeNotFound(std::path::PathBuf),
+    #[error("Error to generate Cargo.toml, {0}")]
+    ManifestError(#[from] toml::ser::Error),
+}
+///转译时可见的项目信息，用于解析自定义类型的默认值
+#[derive(Default)]
+pub struct TranspileContext<'a> {
+    module_name: &'a str,
+    types: CustomTypes<'a>,
+    ///多个模块中同名的原型，只能通过模块限定的名字生成
+    ambiguous_prototypes: HashSet<&'a str>,
+}
+impl<'a> TranspileContext<'a> {
+    pub fn new(module_name: &'a str, types: CustomTypes<'a>) -> Self {
+        Self { module_name, types, ambiguous_prototypes: HashSet::new() }
+    }
+    ///记录在多个模块中定义的原型名
+    pub fn with_prototypes_of(mut self, modules: impl IntoIterator<Item = &'a EcsModule>) -> Self {
+        let mut seen = HashSet::new();
+        for proto in modules.into_iter().flat_map(|module| module.entity_protos.iter().flatten()) {
+            if !seen.insert(proto.name.as_str()) {
+                self.ambiguous_prototypes.insert(proto.name.as_str());
+            }
+        }
+        self
+    }
+    pub fn enter_module(&mut self, module_name: &'a str) {
+        self.module_name = module_name;
+    }
+    pub fn module_name(&self) -> &'a str {
+        self.module_name
+    }
+    pub fn types(&self) -> &CustomTypes<'a> {
+        &self.types
+    }
+    pub fn is_ambiguous_prototype(&self, name: &str) -> bool {
+        self.ambiguous_prototypes.contains(name)
+    }
+}
+///生成的文件，路径相对于输出目录
+#[derive(Debug)]
+pub struct GeneratedFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+impl GeneratedFile {
+    pub fn new(path: impl Into<PathBuf>, content: String) -> Self {
+        Self { path: path.into(), content }
+    }
+    pub fn write_to(&self, output_dir: &Path) -> Result<(), std::io::Error> {
+        let path = output_dir.join(&self.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, &self.content)
+    }
+}
+pub struct AethaumTranspiler;
+impl AethaumTranspiler {
+    ///将已通过检查的项目转译为一个完整的 Cargo 项目，每个模块对应 src 下的一个文件
+    pub fn run(project: &AethaumProject) -> Result<Vec<GeneratedFile>, TranspileError> {
+        let mut modules = project.module_tree.get_modules();
+        modules.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut context = TranspileContext::new("", CustomTypes::default().with_modules(modules.iter().copied()))
+            .with_prototypes_of(modules.iter().copied());
+        let mut files = vec![GeneratedFile::new("Cargo.toml", cargo_manifest(&project.world, &project.root)?)];
+        for module in modules.iter() {
+            context.enter_module(module.name.as_str());
+            let content = format_rust_code(module.transpile_with(&context)?)?;
+            files.push(GeneratedFile::new(format!("src/{}.rs", module.name), content));
+        }
+        files.push(GeneratedFile::new("src/lib.rs", format_rust_code(transpile_lib(&modules))?));
+        let crate_name = crate_name(&project.world.normal.name);
+        files.push(GeneratedFile::new("src/main.rs", format_rust_code(transpile_main(&crate_name, &modules, &project.world, &project.root))?));
+        Ok(files)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::checker::AethaumChecker;
+    use crate::ecs::loader::ProjectLoader;
+    #[test]
+    fn test_transpile_project() {
+        let project = ProjectLoader::new(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../templates/default"))).load().unwrap();
+        let mut project = AethaumChecker::run_check(project).unwrap();
+        project.world.build.as_mut().unwrap().runtime = Some(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../aethaum-runtime")));
+        let files = AethaumTranspiler::run(&project).unwrap();
+        let paths = files.iter().map(|file| file.path.to_string_lossy().into_owned()).collect::<Vec<_>>();
+        assert_eq!(paths, vec!["Cargo.toml", "src/game.rs", "src/lib.rs", "src/main.rs"]);
+        assert!(files[0].content.contains("[workspace]"));
+        assert!(files[1].content.contains("impl Describe for Position"));
+        assert!(files[2].content.contains("pub mod game;"));
+        assert!(files[2].content.contains("pub trait Describe"));
+        assert!(files[1].content.contains("impl Plugin for GamePlugin"));
+        assert!(files[1].content.contains("app.add_event::<Teleport>()"));
+        assert!(files[1].content.contains("app.add_systems(Update, spawn_walker_system)"));
+        assert!(files[3].content.contains("::game::GamePlugin"));
+        assert!(files[3].content.contains("with_project_root(project_root())"));
+        assert!(files[3].content.contains("#[cfg(debug_assertions)] app.add_plugins(HotReloadPlugin::new(project_root()));"));
+        for file in files.iter().filter(|file| file.path.extension().is_some_and(|ext| ext == "rs")) {
+            let parsed = syn::parse_str::<syn::File>(&file.content);
+            assert!(parsed.is_ok(), "{} has syntax errors: {:?}", file.path.display(), parsed.err());
+        }
+    }
+}
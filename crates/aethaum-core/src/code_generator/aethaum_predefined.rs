@@ -5,10 +5,10 @@ pub fn trait_describe() -> TokenStream {
     quote! {
         pub trait Describe {
             fn describe(&self) -> &'static str {
-                 ""
+                ""
             }
-            fn describe_field(&self, field_name: &str) -> &'static str {
-                 ""
+            fn describe_field(&self, _field_name: &str) -> &'static str {
+                ""
             }
         }
     }
@@ -16,10 +16,37 @@ pub fn trait_describe() -> TokenStream {
 //Reserved Events
 pub fn event_aethaum_spawn_entity() -> TokenStream {
     quote! {
-        #[derive(Event)]
+        ///按原型生成实体，原型名可以带模块限定，如 "combat::Goblin"
+        #[derive(Event, Debug, Clone)]
         pub struct AethaumSpawnEntity {
             pub prototype_name: String,
-            pub entity_response: Option<oneshot::Sender<Entity>>,
+            ///组件名（蛇形）-> { 字段名: 值 }，覆盖原型中的初始值
+            pub overrides: Option<aethaum_runtime::spawn::SpawnOverrides>,
+            ///生成的实体作为 parent 的子实体
+            pub parent: Option<Entity>,
+            pub entity_response: Option<std::sync::mpsc::Sender<Entity>>,
+        }
+        impl AethaumSpawnEntity {
+            pub fn new(prototype_name: impl Into<String>) -> Self {
+                Self {
+                    prototype_name: prototype_name.into(),
+                    overrides: None,
+                    parent: None,
+                    entity_response: None,
+                }
+            }
+            pub fn with_overrides(mut self, overrides: aethaum_runtime::spawn::SpawnOverrides) -> Self {
+                self.overrides = Some(overrides);
+                self
+            }
+            pub fn with_parent(mut self, parent: Entity) -> Self {
+                self.parent = Some(parent);
+                self
+            }
+            pub fn with_response(mut self, response: std::sync::mpsc::Sender<Entity>) -> Self {
+                self.entity_response = Some(response);
+                self
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,277 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+use crate::code_generator::TranspileError;
+use crate::code_generator::utils::to_snake_case;
+use crate::toml_parser::parsed::{AethaumType, Component, Enum, Event, Field, PrimitiveType, Record};
+
+pub trait LuaBinding {
+    ///生成 mlua::UserData 实现，Lua 侧通过字段名读写对应的 Rust 字段
+    fn transpile_lua_binding(&self) -> Result<TokenStream, TranspileError>;
+}
+///组件在Lua实体视图中的字段名，如 Health -> entity.health
+pub fn component_lua_name(component_name: &str) -> String {
+    to_snake_case(component_name)
+}
+///字段在Lua侧的类型，实体以 LuaEntity 句柄的形式暴露
+fn lua_value_type(type_spec: &AethaumType) -> syn::Type {
+    match type_spec {
+        AethaumType::Primitive(PrimitiveType::EntityId) => syn::parse_quote! { LuaEntity },
+        AethaumType::List(element_type) => {
+            let element_type = lua_value_type(element_type);
+            syn::parse_quote! { Vec<#element_type> }
+        }
+        _ => type_spec.to_rust_type(),
+    }
+}
+fn needs_conversion(type_spec: &AethaumType) -> bool {
+    match type_spec {
+        AethaumType::Primitive(PrimitiveType::EntityId) => true,
+        AethaumType::List(element_type) => needs_conversion(element_type),
+        _ => false,
+    }
+}
+///将 Rust 字段值（place 表达式）转换为Lua侧的值
+fn to_lua_value(type_spec: &AethaumType, place: TokenStream) -> TokenStream {
+    match type_spec {
+        AethaumType::Primitive(PrimitiveType::EntityId) => quote! { LuaEntity(#place) },
+        AethaumType::List(element_type) if needs_conversion(element_type) => {
+            let element = to_lua_value(element_type, quote! { *element });
+            quote! { #place.iter().map(|element| #element).collect::<Vec<_>>() }
+        }
+        _ => quote! { #place.clone() },
+    }
+}
+///将Lua侧的值转换回 Rust 字段值
+fn from_lua_value(type_spec: &AethaumType, value: TokenStream) -> TokenStream {
+    match type_spec {
+        AethaumType::Primitive(PrimitiveType::EntityId) => quote! { #value.0 },
+        AethaumType::List(element_type) if needs_conversion(element_type) => {
+            let element = from_lua_value(element_type, quote! { element });
+            quote! { #value.into_iter().map(|element| #element).collect::<Vec<_>>() }
+        }
+        _ => value,
+    }
+}
+fn transpile_userdata<T, FieldIter>(name: &str, fields: FieldIter) -> TokenStream
+where
+    T: Field,
+    FieldIter: IntoIterator<Item = T>,
+{
+    let name = Ident::new(name, Span::call_site());
+    let accessors = fields.into_iter().map(|field| {
+        let field_name = field.name_as_rust_ident();
+        let lua_type = lua_value_type(field.type_spec());
+        let lua_name = field_name.to_string();
+        let get_value = to_lua_value(field.type_spec(), quote! { this.#field_name });
+        let set_value = from_lua_value(field.type_spec(), quote! { value });
+        quote! {
+            fields.add_field_method_get(#lua_name, |_, this| Ok(#get_value));
+            fields.add_field_method_set(#lua_name, |_, this, value: #lua_type| {
+                this.#field_name = #set_value;
+                Ok(())
+            });
+        }
+    }).collect::<Vec<_>>();
+
+    // 没有字段时不注册访问器，使用默认实现
+    if accessors.is_empty() {
+        return quote! {
+            impl mlua::UserData for #name {}
+        };
+    }
+    quote! {
+        impl mlua::UserData for #name {
+            fn add_fields<F: mlua::UserDataFields<Self>>(fields: &mut F) {
+                #(#accessors)*
+            }
+        }
+    }
+}
+impl LuaBinding for Component {
+    fn transpile_lua_binding(&self) -> Result<TokenStream, TranspileError> {
+        Ok(transpile_userdata(self.name.as_str(), self.fields.iter().flatten()))
+    }
+}
+impl LuaBinding for Event {
+    fn transpile_lua_binding(&self) -> Result<TokenStream, TranspileError> {
+        Ok(transpile_userdata(self.name.as_str(), self.fields.iter().flatten()))
+    }
+}
+///记录在Lua侧是按值传递的 UserData，也可以由同名字段的表构造
+impl LuaBinding for Record {
+    fn transpile_lua_binding(&self) -> Result<TokenStream, TranspileError> {
+        let userdata = transpile_userdata(self.name.as_str(), self.fields.iter().flatten());
+        let name = Ident::new(self.name.as_str(), Span::call_site());
+        let type_name = self.name.as_str();
+        let table_fields = self.fields.iter().flatten().map(|field| {
+            let field_name = field.name_as_rust_ident();
+            let lua_name = field_name.to_string();
+            let lua_type = lua_value_type(&field.type_spec);
+            let set_value = from_lua_value(&field.type_spec, quote! { value });
+            quote! {
+                if let Some(value) = table.get::<Option<#lua_type>>(#lua_name)? {
+                    record.#field_name = #set_value;
+                }
+            }
+        }).collect::<Vec<_>>();
+        Ok(quote! {
+            #userdata
+
+            impl mlua::FromLua for #name {
+                fn from_lua(value: mlua::Value, _: &mlua::Lua) -> mlua::Result<Self> {
+                    match value {
+                        mlua::Value::UserData(userdata) => Ok(userdata.borrow::<Self>()?.clone()),
+                        mlua::Value::Table(table) => {
+                            let mut record = Self::default();
+                            #(#table_fields)*
+                            Ok(record)
+                        }
+                        other => Err(mlua::Error::FromLuaConversionError {
+                            from: other.type_name(),
+                            to: #type_name.to_string(),
+                            message: Some("expected a record or a table".to_string()),
+                        }),
+                    }
+                }
+            }
+        })
+    }
+}
+///枚举在Lua侧以变体名字符串表示
+impl LuaBinding for Enum {
+    fn transpile_lua_binding(&self) -> Result<TokenStream, TranspileError> {
+        let name = Ident::new(self.name.as_str(), Span::call_site());
+        let type_name = self.name.as_str();
+        let variants = self.variants.iter()
+            .map(|variant| Ident::new(variant, Span::call_site()))
+            .collect::<Vec<_>>();
+        let variant_names = self.variants.iter().map(|variant| variant.as_str()).collect::<Vec<_>>();
+        Ok(quote! {
+            impl mlua::IntoLua for #name {
+                fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+                    mlua::IntoLua::into_lua(self.as_str(), lua)
+                }
+            }
+            impl mlua::FromLua for #name {
+                fn from_lua(value: mlua::Value, lua: &mlua::Lua) -> mlua::Result<Self> {
+                    let variant = <String as mlua::FromLua>::from_lua(value, lua)?;
+                    match variant.as_str() {
+                        #(#variant_names => Ok(Self::#variants),)*
+                        _ => Err(mlua::Error::FromLuaConversionError {
+                            from: "string",
+                            to: #type_name.to_string(),
+                            message: Some(format!("unknown variant \"{}\"", variant)),
+                        }),
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smart_string::SmartString;
+    use crate::toml_parser::source::SourceLocation;
+    use crate::code_generator::utils::format_rust_code;
+    use crate::toml_parser::parsed::{ComponentField, EventField};
+    use super::*;
+    #[test]
+    fn test_component_lua_binding() {
+        let component = Component {
+            name: SmartString::from("Health"),
+            description: None,
+            fields: Some(vec![
+                ComponentField {
+                    name: SmartString::from("value"),
+                    type_spec: AethaumType::Primitive(PrimitiveType::Float),
+                    default_value: Some(toml::Value::Float(100.0)),
+                    description: None,
+                    location: SourceLocation::default(),
+                },
+                ComponentField {
+                    name: SmartString::from("label"),
+                    type_spec: AethaumType::Primitive(PrimitiveType::Str),
+                    default_value: None,
+                    description: None,
+                    location: SourceLocation::default(),
+                },
+            ]),
+            location: SourceLocation::default(),
+        };
+        let transpiled = format_rust_code(component.transpile_lua_binding().unwrap()).unwrap();
+        println!("{}", transpiled);
+        assert!(transpiled.contains("impl mlua::UserData for Health"));
+        assert!(transpiled.contains("|_, this, value: f32|"));
+        assert!(transpiled.contains("|_, this, value: String|"));
+    }
+    #[test]
+    fn test_event_lua_binding() {
+        let event = Event {
+            name: SmartString::from("EntityHealed"),
+            description: None,
+            tags: Vec::new(),
+            fields: Some(vec![
+                EventField {
+                    name: SmartString::from("amount"),
+                    type_spec: AethaumType::Primitive(PrimitiveType::Float),
+                    description: None,
+                    location: SourceLocation::default(),
+                },
+            ]),
+            location: SourceLocation::default(),
+        };
+        let transpiled = format_rust_code(event.transpile_lua_binding().unwrap()).unwrap();
+        let parsed_result = syn::parse_str::<syn::File>(&transpiled);
+        assert!(parsed_result.is_ok(), "Generated code has syntax errors: {:?}", parsed_result.err());
+    }
+    #[test]
+    fn test_entity_id_lua_binding() {
+        let component = Component {
+            name: SmartString::from("Target"),
+            description: None,
+            fields: Some(vec![
+                ComponentField {
+                    name: SmartString::from("entity"),
+                    type_spec: AethaumType::from_type_str("EntityId"),
+                    default_value: None,
+                    description: None,
+                    location: SourceLocation::default(),
+                },
+                ComponentField {
+                    name: SmartString::from("allies"),
+                    type_spec: AethaumType::from_type_str("list<EntityId>"),
+                    default_value: None,
+                    description: None,
+                    location: SourceLocation::default(),
+                },
+            ]),
+            location: SourceLocation::default(),
+        };
+        let transpiled = format_rust_code(component.transpile_lua_binding().unwrap()).unwrap();
+        println!("{}", transpiled);
+        assert!(transpiled.contains("Ok(LuaEntity(this.entity))"));
+        assert!(transpiled.contains("|_, this, value: LuaEntity|"));
+        assert!(transpiled.contains("this.entity = value.0;"));
+        assert!(transpiled.contains("|_, this, value: Vec<LuaEntity>|"));
+    }
+    #[test]
+    fn test_enum_lua_binding() {
+        let faction = Enum {
+            name: SmartString::from("Faction"),
+            description: None,
+            variants: vec![SmartString::from("Ally"), SmartString::from("Enemy")],
+            location: SourceLocation::default(),
+        };
+        let transpiled = format_rust_code(faction.transpile_lua_binding().unwrap()).unwrap();
+        println!("{}", transpiled);
+        assert!(transpiled.contains("impl mlua::FromLua for Faction"));
+        assert!(transpiled.contains("\"Enemy\" => Ok(Self::Enemy),"));
+    }
+    #[test]
+    fn test_component_lua_name() {
+        assert_eq!(component_lua_name("Health"), "health");
+        assert_eq!(component_lua_name("PlayerControlled"), "player_controlled");
+    }
+}
@@ -0,0 +1,598 @@
+use std::path::{Component, Path};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+use toml::{Table, Value};
+use crate::code_generator::TranspileError;
+use crate::code_generator::aethaum_predefined::{event_aethaum_spawn_entity, trait_describe};
+use crate::code_generator::lua_binding::component_lua_name;
+use crate::code_generator::transpile::module_plugin_name;
+use crate::code_generator::utils::to_snake_case;
+use crate::ecs::module::EcsModule;
+use crate::toml_parser::parsed::{World, WorldEnvironment, WorldSandbox};
+
+pub const DEFAULT_EDITION: &str = "2024";
+///world.toml 中没有 [build] 时使用的输出目录
+pub const DEFAULT_OUTPUT_DIR: &str = "generated";
+
+///由世界名生成合法的 crate 名，如 MyAIWorld -> my_ai_world
+pub fn crate_name(world_name: &str) -> String {
+    let name = to_snake_case(world_name)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("world_{}", name),
+    }
+}
+///生成代码所需的依赖
+fn default_dependencies() -> Table {
+    let mut dependencies = Table::new();
+    let mut bevy_ecs = Table::new();
+    bevy_ecs.insert("version".into(), Value::from("0.16.1"));
+    bevy_ecs.insert("features".into(), Value::from(vec!["serialize"]));
+    dependencies.insert("bevy_ecs".into(), Value::Table(bevy_ecs));
+    dependencies.insert("bevy_app".into(), Value::from("0.16.1"));
+    let mut mlua = Table::new();
+    mlua.insert("version".into(), Value::from("0.11"));
+    mlua.insert("features".into(), Value::from(vec!["lua54", "vendored"]));
+    dependencies.insert("mlua".into(), Value::Table(mlua));
+    let mut serde = Table::new();
+    serde.insert("version".into(), Value::from("1.0"));
+    serde.insert("features".into(), Value::from(vec!["derive"]));
+    dependencies.insert("serde".into(), Value::Table(serde));
+    dependencies.insert("serde_json".into(), Value::from("1.0"));
+    dependencies
+}
+///aethaum-runtime 尚未发布，必须由 [build].runtime 指定源码目录，或在 [cargo.dependencies] 中自行声明
+fn runtime_dependency(world: &World, project_root: &Path) -> Result<Value, TranspileError> {
+    let runtime = world.build.as_ref()
+        .and_then(|build| build.runtime.as_ref())
+        .ok_or(TranspileError::RuntimeUnresolved)?;
+    let runtime = project_root.join(runtime);
+    let path = runtime.canonicalize()
+        .ok()
+        .filter(|path| path.join("Cargo.toml").is_file())
+        .ok_or(TranspileError::RuntimeNotFound(runtime))?;
+    let mut dependency = Table::new();
+    dependency.insert("path".into(), Value::from(path.to_string_lossy().into_owned()));
+    Ok(Value::Table(dependency))
+}
+///生成 Cargo.toml，[cargo] 中的配置覆盖默认值
+pub fn cargo_manifest(world: &World, project_root: &Path) -> Result<String, TranspileError> {
+    let cargo = world.cargo.clone().unwrap_or_default();
+    let mut package = Table::new();
+    package.insert("name".into(), Value::from(crate_name(&world.normal.name)));
+    package.insert("version".into(), Value::from(world.normal.version.as_str()));
+    package.insert("edition".into(), Value::from(cargo.edition.as_deref().unwrap_or(DEFAULT_EDITION)));
+    if !world.normal.author.is_empty() {
+        package.insert("authors".into(), Value::from(vec![world.normal.author.as_str()]));
+    }
+    let mut dependencies = default_dependencies();
+    if !cargo.dependencies.contains_key("aethaum-runtime") {
+        dependencies.insert("aethaum-runtime".into(), runtime_dependency(world, project_root)?);
+    }
+    dependencies.extend(cargo.dependencies.into_iter().map(|(name, value)| (name.to_string(), value)));
+
+    let mut sections = vec![("package", package), ("dependencies", dependencies)];
+    if !cargo.features.is_empty() {
+        let features = cargo.features.into_iter()
+            .map(|(name, enables)| (name.to_string(), Value::from(enables.iter().map(|f| f.as_str()).collect::<Vec<_>>())))
+            .collect::<Table>();
+        sections.push(("features", features));
+    }
+    // 生成目录通常位于项目内部，独立的 workspace 避免被上级 workspace 收录
+    sections.push(("workspace", Table::new()));
+    // Table 按键排序，逐节序列化以保持 Cargo.toml 惯用的顺序
+    let mut manifest = String::from("# Generated by aethaum, changes will be overwritten on the next build\n");
+    for (name, section) in sections {
+        let mut table = Table::new();
+        table.insert(name.into(), Value::Table(section));
+        manifest.push('\n');
+        manifest.push_str(&toml::to_string(&table)?);
+    }
+    Ok(manifest)
+}
+///生成按事件名发送事件的函数，事件名可以带模块限定，不带限定的名字只在全局唯一时可用
+fn transpile_emit_event(modules: &[&EcsModule]) -> TokenStream {
+    let events = modules.iter()
+        .flat_map(|module| module.events.iter().flatten().map(move |event| (module.name.as_str(), event.name.as_str())))
+        .collect::<Vec<_>>();
+    let arms = events.iter().map(|&(module_name, event_name)| {
+        let qualified_name = format!("{}::{}", module_name, event_name);
+        let is_unique = events.iter().filter(|(_, other)| *other == event_name).count() == 1;
+        let pattern = if is_unique {
+            quote! { #qualified_name | #event_name }
+        } else {
+            quote! { #qualified_name }
+        };
+        let module_ident = Ident::new(module_name, Span::call_site());
+        let event_ident = Ident::new(event_name, Span::call_site());
+        quote! {
+            #pattern => {
+                commands.send_event(aethaum_runtime::api::deserialize_event::<#module_ident::#event_ident>(event_name, fields)?);
+                Ok(())
+            }
+        }
+    }).collect::<Vec<_>>();
+    let (commands, fields) = if arms.is_empty() {
+        (quote! { _commands }, quote! { _fields })
+    } else {
+        (quote! { commands }, quote! { fields })
+    };
+    quote! {
+        ///按事件名发送事件，供 Lua 中的 aethaum.emit 使用
+        pub fn emit_event(#commands: &mut Commands, event_name: &str, #fields: serde_json::Value) -> Result<(), aethaum_runtime::api::EmitError> {
+            match event_name {
+                #(#arms)*
+                _ => Err(aethaum_runtime::api::EmitError::UnknownEvent(event_name.to_string())),
+            }
+        }
+    }
+}
+///对每个组件生成从 entity_ref 读取该组件的代码，read 由组件的限定名生成读取到 component 后执行的语句
+fn transpile_component_reads(modules: &[&EcsModule], read: impl Fn(&str) -> TokenStream) -> Vec<TokenStream> {
+    modules.iter().flat_map(|module| {
+        let module_ident = Ident::new(module.name.as_str(), Span::call_site());
+        module.components.iter().flatten().map(move |component| (module_ident.clone(), module.name.as_str(), component))
+    }).map(|(module_ident, module_name, component)| {
+        let read = read(&format!("{}::{}", module_name, component.name));
+        let component_ident = Ident::new(component.name.as_str(), Span::call_site());
+        quote! {
+            if let Some(component) = entity_ref.get::<#module_ident::#component_ident>() {
+                #read
+            }
+        }
+    }).collect()
+}
+///生成按组件的限定名读取实体上所有组件的函数，供智能体协议的 observe 使用
+fn transpile_observe_entity(modules: &[&EcsModule]) -> TokenStream {
+    let reads = transpile_component_reads(modules, |qualified_name| quote! {
+        components.insert(#qualified_name.to_string(), aethaum_runtime::rpc::component_to_json(#qualified_name, component)?);
+    });
+    let (entity_ref, components) = if reads.is_empty() {
+        (quote! { _entity_ref }, quote! { components })
+    } else {
+        (quote! { entity_ref }, quote! { mut components })
+    };
+    quote! {
+        ///读取实体上的所有组件：组件的限定名 -> 组件的值
+        pub fn observe_entity(world: &World, entity: Entity) -> Result<aethaum_runtime::rpc::Observation, aethaum_runtime::rpc::ObserveError> {
+            let #entity_ref = world.get_entity(entity).map_err(|_| aethaum_runtime::rpc::ObserveError::NoSuchEntity(entity))?;
+            let #components = aethaum_runtime::rpc::Observation::new();
+            #(#reads)*
+            Ok(components)
+        }
+    }
+}
+///生成以 Describe 中的描述与组件的值描述实体的函数
+fn transpile_describe_entity(modules: &[&EcsModule]) -> TokenStream {
+    let reads = transpile_component_reads(modules, |qualified_name| quote! {
+        components.push(describe_component(#qualified_name, component));
+    });
+    // 没有组件时不生成未使用的辅助函数
+    let (describe_component, entity_ref, components) = if reads.is_empty() {
+        (None, quote! { _entity_ref }, quote! { components })
+    } else {
+        let describe_component = quote! {
+            fn describe_component<T: Describe + serde::Serialize>(name: &'static str, component: &T) -> aethaum_runtime::describe::ComponentDescription {
+                aethaum_runtime::describe::ComponentDescription::new(name, component.describe(), component, |field| component.describe_field(field))
+            }
+        };
+        (Some(describe_component), quote! { entity_ref }, quote! { mut components })
+    };
+    quote! {
+        #describe_component
+        ///读取实体上所有组件的描述与值，实体不存在时返回 None
+        pub fn describe_components(world: &World, entity: Entity) -> Option<Vec<aethaum_runtime::describe::ComponentDescription>> {
+            let #entity_ref = world.get_entity(entity).ok()?;
+            let #components = Vec::new();
+            #(#reads)*
+            Some(components)
+        }
+        ///以文本描述实体上的所有组件，实体不存在时返回 None
+        pub fn describe_entity(world: &World, entity: Entity) -> Option<String> {
+            describe_components(world, entity).map(|components| aethaum_runtime::describe::render_entity(entity, &components))
+        }
+        ///以文本描述世界中所有符合条件的实体
+        pub fn describe_scene(world: &World, filter: &aethaum_runtime::describe::SceneFilter) -> String {
+            aethaum_runtime::describe::describe_scene(world, describe_components, filter)
+        }
+    }
+}
+///生成所有事件的说明，供智能体协议的 list_events 使用
+fn transpile_event_infos(modules: &[&EcsModule]) -> TokenStream {
+    let optional = |description: Option<&str>| match description {
+        Some(description) => quote! { Some(#description) },
+        None => quote! { None },
+    };
+    let events = modules.iter().flat_map(|module| module.events.iter().flatten().map(move |event| (module, event))).map(|(module, event)| {
+        let qualified_name = format!("{}::{}", module.name, event.name);
+        let description = optional(event.description.as_deref());
+        let fields = event.fields.iter().flatten().map(|field| {
+            let name = field.name.as_str();
+            let type_name = field.type_spec.to_string();
+            let description = optional(field.description.as_deref());
+            quote! { aethaum_runtime::rpc::FieldInfo { name: #name, type_name: #type_name, description: #description } }
+        });
+        quote! {
+            aethaum_runtime::rpc::EventInfo { name: #qualified_name, description: #description, fields: &[#(#fields),*] }
+        }
+    });
+    quote! {
+        ///项目中声明的所有事件
+        pub const EVENTS: &[aethaum_runtime::rpc::EventInfo] = &[#(#events),*];
+    }
+}
+///生成 lib.rs：声明各模块并包含预定义类型
+pub fn transpile_lib(modules: &[&EcsModule]) -> TokenStream {
+    let module_names = modules.iter().map(|module| Ident::new(module.name.as_str(), Span::call_site()));
+    let describe = trait_describe();
+    let spawn_entity = event_aethaum_spawn_entity();
+    let emit_event = transpile_emit_event(modules);
+    let observe_entity = transpile_observe_entity(modules);
+    let describe_entity = transpile_describe_entity(modules);
+    let event_infos = transpile_event_infos(modules);
+    quote! {
+        use bevy_ecs::prelude::*;
+
+        #(pub mod #module_names;)*
+
+        #describe
+        #spawn_entity
+        #emit_event
+        #observe_entity
+        #describe_entity
+        #event_infos
+
+        ///按原型名生成实体，实体在下一帧中生成
+        pub fn spawn_prototype(commands: &mut Commands, prototype_name: &str, overrides: Option<aethaum_runtime::spawn::SpawnOverrides>) {
+            let mut event = AethaumSpawnEntity::new(prototype_name);
+            event.overrides = overrides;
+            commands.send_event(event);
+        }
+
+        ///智能体协议访问世界的接口
+        pub fn agent_bindings() -> aethaum_runtime::rpc::WorldBindings {
+            aethaum_runtime::rpc::WorldBindings {
+                observe: observe_entity,
+                emit: emit_event,
+                spawn: spawn_prototype,
+                describe: describe_components,
+                events: EVENTS,
+            }
+        }
+    }
+}
+///生成运行时插件，[sandbox] 中设置的限制覆盖运行时的默认值
+fn transpile_runtime_plugin(sandbox: Option<&WorldSandbox>) -> TokenStream {
+    let sandbox = sandbox.cloned().unwrap_or_default();
+    if sandbox == WorldSandbox::default() {
+        return quote! { AethaumRuntimePlugin::default() };
+    }
+    let instruction_limit = sandbox.instruction_limit.map(|limit| quote! { .with_instruction_limit(#limit) });
+    let memory_limit = sandbox.memory_limit_mb.map(|limit_mb| {
+        // 换算为字节后超出 usize 范围的限制按最大值处理
+        let limit = usize::try_from(limit_mb).ok().and_then(|limit_mb| limit_mb.checked_mul(1024 * 1024)).unwrap_or(usize::MAX);
+        quote! { .with_memory_limit(#limit) }
+    });
+    quote! {
+        AethaumRuntimePlugin::default().with_sandbox(SandboxConfig::default() #instruction_limit #memory_limit)
+    }
+}
+///生成 [environment] 对应的 ENVIRONMENT，组件与原型的引用已在检查时确认带有模块限定
+fn transpile_environment(environment: &WorldEnvironment) -> TokenStream {
+    let optional = |value: Option<&str>| match value {
+        Some(value) => quote! { Some(#value) },
+        None => quote! { None },
+    };
+    let scene = environment.scene.iter().map(|spawn| {
+        let prototype = spawn.prototype.as_path_str();
+        let count = spawn.count;
+        let overrides = spawn.overrides.as_ref()
+            .map(|overrides| serde_json::to_string(overrides).expect("TOML values are representable in JSON")); //ROBUST: toml::Table always serializes
+        let overrides = optional(overrides.as_deref());
+        quote! { SceneSpawn { prototype: #prototype, count: #count, overrides: #overrides } }
+    });
+    let queries = environment.queries.iter().map(|query| {
+        let name = query.name.as_str();
+        let include = query.component_constraint.get_include().into_iter().flatten().map(|component_ref| {
+            let qualified_name = component_ref.as_path_str();
+            let lua_name = component_lua_name(&component_ref.name);
+            quote! { (#qualified_name, #lua_name) }
+        });
+        let exclude = query.component_constraint.get_exclude().into_iter().flatten().map(|component_ref| component_ref.as_path_str());
+        quote! { EnvironmentQuery { name: #name, include: &[#(#include),*], exclude: &[#(#exclude),*] } }
+    });
+    let reward = optional(environment.reward.as_deref());
+    let done = optional(environment.done.as_deref());
+    let max_steps = match environment.max_steps {
+        Some(max_steps) => quote! { Some(#max_steps) },
+        None => quote! { None },
+    };
+    let frames_per_step = environment.frames_per_step;
+    quote! {
+        static ENVIRONMENT: EnvironmentConfig = EnvironmentConfig {
+            scene: &[#(#scene),*],
+            queries: &[#(#queries),*],
+            reward: #reward,
+            done: #done,
+            max_steps: #max_steps,
+            frames_per_step: #frames_per_step,
+        };
+    }
+}
+///生成项目根目录的表达式，输出目录为相对路径时相对于生成项目的目录，以便移动整个项目
+pub fn transpile_project_root(world: &World, project_root: &Path) -> TokenStream {
+    let output_dir = Path::new(world.build.as_ref().map(|build| build.output_dir.as_str()).unwrap_or(DEFAULT_OUTPUT_DIR));
+    let depth = output_dir.components().try_fold(0usize, |depth, component| match component {
+        Component::CurDir => Some(depth),
+        Component::Normal(_) => Some(depth + 1),
+        _ => None,
+    });
+    match depth {
+        Some(depth) => {
+            let relative = vec![".."; depth].join("/");
+            quote! { std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(#relative) }
+        }
+        None => {
+            let root = project_root.canonicalize().unwrap_or_else(|_| project_root.to_path_buf());
+            let root = root.to_string_lossy().into_owned();
+            quote! { std::path::PathBuf::from(#root) }
+        }
+    }
+}
+///生成 main.rs：加载所有模块插件，以固定频率运行世界，调试构建时热重载脚本；有 [environment] 时可以作为强化学习环境运行
+pub fn transpile_main(crate_name: &str, modules: &[&EcsModule], world: &World, project_root: &Path) -> TokenStream {
+    let crate_name = Ident::new(crate_name, Span::call_site());
+    let plugins = modules.iter().map(|module| {
+        let module_name = Ident::new(module.name.as_str(), Span::call_site());
+        let plugin_name = module_plugin_name(&module.name);
+        quote! { #crate_name::#module_name::#plugin_name }
+    });
+    let runtime_plugin = transpile_runtime_plugin(world.sandbox.as_ref());
+    let project_root = transpile_project_root(world, project_root);
+    let environment = world.environment.as_ref().map(transpile_environment);
+    let run_environment = environment.is_some().then(|| quote! {
+        if std::env::args().any(|arg| arg == "--env") {
+            // 每个回合重新构建世界，不热重载脚本
+            let build = || {
+                let mut app = build_app();
+                app.insert_resource(WorldClock::fixed(TICK));
+                app
+            };
+            if let Err(error) = EnvironmentServer::new(build, #crate_name::agent_bindings(), &ENVIRONMENT).serve_stdio() {
+                eprintln!("Environment failed: {}", error);
+                std::process::exit(1);
+            }
+            return;
+        }
+    });
+    quote! {
+        use std::time::Duration;
+        use bevy_app::prelude::*;
+        use bevy_app::ScheduleRunnerPlugin;
+        use aethaum_runtime::prelude::*;
+
+        const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+        #environment
+
+        ///项目根目录，文件脚本与热重载都相对于此目录
+        fn project_root() -> std::path::PathBuf {
+            #project_root
+        }
+
+        fn build_app() -> App {
+            let mut app = App::new();
+            app.add_plugins(#runtime_plugin.with_project_root(project_root()))
+                .add_plugins((#(#plugins,)*));
+            app
+        }
+
+        fn main() {
+            #run_environment
+            let mut app = build_app();
+            // 只在调试构建中热重载脚本，发布构建不监视项目目录
+            #[cfg(debug_assertions)]
+            app.add_plugins(HotReloadPlugin::new(project_root()));
+            if std::env::args().any(|arg| arg == "--rpc") {
+                // 由智能体通过 step 推进世界，每帧推进固定的时长
+                app.insert_resource(WorldClock::fixed(TICK));
+                let result = match AgentServer::new(app, #crate_name::agent_bindings()) {
+                    Ok(mut server) => server.serve_stdio().map_err(|error| error.to_string()),
+                    Err(error) => Err(error.to_string()),
+                };
+                if let Err(error) = result {
+                    eprintln!("Agent protocol failed: {}", error);
+                    std::process::exit(1);
+                }
+            } else {
+                app.add_plugins(ScheduleRunnerPlugin::run_loop(TICK)).run();
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toml_parser::parsed::{AethaumType, Component, Event, EventField, WorldBuild, WorldCargo};
+    use crate::toml_parser::source::SourceLocation;
+    use crate::toml_parser::raw::RawWorldFile;
+    #[test]
+    fn test_crate_name() {
+        assert_eq!(crate_name("MyAIWorld"), "my_ai_world");
+        assert_eq!(crate_name("dungeon-crawler"), "dungeon_crawler");
+        assert_eq!(crate_name("2048"), "world_2048");
+    }
+    #[test]
+    fn test_transpile_emit_event() {
+        let event = |name: &str| Event {
+            name: name.into(),
+            description: None,
+            tags: Vec::new(),
+            fields: None,
+            location: SourceLocation::default(),
+        };
+        let mut combat = EcsModule::new_empty("combat".into());
+        combat.events = Some(vec![event("EntityDied"), event("Tick")]);
+        let mut explore = EcsModule::new_empty("explore".into());
+        explore.events = Some(vec![event("Tick")]);
+        let transpiled = transpile_emit_event(&[&combat, &explore]).to_string();
+        assert!(transpiled.contains(r#""combat::EntityDied" | "EntityDied" =>"#));
+        // 重名事件只能通过限定名发送
+        assert!(transpiled.contains(r#""combat::Tick" =>"#));
+        assert!(transpiled.contains(r#""explore::Tick" =>"#));
+        assert!(!transpiled.contains(r#"| "Tick""#));
+        let empty = transpile_emit_event(&[&EcsModule::new_empty("empty".into())]).to_string();
+        assert!(empty.contains("_commands : & mut Commands"));
+    }
+    #[test]
+    fn test_transpile_agent_bindings() {
+        let mut combat = EcsModule::new_empty("combat".into());
+        combat.components = Some(vec![Component {
+            name: "Health".into(),
+            description: None,
+            fields: None,
+            location: SourceLocation::default(),
+        }]);
+        combat.events = Some(vec![Event {
+            name: "EntityDied".into(),
+            description: Some("实体死亡".into()),
+            tags: Vec::new(),
+            fields: Some(vec![EventField {
+                name: "entity".into(),
+                type_spec: AethaumType::from_type_str("EntityId"),
+                description: None,
+                location: SourceLocation::default(),
+            }]),
+            location: SourceLocation::default(),
+        }]);
+        let observe = transpile_observe_entity(&[&combat]).to_string();
+        assert!(observe.contains("entity_ref . get :: < combat :: Health > ()"));
+        assert!(observe.contains(r#"component_to_json ("combat::Health" , component)"#));
+        let events = transpile_event_infos(&[&combat]).to_string();
+        assert!(events.contains(r#"name : "combat::EntityDied" , description : Some ("实体死亡")"#));
+        assert!(events.contains(r#"FieldInfo { name : "entity" , type_name : "EntityId" , description : None }"#));
+        let describe = transpile_describe_entity(&[&combat]).to_string();
+        assert!(describe.contains(r#"components . push (describe_component ("combat::Health" , component))"#));
+        let empty = EcsModule::new_empty("empty".into());
+        assert!(transpile_observe_entity(&[&empty]).to_string().contains("let _entity_ref"));
+        let empty_describe = transpile_describe_entity(&[&empty]).to_string();
+        assert!(!empty_describe.contains("fn describe_component <"));
+        assert!(empty_describe.contains("let _entity_ref"));
+    }
+    #[test]
+    fn test_transpile_runtime_plugin() {
+        assert_eq!(transpile_runtime_plugin(None).to_string(), quote! { AethaumRuntimePlugin::default() }.to_string());
+        let sandbox = WorldSandbox { instruction_limit: None, memory_limit_mb: Some(16) };
+        let transpiled = transpile_runtime_plugin(Some(&sandbox)).to_string();
+        assert!(transpiled.contains("with_memory_limit (16777216usize)"));
+        assert!(!transpiled.contains("with_instruction_limit"));
+        let sandbox = WorldSandbox { instruction_limit: None, memory_limit_mb: Some(u64::MAX) };
+        assert!(transpile_runtime_plugin(Some(&sandbox)).to_string().contains(&format!("with_memory_limit ({}usize)", usize::MAX)));
+    }
+    #[test]
+    fn test_transpile_environment() {
+        let world = World::try_from(toml::from_str::<RawWorldFile>(r##"
+        [world]
+        name = "W"
+        version = "0.1.0"
+        author = ""
+
+        [modules]
+
+        [environment]
+        max_steps = 100
+        done = "#enemies == 0"
+
+        [[environment.scene]]
+        prototype = "explore::Enemy"
+        count = 3
+        overrides = { health = { value = 50.0 } }
+
+        [[environment.queries]]
+        name = "enemies"
+        components_include = ["combat::Health"]
+        components_exclude = ["explore::Ally"]
+        "##).unwrap()).unwrap();
+        let environment = transpile_environment(world.environment.as_ref().unwrap()).to_string();
+        assert!(environment.contains(r#"SceneSpawn { prototype : "explore::Enemy" , count : 3u32 , overrides : Some ("{\"health\":{\"value\":50.0}}") }"#), "{}", environment);
+        assert!(environment.contains(r#"include : & [("combat::Health" , "health")] , exclude : & ["explore::Ally"]"#), "{}", environment);
+        assert!(environment.contains("reward : None , done : Some (\"#enemies == 0\") , max_steps : Some (100u64) , frames_per_step : 1u32"), "{}", environment);
+        let main = transpile_main("w", &[], &world, Path::new("/project")).to_string();
+        assert!(main.contains("static ENVIRONMENT"));
+        assert!(main.contains(r#"arg == "--env""#));
+        let without_environment = World { environment: None, ..world };
+        assert!(!transpile_main("w", &[], &without_environment, Path::new("/project")).to_string().contains("--env"));
+    }
+    #[test]
+    fn test_transpile_project_root() {
+        let mut world = World::try_from(toml::from_str::<RawWorldFile>(r#"
+        [world]
+        name = "W"
+        version = "0.1.0"
+        author = ""
+
+        [modules]
+        "#).unwrap()).unwrap();
+        let root = transpile_project_root(&world, Path::new("/project")).to_string();
+        assert!(root.contains(r#"join ("..")"#), "{}", root);
+        world.build = Some(WorldBuild { output_dir: "./out/world".into(), runtime: None });
+        assert!(transpile_project_root(&world, Path::new("/project")).to_string().contains(r#"join ("../..")"#));
+        world.build = Some(WorldBuild { output_dir: "/tmp/out".into(), runtime: None });
+        assert!(transpile_project_root(&world, Path::new("/project")).to_string().contains(r#"PathBuf :: from ("/project")"#));
+    }
+    #[test]
+    fn test_cargo_manifest() {
+        let raw = toml::from_str::<RawWorldFile>(r#"
+        [world]
+        name = "MyAIWorld"
+        version = "0.2.0"
+        author = "Someone"
+
+        [modules]
+
+        [build]
+        output_dir = "generated"
+        runtime = "../aethaum-runtime"
+
+        [cargo]
+        edition = "2021"
+
+        [cargo.dependencies]
+        rand = "0.8"
+        mlua = { version = "0.11", features = ["lua54", "vendored", "serialize"] }
+
+        [cargo.features]
+        debug_view = []
+        "#).unwrap();
+        let world = World::try_from(raw).unwrap();
+        assert!(matches!(world.cargo, Some(WorldCargo { .. })));
+        let project_root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let manifest = cargo_manifest(&world, project_root).unwrap().parse::<Table>().unwrap();
+        assert_eq!(manifest["package"]["name"].as_str(), Some("my_ai_world"));
+        assert_eq!(manifest["package"]["version"].as_str(), Some("0.2.0"));
+        assert_eq!(manifest["package"]["edition"].as_str(), Some("2021"));
+        assert!(manifest["workspace"].as_table().unwrap().is_empty());
+        let dependencies = manifest["dependencies"].as_table().unwrap();
+        assert_eq!(dependencies["rand"].as_str(), Some("0.8"));
+        assert_eq!(dependencies["mlua"]["features"].as_array().unwrap().len(), 3);
+        assert!(dependencies.contains_key("bevy_ecs"));
+        // 运行时库以绝对路径引用
+        let runtime = Path::new(dependencies["aethaum-runtime"]["path"].as_str().unwrap());
+        assert!(runtime.is_absolute() && runtime.ends_with("aethaum-runtime"), "{}", runtime.display());
+        assert!(manifest["features"]["debug_view"].as_array().unwrap().is_empty());
+
+        let mut world = world;
+        world.build.as_mut().unwrap().runtime = Some("missing-runtime".into());
+        assert!(matches!(cargo_manifest(&world, project_root), Err(TranspileError::RuntimeNotFound(path)) if path.ends_with("missing-runtime")));
+        world.build = None;
+        assert!(matches!(cargo_manifest(&world, project_root), Err(TranspileError::RuntimeUnresolved)));
+        // 在 [cargo.dependencies] 中自行声明时不需要定位运行时库
+        world.cargo.as_mut().unwrap().dependencies.insert("aethaum-runtime".into(), toml::Value::from("0.1"));
+        let manifest = cargo_manifest(&world, project_root).unwrap().parse::<Table>().unwrap();
+        assert_eq!(manifest["dependencies"]["aethaum-runtime"].as_str(), Some("0.1"));
+    }
+}
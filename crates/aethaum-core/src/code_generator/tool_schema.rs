@@ -0,0 +1,217 @@
+use miette::Diagnostic;
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+use crate::ecs::module::{AethaumProject, CustomTypeDef, CustomTypes, EcsModule};
+use crate::toml_parser::parsed::{AethaumType, ComponentField, Event, PrimitiveType};
+
+///约定的标签，标记可以由智能体发送的事件
+pub const AGENT_ACTION_TAG: &str = "agent-action";
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum ToolSchemaError {
+    #[error("Cannot resolve type '{0}' when generating the tool schema")]
+    UnresolvedType(String),
+    #[error("Type '{0}' contains itself and cannot be expressed as an inline JSON schema")]
+    RecursiveType(String),
+}
+
+///工具定义的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToolFormat {
+    ///{ "type": "function", "function": { "name", "description", "parameters" } }
+    #[default]
+    OpenAi,
+    ///{ "name", "description", "input_schema" }
+    Anthropic,
+}
+
+///将事件导出为 LLM 的工具定义，tag 不为空时只导出带有该标签的事件
+///事件名在项目中唯一时作为工具名，否则使用 "模块__事件"，将 "__" 替换为 "::" 即为 act 使用的事件名
+pub fn event_tools(project: &AethaumProject, tag: Option<&str>, format: ToolFormat) -> Result<Vec<Value>, ToolSchemaError> {
+    let mut modules = project.module_tree.get_modules();
+    modules.sort_by(|a, b| a.name.cmp(&b.name));
+    let types = CustomTypes::default().with_modules(modules.iter().copied());
+    let events = modules.iter()
+        .flat_map(|module| module.events.iter().flatten().map(move |event| (*module, event)))
+        .collect::<Vec<_>>();
+    events.iter()
+        .filter(|(_, event)| tag.is_none_or(|tag| event.tags.iter().any(|event_tag| event_tag.as_str() == tag)))
+        .map(|&(module, event)| {
+            let is_unique = events.iter().filter(|(_, other)| other.name == event.name).count() == 1;
+            let name = if is_unique {
+                event.name.to_string()
+            } else {
+                format!("{}__{}", module.name, event.name)
+            };
+            let description = match &event.description {
+                Some(description) => description.to_string(),
+                None => format!("Emit the {}::{} event", module.name, event.name),
+            };
+            let parameters = event_parameters(event, module, &types)?;
+            Ok(match format {
+                ToolFormat::OpenAi => json!({
+                    "type": "function",
+                    "function": { "name": name, "description": description, "parameters": parameters },
+                }),
+                ToolFormat::Anthropic => json!({ "name": name, "description": description, "input_schema": parameters }),
+            })
+        })
+        .collect()
+}
+///事件字段对应的参数 schema，所有字段都是必需的
+pub fn event_parameters(event: &Event, module: &EcsModule, types: &CustomTypes) -> Result<Value, ToolSchemaError> {
+    let mut properties = Map::new();
+    for field in event.fields.iter().flatten() {
+        let schema = type_schema(&field.type_spec, module.name.as_str(), types, &mut Vec::new())?;
+        properties.insert(field.name.to_string(), with_description(schema, field.description.as_deref()));
+    }
+    let required = properties.keys().cloned().collect::<Vec<_>>();
+    Ok(json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    }))
+}
+fn with_description(mut schema: Value, description: Option<&str>) -> Value {
+    if let (Value::Object(schema), Some(description)) = (&mut schema, description) {
+        schema.insert("description".to_string(), Value::from(description));
+    }
+    schema
+}
+///类型对应的 JSON schema，visiting 为正在展开的记录，用于发现递归的记录
+fn type_schema(type_spec: &AethaumType, module_name: &str, types: &CustomTypes, visiting: &mut Vec<String>) -> Result<Value, ToolSchemaError> {
+    match type_spec {
+        AethaumType::Primitive(PrimitiveType::Float) => Ok(json!({ "type": "number" })),
+        // int 对应生成代码中的 i32
+        AethaumType::Primitive(PrimitiveType::Int) => Ok(json!({ "type": "integer", "minimum": i32::MIN, "maximum": i32::MAX })),
+        AethaumType::Primitive(PrimitiveType::Bool) => Ok(json!({ "type": "boolean" })),
+        AethaumType::Primitive(PrimitiveType::Str) => Ok(json!({ "type": "string" })),
+        // 实体以其位表示传递
+        AethaumType::Primitive(PrimitiveType::EntityId) => Ok(json!({ "type": "integer", "minimum": 0 })),
+        AethaumType::List(element_type) => Ok(json!({
+            "type": "array",
+            "items": type_schema(element_type, module_name, types, visiting)?,
+        })),
+        AethaumType::Custom(type_ref) => {
+            let (type_module, type_def) = types.resolve(type_ref, module_name)
+                .ok_or_else(|| ToolSchemaError::UnresolvedType(type_ref.to_string()))?;
+            match type_def {
+                CustomTypeDef::Enum(enum_def) => Ok(with_description(
+                    json!({ "type": "string", "enum": enum_def.variants }),
+                    enum_def.description.as_deref(),
+                )),
+                CustomTypeDef::Record(record) => {
+                    let qualified_name = format!("{}::{}", type_module, record.name);
+                    if visiting.contains(&qualified_name) {
+                        return Err(ToolSchemaError::RecursiveType(qualified_name));
+                    }
+                    visiting.push(qualified_name);
+                    let schema = record_schema(record.fields.iter().flatten(), type_module, types, visiting)?;
+                    visiting.pop();
+                    Ok(with_description(schema, record.description.as_deref()))
+                }
+            }
+        }
+    }
+}
+///记录的字段都有默认值，因此都不是必需的
+fn record_schema<'a>(fields: impl Iterator<Item = &'a ComponentField>, module_name: &str, types: &CustomTypes, visiting: &mut Vec<String>) -> Result<Value, ToolSchemaError> {
+    let mut properties = Map::new();
+    for field in fields {
+        let schema = type_schema(&field.type_spec, module_name, types, visiting)?;
+        properties.insert(field.name.to_string(), with_description(schema, field.description.as_deref()));
+    }
+    Ok(json!({ "type": "object", "properties": properties, "additionalProperties": false }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::ecs::checker::AethaumChecker;
+    use crate::ecs::loader::ProjectLoader;
+    use crate::toml_parser::parsed::{Enum, EventField, Record};
+    use crate::toml_parser::source::SourceLocation;
+    fn field(name: &str, type_str: &str, description: Option<&str>) -> ComponentField {
+        ComponentField {
+            name: name.into(),
+            type_spec: AethaumType::from_type_str(type_str),
+            default_value: None,
+            description: description.map(Into::into),
+            location: SourceLocation::default(),
+        }
+    }
+    #[test]
+    fn test_event_tools() {
+        let project = ProjectLoader::new(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../templates/default"))).load().unwrap();
+        let project = AethaumChecker::run_check(project).unwrap();
+        let tools = event_tools(&project, None, ToolFormat::Anthropic).unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0]["name"], "Teleport");
+        assert_eq!(tools[0]["input_schema"]["properties"]["x"]["type"], "number");
+        assert_eq!(tools[0]["input_schema"]["required"], json!(["x", "y"]));
+        let tools = event_tools(&project, None, ToolFormat::OpenAi).unwrap();
+        assert_eq!(tools[0]["function"]["parameters"]["additionalProperties"], false);
+        assert!(event_tools(&project, Some(AGENT_ACTION_TAG), ToolFormat::OpenAi).unwrap().is_empty());
+    }
+    #[test]
+    fn test_event_parameters() {
+        let mut module = EcsModule::new_empty("combat".into());
+        module.enums = Some(vec![Enum {
+            name: "Faction".into(),
+            description: None,
+            variants: vec!["Player".into(), "Monster".into()],
+            location: SourceLocation::default(),
+        }]);
+        module.records = Some(vec![
+            Record {
+                name: "Vec2".into(),
+                description: Some("二维向量".into()),
+                fields: Some(vec![field("x", "float", None), field("y", "float", None)]),
+                location: SourceLocation::default(),
+            },
+            Record {
+                name: "Node".into(),
+                description: None,
+                fields: Some(vec![field("children", "list<Node>", None)]),
+                location: SourceLocation::default(),
+            },
+        ]);
+        let event_field = |name: &str, type_str: &str, description: Option<&str>| {
+            let field = field(name, type_str, description);
+            EventField { name: field.name, type_spec: field.type_spec, description: field.description, location: field.location }
+        };
+        let event = Event {
+            name: "Attack".into(),
+            description: None,
+            tags: Vec::new(),
+            fields: Some(vec![
+                event_field("target", "EntityId", Some("攻击目标")),
+                event_field("count", "int", None),
+                event_field("path", "list<Vec2>", None),
+                event_field("faction", "Faction", None),
+                event_field("critical", "bool", None),
+                event_field("cause", "str", None),
+            ]),
+            location: SourceLocation::default(),
+        };
+        let types = CustomTypes::default().with_module(&module);
+        let parameters = event_parameters(&event, &module, &types).unwrap();
+        let properties = &parameters["properties"];
+        assert_eq!(properties["target"], json!({ "type": "integer", "minimum": 0, "description": "攻击目标" }));
+        assert_eq!(properties["count"]["type"], "integer");
+        assert_eq!((&properties["count"]["minimum"], &properties["count"]["maximum"]), (&json!(i32::MIN), &json!(i32::MAX)));
+        assert_eq!(properties["critical"]["type"], "boolean");
+        assert_eq!(properties["cause"]["type"], "string");
+        assert_eq!(properties["faction"], json!({ "type": "string", "enum": ["Player", "Monster"] }));
+        assert_eq!(properties["path"]["items"]["description"], "二维向量");
+        assert_eq!(properties["path"]["items"]["properties"]["y"]["type"], "number");
+        assert_eq!(parameters["required"].as_array().unwrap().len(), 6);
+
+        let recursive = Event { fields: Some(vec![event_field("tree", "Node", None)]), ..event.clone() };
+        assert!(matches!(event_parameters(&recursive, &module, &types), Err(ToolSchemaError::RecursiveType(name)) if name == "combat::Node"));
+        let unresolved = Event { fields: Some(vec![event_field("item", "inventory::Item", None)]), ..event };
+        assert!(matches!(event_parameters(&unresolved, &module, &types), Err(ToolSchemaError::UnresolvedType(_))));
+    }
+}
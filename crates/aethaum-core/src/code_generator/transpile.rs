@@ -1,11 +1,19 @@
 use quote::quote;
-use crate::code_generator::TranspileError;
-use crate::toml_parser::parsed::{Component, Describable, EntityProto, Event, Field, System, SystemQuery};
-use proc_macro2::{Span, TokenStream};
+use crate::code_generator::{TranspileContext, TranspileError};
+use crate::ecs::module::{CustomTypeDef, CustomTypes, EcsModule};
+use crate::code_generator::lua_binding::{component_lua_name, LuaBinding};
+use crate::code_generator::utils::{to_pascal_case, to_snake_case};
+use crate::toml_parser::parsed::{AethaumType, Component, ComponentField, ComponentRef, Describable, EntityProto, Enum, Event, Field, LuaScript, PrimitiveType, Record, System, SystemQuery, SystemRef, UpdateMode};
+use proc_macro2::{Literal, Span, TokenStream};
 use syn::Ident;
+use smart_string::SmartString;
 
 pub trait Transpile {
     fn transpile(&self) -> Result<TokenStream, TranspileError>;
+    ///需要项目信息（如自定义类型定义）时使用，默认忽略上下文
+    fn transpile_with(&self, _context: &TranspileContext) -> Result<TokenStream, TranspileError> {
+        self.transpile()
+    }
     fn transpile_into(&self, output: &mut TokenStream) -> Result<(), TranspileError> {
         output.extend(self.transpile()?);
         Ok(())
@@ -18,103 +26,181 @@ where
 {
     fields.into_iter().map(|field| {
         let field_name = field.name_as_rust_ident();
-        let field_type = field.type_as_rust_ident();
+        let field_type = field.type_as_rust_type();
+        // Lua 中的空表转换为 JSON 时无法确定是数组还是对象，列表字段同时接受空对象
+        let deserialize_with = matches!(field.type_spec(), AethaumType::List(_)).then(|| quote! {
+            #[serde(deserialize_with = "aethaum_runtime::api::deserialize_list")]
+        });
         quote! {
+            #deserialize_with
             pub #field_name: #field_type,
         }
     })
 }
-fn transpile_descriptions<T: Describable>(to_transpile: &T, name: &str) -> TokenStream {
-    let struct_desc = to_transpile.description()
-        .map(|d| {
-            quote! { #d }
-        })
-        .unwrap_or_else(|| quote! { "" });
-
-    let field_desc_impl = if let Some(fields) = to_transpile.field_description() {
-        let field_matches = fields.map(|(field_name, desc)| {
-            quote! {
-                stringify!(#field_name) => #desc,
+///按字段类型将 TOML 值转换为 Rust 表达式，值与类型的匹配已在类型检查阶段保证
+///module_name 为值所在定义的模块，记录字段中的类型引用相对于记录所在模块解析
+fn transpile_value(type_spec: &AethaumType, value: &toml::Value, module_name: &str, context: &TranspileContext) -> Result<TokenStream, TranspileError> {
+    let value = match (type_spec, value) {
+        (AethaumType::Primitive(PrimitiveType::Float), toml::Value::Float(f)) => {
+            let f = *f as f32;
+            if f.is_nan() {
+                quote! { f32::NAN }
+            } else if f.is_infinite() {
+                if f > 0.0 { quote! { f32::INFINITY } } else { quote! { f32::NEG_INFINITY } }
+            } else {
+                let literal = Literal::f32_suffixed(f);
+                quote! { #literal }
             }
-        }).collect::<Vec<_>>();
-
-        if !field_matches.is_empty() {
-            quote! {
-                    match field_name {
-                        #(#field_matches)*
-                        _ => "",
+        }
+        (AethaumType::Primitive(PrimitiveType::Int), toml::Value::Integer(i)) => {
+            let i = i32::try_from(*i).map_err(|_| TranspileError::IntOutOfRange(*i))?;
+            let literal = Literal::i32_suffixed(i);
+            quote! { #literal }
+        }
+        (AethaumType::Primitive(PrimitiveType::Bool), toml::Value::Boolean(b)) => quote! { #b },
+        (AethaumType::Primitive(PrimitiveType::Str), toml::Value::String(s)) => quote! { String::from(#s) },
+        (AethaumType::List(element_type), toml::Value::Array(elements)) => {
+            let elements = elements.iter()
+                .map(|element| transpile_value(element_type, element, module_name, context))
+                .collect::<Result<Vec<_>, _>>()?;
+            quote! { vec![#(#elements),*] }
+        }
+        (AethaumType::Custom(type_ref), value) => match context.types().resolve(type_ref, module_name) {
+            Some((type_module, CustomTypeDef::Record(record))) => {
+                let toml::Value::Table(table) = value else {
+                    return Ok(quote! { Default::default() });
+                };
+                let path = custom_type_path(type_module, &record.name, context);
+                let fields = table.iter().map(|(field_name, field_value)| {
+                    let field = record.get_field(field_name)
+                        .ok_or_else(|| TranspileError::UnresolvedType(format!("{}.{}", type_ref, field_name)))?;
+                    let field_ident = field.name_as_rust_ident();
+                    let field_value = transpile_value(&field.type_spec, field_value, type_module, context)?;
+                    Ok(quote! { #field_ident: #field_value })
+                }).collect::<Result<Vec<_>, TranspileError>>()?;
+                // 未给出的字段使用记录自身的默认值
+                let rest = if fields.len() < record.fields.iter().flatten().count() {
+                    quote! { ..Default::default() }
+                } else {
+                    quote! {}
+                };
+                quote! { #path { #(#fields,)* #rest } }
+            }
+            Some((type_module, CustomTypeDef::Enum(enum_def))) => {
+                let path = custom_type_path(type_module, &enum_def.name, context);
+                match value {
+                    toml::Value::String(variant) => {
+                        let variant = Ident::new(variant, Span::call_site());
+                        quote! { #path::#variant }
                     }
+                    _ => quote! { Default::default() },
                 }
-        } else {
-            quote! { "" }
+            }
+            // 没有类型信息时，字符串只可能是枚举变体
+            None => match value {
+                toml::Value::String(variant) => {
+                    let path = type_spec.to_rust_type();
+                    let variant = Ident::new(variant, Span::call_site());
+                    quote! { #path::#variant }
+                }
+                _ => return Err(TranspileError::UnresolvedType(type_ref.to_string())),
+            }
+        },
+        _ => quote! { Default::default() },
+    };
+    Ok(value)
+}
+///生成代码中自定义类型的路径，定义在当前模块之外的类型使用 crate::模块::类型
+fn custom_type_path(type_module: &str, type_name: &str, context: &TranspileContext) -> TokenStream {
+    let type_name = Ident::new(type_name, Span::call_site());
+    if type_module == context.module_name() {
+        quote! { #type_name }
+    } else {
+        let type_module = Ident::new(type_module, Span::call_site());
+        quote! { crate::#type_module::#type_name }
+    }
+}
+///字段默认值：有默认值时按类型转换，否则使用类型自身的默认值
+fn transpile_field_default(field: &ComponentField, context: &TranspileContext) -> Result<TokenStream, TranspileError> {
+    match &field.default_value {
+        Some(default_value) => transpile_value(&field.type_spec, default_value, context.module_name(), context),
+        None => Ok(transpile_type_default(&field.type_spec)),
+    }
+}
+///未指定默认值的字段使用的默认表达式，Entity 没有 Default 实现，使用占位实体
+fn transpile_type_default(type_spec: &AethaumType) -> TokenStream {
+    match type_spec {
+        AethaumType::Primitive(PrimitiveType::EntityId) => quote! { Entity::PLACEHOLDER },
+        _ => quote! { Default::default() },
+    }
+}
+fn transpile_descriptions<T: Describable>(to_transpile: &T, name: &str) -> TokenStream {
+    let struct_desc = to_transpile.description().unwrap_or_default();
+    let field_matches = to_transpile.field_description()
+        .map(|fields| fields.map(|(field_name, desc)| quote! { #field_name => #desc, }).collect::<Vec<_>>())
+        .unwrap_or_default();
+    // 没有字段描述时不使用参数，避免生成代码出现未使用变量的警告
+    let field_desc_impl = if field_matches.is_empty() {
+        quote! {
+            fn describe_field(&self, _field_name: &str) -> &'static str {
+                ""
+            }
         }
     } else {
-        quote! { "" }
+        quote! {
+            fn describe_field(&self, field_name: &str) -> &'static str {
+                match field_name {
+                    #(#field_matches)*
+                    _ => "",
+                }
+            }
+        }
     };
-
     let name = Ident::new(name, Span::call_site());
 
     quote! {
         impl Describe for #name {
-            fn description(&self) -> &'static str {
+            fn describe(&self) -> &'static str {
                 #struct_desc
             }
 
-            fn describe_field(&self, field_name: &str) -> &'static str {
-                #field_desc_impl
-            }
+            #field_desc_impl
         }
     }
 }
 impl Transpile for Component {
     fn transpile(&self) -> Result<TokenStream, TranspileError> {
+        self.transpile_with(&TranspileContext::default())
+    }
+    fn transpile_with(&self, context: &TranspileContext) -> Result<TokenStream, TranspileError> {
         let name = Ident::new(self.name.as_str(), Span::call_site());
         let fields = if let Some(fields) = &self.fields {
             transpile_fields(fields).collect()
         } else {
             vec![]
         };
-        // 生成 Default 实现（如果有默认值的话）
-        let default_impl = if let Some(fields) = &self.fields {
-            if fields.iter().any(|f| f.default_value.is_some()) {
-                let default_fields = fields.iter().map(|field| {
-                    let field_name = Ident::new(field.name.as_str(), Span::call_site());
-                    if let Some(default_value) = &field.default_value {
-                        // 将 TOML 值转换为 Rust 字面量
-                        let default_literal = match default_value {
-                            toml::Value::Boolean(b) => quote! { #b },
-                            toml::Value::Integer(i) => quote! { #i },
-                            toml::Value::Float(f) => quote! { #f },
-                            toml::Value::String(s) => quote! { #s },
-                            // 其他类型需要进一步处理
-                            _ => quote! { Default::default() },
-                        };
-                        quote! { #field_name: #default_literal }
-                    } else {
-                        quote! { #field_name: Default::default() }
-                    }
-                }).collect::<Vec<_>>();
-
-                quote! {
-                    impl Default for #name {
-                        fn default() -> Self {
-                            Self {
-                                #(#default_fields),*
-                            }
-                        }
+        // 总是生成 Default 实现，原型的 Bundle 依赖于此，未指定默认值的字段使用类型自身的默认值
+        let default_fields = self.fields.iter().flatten().map(|field| {
+            let field_name = Ident::new(field.name.as_str(), Span::call_site());
+            let default_value = transpile_field_default(field, context)?;
+            Ok(quote! { #field_name: #default_value })
+        }).collect::<Result<Vec<_>, TranspileError>>()?;
+        let default_impl = quote! {
+            impl Default for #name {
+                fn default() -> Self {
+                    Self {
+                        #(#default_fields),*
                     }
                 }
-            } else {
-                quote! {}
             }
-        } else {
-            quote! {}
         };
         //生成Describe trait
         let description_impl = transpile_descriptions(self,self.name.as_str());
+        let lua_binding = self.transpile_lua_binding()?;
 
         Ok(quote! {
-            #[derive(Component)]
+            #[derive(Component, Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+            #[serde(default)]
             pub struct #name {
                 #(#fields)*
             }
@@ -122,6 +208,8 @@ impl Transpile for Component {
             #default_impl
 
             #description_impl
+
+            #lua_binding
         })
     }
 }
@@ -134,32 +222,173 @@ impl Transpile for Event {
             vec![]
         };
         let description_impl = transpile_descriptions(self, self.name.as_str());
+        let lua_binding = self.transpile_lua_binding()?;
 
         Ok(
             quote! {
-                #[derive(Event)]
+                #[derive(Event, Debug, Clone, serde::Serialize, serde::Deserialize)]
                 pub struct #name {
                     #(#fields)*
                 }
 
                 #description_impl
+
+                #lua_binding
             }
         )
     }
 }
+impl Transpile for Enum {
+    fn transpile(&self) -> Result<TokenStream, TranspileError> {
+        let name = Ident::new(self.name.as_str(), Span::call_site());
+        let variants = self.variants.iter()
+            .map(|variant| Ident::new(variant, Span::call_site()))
+            .collect::<Vec<_>>();
+        let variant_names = self.variants.iter().map(|variant| variant.as_str()).collect::<Vec<_>>();
+        // 第一个变体作为默认值
+        let variant_defs = variants.iter().enumerate().map(|(index, variant)| {
+            if index == 0 {
+                quote! { #[default] #variant }
+            } else {
+                quote! { #variant }
+            }
+        });
+        let doc = self.description.as_ref().map(|description| {
+            let description = description.as_str();
+            quote! { #[doc = #description] }
+        });
+        let lua_binding = self.transpile_lua_binding()?;
+
+        Ok(quote! {
+            #doc
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize)]
+            pub enum #name {
+                #(#variant_defs),*
+            }
+
+            impl #name {
+                pub fn as_str(&self) -> &'static str {
+                    match self {
+                        #(Self::#variants => #variant_names),*
+                    }
+                }
+            }
+
+            #lua_binding
+        })
+    }
+}
+impl Transpile for Record {
+    fn transpile(&self) -> Result<TokenStream, TranspileError> {
+        self.transpile_with(&TranspileContext::default())
+    }
+    fn transpile_with(&self, context: &TranspileContext) -> Result<TokenStream, TranspileError> {
+        let name = Ident::new(self.name.as_str(), Span::call_site());
+        let fields = transpile_fields(self.fields.iter().flatten()).collect::<Vec<_>>();
+        // 记录总是可以默认构造，内联表默认值中缺省的字段依赖于此
+        let default_fields = self.fields.iter().flatten().map(|field| {
+            let field_name = field.name_as_rust_ident();
+            let default_value = transpile_field_default(field, context)?;
+            Ok(quote! { #field_name: #default_value })
+        }).collect::<Result<Vec<_>, TranspileError>>()?;
+        let doc = self.description.as_ref().map(|description| {
+            let description = description.as_str();
+            quote! { #[doc = #description] }
+        });
+        let lua_binding = self.transpile_lua_binding()?;
+
+        Ok(quote! {
+            #doc
+            #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+            #[serde(default)]
+            pub struct #name {
+                #(#fields)*
+            }
+
+            impl Default for #name {
+                fn default() -> Self {
+                    Self {
+                        #(#default_fields),*
+                    }
+                }
+            }
+
+            #lua_binding
+        })
+    }
+}
+///原型对应的生成系统名，如 Goblin -> spawn_goblin_system
+pub fn entity_proto_spawn_system_name(proto_name: &str) -> Ident {
+    Ident::new(&format!("spawn_{}_system", proto_name.to_lowercase()), Span::call_site())
+}
 impl Transpile for EntityProto {
     fn transpile(&self) -> Result<TokenStream, TranspileError> {
+        self.transpile_with(&TranspileContext::default())
+    }
+    fn transpile_with(&self, context: &TranspileContext) -> Result<TokenStream, TranspileError> {
+        // 继承需要在检查阶段展开
+        if self.extends.is_some() {
+            return Err(TranspileError::UnflattenedPrototype(self.name.to_string()));
+        }
         let name = Ident::new(self.name.as_str(), Span::call_site());
         let bundle_name = Ident::new(&format!("{}Bundle", self.name), Span::call_site());
-        let spawn_system_name = Ident::new(&format!("spawn_{}_system", self.name.to_lowercase()), Span::call_site());
+        let spawn_system_name = entity_proto_spawn_system_name(&self.name);
 
         // 生成 Bundle 字段
-        let bundle_fields = self.components.iter().map(|component_ref| {
-            let component_name = Ident::new(component_ref.name.as_str(), Span::call_site());
+        let bundle_fields = self.components.iter().map(|component| {
+            let component_path = component.component_ref.to_rust_path(context.module_name());
+            let field_name = Ident::new(&to_snake_case(component.component_ref.name.as_str()), Span::call_site());
             quote! {
-                pub #component_name: #component_name,
+                pub #field_name: #component_path,
             }
         }).collect::<Vec<_>>();
+        // 覆盖的字段以字面量写出，其余字段使用组件的默认值
+        let bundle_values = self.components.iter().map(|component| {
+            let component_ref = &component.component_ref;
+            let field_name = Ident::new(&to_snake_case(component_ref.name.as_str()), Span::call_site());
+            if component.overrides.is_empty() {
+                return Ok(quote! { #field_name: Default::default() });
+            }
+            let (component_module, component_def) = context.types()
+                .resolve_component(component_ref, context.module_name())
+                .ok_or_else(|| TranspileError::UnresolvedType(component_ref.to_string()))?;
+            let component_path = component_ref.to_rust_path(context.module_name());
+            let overrides = component.overrides.iter().map(|(override_name, value)| {
+                let field = component_def.get_field(override_name)
+                    .ok_or_else(|| TranspileError::UnresolvedType(format!("{}.{}", component_ref, override_name)))?;
+                let override_name = field.name_as_rust_ident();
+                let value = transpile_value(&field.type_spec, value, component_module, context)?;
+                Ok(quote! { #override_name: #value })
+            }).collect::<Result<Vec<_>, TranspileError>>()?;
+            // 所有字段都被覆盖时不需要默认值补全
+            let rest = (overrides.len() < component_def.fields.iter().flatten().count())
+                .then(|| quote! { ..Default::default() });
+            Ok(quote! {
+                #field_name: #component_path {
+                    #(#overrides,)*
+                    #rest
+                }
+            })
+        }).collect::<Result<Vec<_>, TranspileError>>()?;
+
+        // 运行时的覆盖按组件的蛇形名匹配 Bundle 字段
+        let override_arms = self.components.iter().map(|component| {
+            let field_name = Ident::new(&to_snake_case(component.component_ref.name.as_str()), Span::call_site());
+            let key = field_name.to_string();
+            quote! {
+                #key => bundle.#field_name = apply_overrides(component, &bundle.#field_name, fields)?,
+            }
+        }).collect::<Vec<_>>();
+        let prototype_name = self.name.as_str();
+
+        // 原型可以通过模块限定的名字生成，名字在所有模块中唯一时也可以省略模块
+        let mut prototype_names = Vec::new();
+        if !context.is_ambiguous_prototype(self.name.as_str()) {
+            prototype_names.push(self.name.to_string());
+        }
+        if !context.module_name().is_empty() {
+            prototype_names.push(format!("{}::{}", context.module_name(), self.name));
+        }
 
         // 生成描述实现
         let description_impl = transpile_descriptions(self, self.name.as_str());
@@ -174,7 +403,24 @@ impl Transpile for EntityProto {
 
             impl #name {
                 pub fn bundle() -> #bundle_name {
-                    #bundle_name::default()
+                    #bundle_name {
+                        #(#bundle_values,)*
+                    }
+                }
+
+                ///在原型的初始值上应用运行时覆盖
+                pub fn bundle_with(overrides: &SpawnOverrides) -> Result<#bundle_name, OverrideError> {
+                    let mut bundle = Self::bundle();
+                    for (component, fields) in overrides.iter() {
+                        match component.as_str() {
+                            #(#override_arms)*
+                            _ => return Err(OverrideError::UnknownComponent {
+                                prototype: #prototype_name.to_string(),
+                                component: component.clone(),
+                            }),
+                        }
+                    }
+                    Ok(bundle)
                 }
 
                 pub fn spawn(commands: &mut Commands) -> Entity {
@@ -188,11 +434,25 @@ impl Transpile for EntityProto {
                 mut commands: Commands,
             ) {
                 for event in events.read() {
-                    if event.prototype_name == stringify!(#name) {
-                        let entity = #name::spawn(&mut commands);
-                        if let Some(response) = &event.entity_response {
-                            let _ = response.send(entity);
-                        }
+                    if !matches!(event.prototype_name.as_str(), #(#prototype_names)|*) {
+                        continue;
+                    }
+                    let bundle = match &event.overrides {
+                        Some(overrides) => match #name::bundle_with(overrides) {
+                            Ok(bundle) => bundle,
+                            Err(error) => {
+                                eprintln!("Failed to spawn \"{}\": {}", event.prototype_name, error);
+                                continue;
+                            }
+                        },
+                        None => #name::bundle(),
+                    };
+                    let mut entity = commands.spawn(bundle);
+                    if let Some(parent) = event.parent {
+                        entity.insert(ChildOf(parent));
+                    }
+                    if let Some(response) = &event.entity_response {
+                        let _ = response.send(entity.id());
                     }
                 }
             }
@@ -203,22 +463,25 @@ impl Transpile for EntityProto {
 }
 impl Transpile for SystemQuery {
     fn transpile(&self) -> Result<TokenStream, TranspileError> {
+        self.transpile_with(&TranspileContext::default())
+    }
+    fn transpile_with(&self, context: &TranspileContext) -> Result<TokenStream, TranspileError> {
         let mut filters = {
             let mut filters = Vec::new();
 
             // 处理 With 过滤器（包含的组件）
             if let Some(include_components) = self.component_constraint.get_include() {
                 for component_ref in include_components {
-                    let component_name = Ident::new(&component_ref.as_path_str(), Span::call_site());
-                    filters.push(quote! { With<#component_name> });
+                    let component_path = component_ref.to_rust_path(context.module_name());
+                    filters.push(quote! { With<#component_path> });
                 }
             }
 
             // 处理 Without 过滤器（排除的组件）
             if let Some(exclude_components) = self.component_constraint.get_exclude() {
                 for component_ref in exclude_components {
-                    let component_name = Ident::new(&component_ref.as_path_str(), Span::call_site());
-                    filters.push(quote! { Without<#component_name> });
+                    let component_path = component_ref.to_rust_path(context.module_name());
+                    filters.push(quote! { Without<#component_path> });
                 }
             }
             filters
@@ -237,26 +500,563 @@ impl Transpile for SystemQuery {
         }
     }
 }
+///生成将系统注册到 App 的语句，系统按 normal.priority 排序执行
+pub fn transpile_system_registration(system: &System, module_name: &str) -> TokenStream {
+    let name = Ident::new(&to_snake_case(system.normal.name.as_str()), Span::call_site());
+    let priority = system.normal.priority;
+    let qualified_name = format!("{}::{}", module_name, system.normal.name);
+    quote! {
+        app.add_aethaum_system(AethaumSystemSet::new(#priority, #qualified_name), #name);
+    }
+}
+///生成获取编译后脚本的表达式，脚本按 (系统, 位置) 缓存，只在第一次执行时编译
+///内嵌脚本以 "系统名::位置" 命名，文件脚本在运行时从项目根目录读取
+fn transpile_load_script(script: &LuaScript, system_ref: &str, slot: TokenStream, chunk_name: &str) -> TokenStream {
+    let source = match script {
+        LuaScript::Embed(source) => {
+            let source = source.as_str();
+            quote! { ScriptSource::Embed { name: #chunk_name, source: #source } }
+        }
+        LuaScript::File(path) => {
+            let path = path.to_string_lossy();
+            quote! { ScriptSource::File(::std::path::Path::new(#path)) }
+        }
+    };
+    quote! { load_script(&lua, ScriptKey::new(#system_ref, #slot), #source)? }
+}
 impl Transpile for System {
     fn transpile(&self) -> Result<TokenStream, TranspileError> {
-        let name = Ident::new(self.normal.name.as_str(), Span::call_site());
-        let queries = {
-            self.queries.iter()
-                .map(|query| {
-                    query.transpile().unwrap() //ROBUST: the Transpile for Query will always succeed
-                })
-                .collect::<Vec<_>>()
+        self.transpile_with(&TranspileContext::default())
+    }
+    fn transpile_with(&self, context: &TranspileContext) -> Result<TokenStream, TranspileError> {
+        let system_name = self.normal.name.as_str();
+        let name = Ident::new(&to_snake_case(system_name), Span::call_site());
+        let doc = self.description().unwrap_or(system_name);
+        let module_name = context.module_name();
+        let system_ref = SystemRef::new((!module_name.is_empty()).then_some(module_name), system_name).as_path_str();
+
+        // 每个查询对应一个 Query 参数
+        let query_names = self.queries.iter()
+            .map(|query| Ident::new(query.name.as_str(), Span::call_site()))
+            .collect::<Vec<_>>();
+        let query_params = self.queries.iter()
+            .zip(query_names.iter())
+            .map(|(query, query_name)| {
+                let query_type = query.transpile_with(context).unwrap(); //ROBUST: the Transpile for Query will always succeed
+                quote! { #query_name: #query_type, }
+            })
+            .collect::<Vec<_>>();
+        let query_str_names = self.queries.iter().map(|query| query.name.as_str());
+
+        // 每个事件处理器对应一个 EventReader 参数，优先级高的处理器先执行，同优先级保持声明顺序
+        let mut event_handlers = self.event_handlers.iter().enumerate().collect::<Vec<_>>();
+        event_handlers.sort_by_key(|(_, handler)| std::cmp::Reverse(handler.priority));
+        let reader_names = event_handlers.iter()
+            .map(|(index, _)| Ident::new(&format!("event_handler_{}", index), Span::call_site()))
+            .collect::<Vec<_>>();
+        let reader_params = event_handlers.iter()
+            .map(|(_, handler)| handler)
+            .zip(reader_names.iter())
+            .map(|(handler, reader_name)| {
+                let event_path = handler.watch_for.to_rust_path(context.module_name());
+                quote! { mut #reader_name: EventReader<#event_path>, }
+            })
+            .collect::<Vec<_>>();
+
+        // 每个查询的结果单独收集，逻辑对每个查询分别执行，保证实体满足该查询的组件约束
+        let collect_queried = if self.queries.is_empty() {
+            quote! {}
+        } else {
+            let query_count = self.queries.len();
+            quote! {
+                let aethaum_queried: [Vec<Entity>; #query_count] = [#(#query_names.iter().collect(),)*];
+            }
+        };
+        // update 与事件处理器可以用 query 指定只处理其中一个查询
+        let select_queries = |query: Option<&SmartString>| -> Result<TokenStream, TranspileError> {
+            match query {
+                Some(query_name) => {
+                    let index = self.queries.iter()
+                        .position(|query| &query.name == query_name)
+                        .ok_or_else(|| TranspileError::UnknownQuery(query_name.to_string(), system_name.to_string()))?;
+                    Ok(quote! { [&aethaum_queried[#index]] })
+                }
+                None => Ok(quote! { aethaum_queried.iter() }),
+            }
+        };
+        // 查询中包含的组件复制一份借给Lua，脚本执行完后写回，其间 aethaum.get 可以读取其他实体的组件
+        let mut accessed_components: Vec<&ComponentRef> = Vec::new();
+        for component_ref in self.queries.iter().flat_map(|query| query.component_constraint.get_include().into_iter().flatten()) {
+            if !accessed_components.contains(&component_ref) {
+                accessed_components.push(component_ref);
+            }
+        }
+        let component_types = accessed_components.iter()
+            .map(|component_ref| component_ref.to_rust_path(context.module_name()))
+            .collect::<Vec<_>>();
+        let component_vars = accessed_components.iter()
+            .map(|component_ref| Ident::new(&format!("component_{}", component_lua_name(&component_ref.name)), Span::call_site()))
+            .collect::<Vec<_>>();
+        let view_vars = accessed_components.iter()
+            .map(|component_ref| Ident::new(&format!("view_{}", component_lua_name(&component_ref.name)), Span::call_site()))
+            .collect::<Vec<_>>();
+        let component_lua_names = accessed_components.iter()
+            .map(|component_ref| component_lua_name(&component_ref.name))
+            .collect::<Vec<_>>();
+        let components_param = if accessed_components.is_empty() {
+            quote! {}
+        } else {
+            quote! { mut aethaum_components: Query<(#(Option<&mut #component_types>,)*)>, }
         };
-        todo!("transpile System")
+
+        // aethaum.get 只能读取查询中出现过的组件，正在处理的实体直接返回实体视图中的组件
+        let get_component = |local_view: TokenStream| if accessed_components.is_empty() {
+            quote! {
+                |_, (_, component): (LuaEntity, String)| -> mlua::Result<mlua::Value> {
+                    Err(mlua::Error::runtime(format!("component '{}' is not accessible in system \"{}\", include it in a query", component, #system_name)))
+                }
+            }
+        } else {
+            quote! {
+                move |lua, (target, component): (LuaEntity, String)| -> mlua::Result<mlua::Value> {
+                    if !matches!(component.as_str(), #(#component_lua_names)|*) {
+                        return Err(mlua::Error::runtime(format!("component '{}' is not accessible in system \"{}\", include it in a query", component, #system_name)));
+                    }
+                    #local_view
+                    let Ok((#(#component_vars,)*)) = aethaum_lookup.get(target.0) else {
+                        return Ok(mlua::Value::Nil);
+                    };
+                    let found = match component.as_str() {
+                        #(#component_lua_names => #component_vars.map(|component| lua.create_userdata(component.clone())).transpose()?,)*
+                        _ => None,
+                    };
+                    Ok(found.map_or(mlua::Value::Nil, mlua::Value::UserData))
+                }
+            }
+        };
+        let bind_api = |get_component: TokenStream| {
+            quote! {
+                let aethaum_api = lua.create_table()?;
+                aethaum_api.set("spawn", scope.create_function(move |_, (prototype, overrides, parent): (String, Option<mlua::Value>, Option<LuaEntity>)| {
+                    let mut spawn = AethaumSpawnEntity::new(prototype);
+                    if let Some(overrides) = overrides {
+                        spawn = spawn.with_overrides(lua_to_overrides(&overrides)?);
+                    }
+                    if let Some(parent) = parent {
+                        spawn = spawn.with_parent(parent.0);
+                    }
+                    aethaum_commands.borrow_mut().send_event(spawn);
+                    Ok(())
+                })?)?;
+                aethaum_api.set("despawn", scope.create_function(move |_, target: LuaEntity| {
+                    let mut commands = aethaum_commands.borrow_mut();
+                    if let Ok(mut target) = commands.get_entity(target.0) {
+                        target.try_despawn();
+                    }
+                    Ok(())
+                })?)?;
+                aethaum_api.set("emit", scope.create_function(move |_, (event_name, fields): (String, Option<mlua::Value>)| {
+                    let fields = match fields {
+                        Some(fields) => lua_to_json(&fields)?,
+                        None => serde_json::Value::Object(Default::default()),
+                    };
+                    crate::emit_event(&mut aethaum_commands.borrow_mut(), &event_name, fields).map_err(mlua::Error::external)
+                })?)?;
+                aethaum_api.set("get", scope.create_function(#get_component)?)?;
+                let queries = aethaum_queries.clone();
+                aethaum_api.set("query", scope.create_function(move |_, query_name: String| {
+                    match queries.get::<Option<mlua::Table>>(query_name.as_str())? {
+                        Some(entities) => Ok(entities),
+                        None => Err(mlua::Error::runtime(format!("system \"{}\" has no query named '{}'", #system_name, query_name))),
+                    }
+                })?)?;
+                globals.set("aethaum", aethaum_api)?;
+            }
+        };
+
+        // 无查询的系统只针对"世界"执行一次，此时 entity 为 nil
+        let for_each_entity = |body: TokenStream, event: Option<TokenStream>, queries: TokenStream| {
+            let bind_event = event.map(|event| quote! {
+                globals.set("event", scope.create_userdata_ref(#event)?)?;
+            });
+            if self.queries.is_empty() {
+                let bind_api = bind_api(get_component(quote! {}));
+                quote! {
+                    lua.scope(|scope| {
+                        globals.set("entity", mlua::Value::Nil)?;
+                        #bind_event
+                        #bind_api
+                        reset_instruction_budget(&lua);
+                        #body
+                    })?;
+                }
+            } else if accessed_components.is_empty() {
+                let bind_api = bind_api(get_component(quote! {}));
+                quote! {
+                    for queried in #queries {
+                        for &entity in queried.iter() {
+                            lua.scope(|scope| {
+                                let entity_view = lua.create_table()?;
+                                entity_view.set("id", LuaEntity(entity))?;
+                                globals.set("entity", entity_view)?;
+                                #bind_event
+                                #bind_api
+                                reset_instruction_budget(&lua);
+                                #body
+                            })?;
+                        }
+                    }
+                }
+            } else {
+                let bind_api = bind_api(get_component(quote! {
+                    if target.0 == entity {
+                        return current_view.get(component.as_str());
+                    }
+                }));
+                quote! {
+                    for queried in #queries {
+                        for &entity in queried.iter() {
+                            let Ok((#(#component_vars,)*)) = aethaum_components.get(entity) else {
+                                continue;
+                            };
+                            #(
+                                let mut #component_vars = #component_vars.cloned();
+                                let #view_vars = #component_vars.as_mut();
+                            )*
+                            let aethaum_lookup = &aethaum_components;
+                            lua.scope(|scope| {
+                                let entity_view = lua.create_table()?;
+                                entity_view.set("id", LuaEntity(entity))?;
+                                #(
+                                    if let Some(component) = #view_vars {
+                                        entity_view.set(#component_lua_names, scope.create_userdata_ref_mut(component)?)?;
+                                    }
+                                )*
+                                globals.set("entity", &entity_view)?;
+                                let current_view = entity_view.clone();
+                                #bind_event
+                                #bind_api
+                                reset_instruction_budget(&lua);
+                                #body
+                            })?;
+                            // 只有值发生变化的组件才会触发变更检测
+                            if let Ok((#(#view_vars,)*)) = aethaum_components.get_mut(entity) {
+                                #(
+                                    if let (Some(mut target), Some(value)) = (#view_vars, #component_vars) {
+                                        target.set_if_neq(value);
+                                    }
+                                )*
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        // 批量模式下脚本每次更新对每个查询只执行一次，查询到的实体视图以数组 entities 传入，此时 entity 为 nil
+        let for_batch = |body: TokenStream, queries: TokenStream| {
+            if self.queries.is_empty() {
+                let bind_api = bind_api(get_component(quote! {}));
+                quote! {
+                    lua.scope(|scope| {
+                        globals.set("entity", mlua::Value::Nil)?;
+                        globals.set("entities", lua.create_table()?)?;
+                        #bind_api
+                        reset_instruction_budget(&lua);
+                        #body
+                    })?;
+                }
+            } else if accessed_components.is_empty() {
+                let bind_api = bind_api(get_component(quote! {}));
+                quote! {
+                    for queried in #queries {
+                        lua.scope(|scope| {
+                            let entity_views = lua.create_table_with_capacity(queried.len(), 0)?;
+                            for &entity in queried.iter() {
+                                let entity_view = lua.create_table()?;
+                                entity_view.set("id", LuaEntity(entity))?;
+                                entity_views.push(entity_view)?;
+                            }
+                            globals.set("entity", mlua::Value::Nil)?;
+                            globals.set("entities", entity_views)?;
+                            #bind_api
+                            reset_instruction_budget(&lua);
+                            #body
+                        })?;
+                    }
+                }
+            } else {
+                let bind_api = bind_api(get_component(quote! {
+                    if let Some(view) = batch_views.get(&target.0) {
+                        return view.get(component.as_str());
+                    }
+                }));
+                quote! {
+                    for queried in #queries {
+                        let mut aethaum_batch = Vec::with_capacity(queried.len());
+                        for &entity in queried.iter() {
+                            if let Ok((#(#component_vars,)*)) = aethaum_components.get(entity) {
+                                aethaum_batch.push((entity, #(#component_vars.cloned(),)*));
+                            }
+                        }
+                        let batch_entries = aethaum_batch.iter_mut();
+                        let aethaum_lookup = &aethaum_components;
+                        lua.scope(|scope| {
+                            let entity_views = lua.create_table_with_capacity(batch_entries.len(), 0)?;
+                            let mut batch_views = ::std::collections::HashMap::with_capacity(batch_entries.len());
+                            for (entity, #(#component_vars,)*) in batch_entries {
+                                let entity_view = lua.create_table()?;
+                                entity_view.set("id", LuaEntity(*entity))?;
+                                #(
+                                    if let Some(component) = #component_vars.as_mut() {
+                                        entity_view.set(#component_lua_names, scope.create_userdata_ref_mut(component)?)?;
+                                    }
+                                )*
+                                entity_views.push(&entity_view)?;
+                                batch_views.insert(*entity, entity_view);
+                            }
+                            globals.set("entity", mlua::Value::Nil)?;
+                            globals.set("entities", entity_views)?;
+                            #bind_api
+                            reset_instruction_budget(&lua);
+                            #body
+                        })?;
+                        for (entity, #(#component_vars,)*) in aethaum_batch {
+                            if let Ok((#(#view_vars,)*)) = aethaum_components.get_mut(entity) {
+                                #(
+                                    if let (Some(mut target), Some(value)) = (#view_vars, #component_vars) {
+                                        target.set_if_neq(value);
+                                    }
+                                )*
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        // update 逻辑按 interval 定时执行，condition 在逐实体模式下对每个实体单独求值，在批量模式下每次更新求值一次
+        let (update_params, update_logic) = match self.update.as_ref().and_then(|update| update.logic.as_ref().map(|logic| (update, logic))) {
+            Some((update, logic)) => {
+                let load_logic = transpile_load_script(logic, &system_ref, quote! { ScriptSlot::UpdateLogic }, &format!("{}::update", system_name));
+                let load_condition = update.condition.as_ref().map(|condition| {
+                    let load_condition = transpile_load_script(condition, &system_ref, quote! { ScriptSlot::UpdateCondition }, &format!("{}::update::condition", system_name));
+                    quote! { let update_condition = #load_condition; }
+                });
+                let body = match &update.condition {
+                    Some(_) => quote! {
+                        if update_condition.call::<bool>(())? {
+                            update_logic.call::<()>(())
+                        } else {
+                            Ok(())
+                        }
+                    },
+                    None => quote! { update_logic.call::<()>(()) },
+                };
+                let run_for_entities = match update.mode {
+                    UpdateMode::PerEntity => for_each_entity(body, None, select_queries(update.query.as_ref())?),
+                    UpdateMode::Batch => for_batch(body, select_queries(update.query.as_ref())?),
+                };
+                let interval_nanos = update.interval.as_nanos() as u64;
+                (
+                    quote! {
+                        aethaum_clock: Res<WorldClock>,
+                        mut update_timer: Local<IntervalTimer>,
+                    },
+                    quote! {
+                        let update_runs = update_timer.tick(
+                            aethaum_clock.delta(),
+                            ::std::time::Duration::from_nanos(#interval_nanos),
+                        );
+                        report("update", (|| -> mlua::Result<()> {
+                            if update_runs == 0 {
+                                return Ok(());
+                            }
+                            #load_condition
+                            let update_logic = #load_logic;
+                            for _ in 0..update_runs {
+                                #run_for_entities
+                            }
+                            Ok(())
+                        })());
+                    }
+                )
+            }
+            None => (quote! {}, quote! {}),
+        };
+
+        let handler_logics = event_handlers.iter()
+            .zip(reader_names.iter())
+            .map(|((index, handler), reader_name)| {
+                match &handler.logic {
+                    Some(logic) => {
+                        let load_handler = transpile_load_script(logic, &system_ref, quote! { ScriptSlot::EventHandler(#index) }, &format!("{}::event_handlers[{}]", system_name, index));
+                        let run_for_entities = for_each_entity(quote! { handler_logic.call::<()>(()) }, Some(quote! { event }), select_queries(handler.query.as_ref())?);
+                        let handler_tag = format!("event_handlers[{}]", index);
+                        Ok(quote! {
+                            report(#handler_tag, (|| -> mlua::Result<()> {
+                                // 没有事件时不需要加载脚本
+                                if #reader_name.is_empty() {
+                                    return Ok(());
+                                }
+                                let handler_logic = #load_handler;
+                                for event in #reader_name.read() {
+                                    #run_for_entities
+                                }
+                                Ok(())
+                            })());
+                        })
+                    }
+                    // 没有处理逻辑时仍然需要消费事件
+                    None => Ok(quote! { #reader_name.clear(); })
+                }
+            })
+            .collect::<Result<Vec<_>, TranspileError>>()?;
+
+        // 没有任何脚本的系统不需要查询，只需要消费事件
+        let has_logic = !update_logic.is_empty() || event_handlers.iter().any(|(_, handler)| handler.logic.is_some());
+        let (lua_params, body) = if has_logic {
+            (
+                quote! {
+                    lua: NonSend<mlua::Lua>,
+                    commands: Commands,
+                    #(#query_params)*
+                    #components_param
+                },
+                quote! {
+                    let aethaum_commands = &::std::cell::RefCell::new(commands);
+                    // 错误标明出错的系统与处理器，一个处理器失败不影响其他处理器
+                    let report = |handler: &str, result: mlua::Result<()>| {
+                        if let Err(error) = result {
+                            eprintln!("{}", ScriptError::new(#system_ref, handler, error));
+                        }
+                    };
+                    let globals = lua.globals();
+                    // 查询结果以实体句柄数组的形式暴露给Lua，同时可以通过 aethaum.query 按名获取
+                    let aethaum_queries = match (|| -> mlua::Result<mlua::Table> {
+                        let aethaum_queries = lua.create_table()?;
+                        #(
+                            let entities = lua.create_sequence_from(#query_names.iter().map(LuaEntity))?;
+                            globals.set(#query_str_names, &entities)?;
+                            aethaum_queries.set(#query_str_names, entities)?;
+                        )*
+                        Ok(aethaum_queries)
+                    })() {
+                        Ok(aethaum_queries) => aethaum_queries,
+                        Err(error) => {
+                            report("queries", Err(error));
+                            return;
+                        }
+                    };
+                    #collect_queried
+
+                    #update_logic
+
+                    #(#handler_logics)*
+                },
+            )
+        } else {
+            (quote! {}, quote! { #(#handler_logics)* })
+        };
+
+        Ok(quote! {
+            #[doc = #doc]
+            pub fn #name(
+                #lua_params
+                #update_params
+                #(#reader_params)*
+            ) {
+                #body
+            }
+        })
+    }
+}
+///模块对应的插件名，如 combat -> CombatPlugin
+pub fn module_plugin_name(module_name: &str) -> Ident {
+    Ident::new(&format!("{}Plugin", to_pascal_case(module_name)), Span::call_site())
+}
+///生成模块插件，注册模块内的事件、原型生成系统与系统
+fn transpile_module_plugin(module: &EcsModule) -> TokenStream {
+    let plugin_name = module_plugin_name(&module.name);
+    let events = module.events.iter().flatten()
+        .map(|event| Ident::new(event.name.as_str(), Span::call_site()))
+        .collect::<Vec<_>>();
+    let spawn_systems = module.entity_protos.iter().flatten()
+        .map(|entity_proto| entity_proto_spawn_system_name(&entity_proto.name))
+        .collect::<Vec<_>>();
+    let system_registrations = module.systems.iter().flatten()
+        .map(|system| transpile_system_registration(system, module.name.as_str()))
+        .collect::<Vec<_>>();
+    // 生成系统与 Lua 中的 aethaum.spawn 依赖 AethaumSpawnEntity，重复注册事件不会产生影响
+    let spawn_event = (!spawn_systems.is_empty() || !system_registrations.is_empty()).then(|| quote! {
+        app.add_event::<AethaumSpawnEntity>();
+    });
+    // 空模块的插件不注册任何内容
+    let app = if spawn_systems.is_empty() && events.is_empty() && system_registrations.is_empty() {
+        quote! { _app }
+    } else {
+        quote! { app }
+    };
+    let doc = format!("Registers the events, prototypes and systems of module `{}`", module.name);
+    quote! {
+        #[doc = #doc]
+        pub struct #plugin_name;
+        impl Plugin for #plugin_name {
+            fn build(&self, #app: &mut App) {
+                #spawn_event
+                #(app.add_event::<#events>();)*
+                #(app.add_systems(Update, #spawn_systems);)*
+                #(#system_registrations)*
+            }
+        }
     }
 }
+impl Transpile for EcsModule {
+    fn transpile(&self) -> Result<TokenStream, TranspileError> {
+        self.transpile_with(&TranspileContext::new(self.name.as_str(), CustomTypes::default().with_module(self)))
+    }
+    ///一个模块对应生成代码中的一个 Rust 模块，预定义的类型位于上级模块
+    fn transpile_with(&self, context: &TranspileContext) -> Result<TokenStream, TranspileError> {
+        let mut items = TokenStream::new();
+        for enum_def in self.enums.iter().flatten() {
+            items.extend(enum_def.transpile_with(context)?);
+        }
+        for record in self.records.iter().flatten() {
+            items.extend(record.transpile_with(context)?);
+        }
+        for component in self.components.iter().flatten() {
+            items.extend(component.transpile_with(context)?);
+        }
+        for event in self.events.iter().flatten() {
+            items.extend(event.transpile_with(context)?);
+        }
+        for entity_proto in self.entity_protos.iter().flatten() {
+            items.extend(entity_proto.transpile_with(context)?);
+        }
+        for system in self.systems.iter().flatten() {
+            items.extend(system.transpile_with(context)?);
+        }
+        items.extend(transpile_module_plugin(self));
+        Ok(quote! {
+            #![allow(unused_imports)]
+            use bevy_app::prelude::*;
+            use bevy_ecs::prelude::*;
+            use aethaum_runtime::prelude::*;
+            use super::{AethaumSpawnEntity, Describe};
 
+            #items
+        })
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use smart_string::SmartString;
+    use crate::toml_parser::source::SourceLocation;
     use crate::code_generator::utils::format_rust_code;
     use crate::toml_parser::parsed::{AethaumType, ComponentField, ComponentRef, EventField, PrimitiveType};
+    use crate::ecs::module::{CustomTypes, EcsModule};
+    use crate::toml_parser::raw::{RawComponentFile, RawEntityProtoFile, RawSystemFile, RawTomlCodeFile, RawTypeFile};
     use super::*;
     #[test]
     fn test_transpile_component() {
@@ -269,18 +1069,32 @@ mod tests {
                     type_spec: AethaumType::Primitive(PrimitiveType::Bool),
                     default_value: Some(toml::Value::Boolean(true)),
                     description: Some(SmartString::from("This is a test field".to_string())),
+                    location: SourceLocation::default(),
                 },
                 ComponentField {
                     name: SmartString::from("test_field2".to_string()),
                     type_spec: AethaumType::Primitive(PrimitiveType::Int),
                     default_value: None,
                     description: None,
+                    location: SourceLocation::default(),
+                },
+                ComponentField {
+                    name: SmartString::from("waypoints".to_string()),
+                    type_spec: AethaumType::from_type_str("list<float>"),
+                    default_value: Some(toml::Value::Array(vec![toml::Value::Float(1.0), toml::Value::Float(2.5)])),
+                    description: None,
+                    location: SourceLocation::default(),
                 },
             ]),
+            location: SourceLocation::default(),
         };
         let transpiled = component.transpile().unwrap();
         let transpiled = format_rust_code(transpiled).unwrap();
         println!("{}", transpiled);
+        assert!(transpiled.contains("pub waypoints: Vec<f32>,"));
+        assert!(transpiled.contains("#[serde(deserialize_with = \"aethaum_runtime::api::deserialize_list\")]\n    pub waypoints"), "{}", transpiled);
+        assert_eq!(transpiled.matches("deserialize_with").count(), 1);
+        assert!(transpiled.contains("waypoints: vec![1f32, 2.5f32]"));
         let parsed_result = syn::parse_str::<syn::File>(&transpiled);
         assert!(parsed_result.is_ok(), "Generated code has syntax errors: {:?}", parsed_result.err());
     }
@@ -289,18 +1103,22 @@ mod tests {
         let event = Event {
             name: SmartString::from("click"),
             description: Some("Click event".into()),
+            tags: Vec::new(),
             fields: Option::from(vec![
                 EventField {
                     name: SmartString::from("target"),
                     description: Some("The element that was clicked".into()),
                     type_spec: AethaumType::Primitive(PrimitiveType::Str),
+                    location: SourceLocation::default(),
                 },
                 EventField {
                     name: SmartString::from("value"),
                     description: None,
                     type_spec: AethaumType::Primitive(PrimitiveType::Int),
+                    location: SourceLocation::default(),
                 },
             ]),
+            location: SourceLocation::default(),
         };
         let transpiled = event.transpile().unwrap();
         println!("{}", transpiled);
@@ -310,14 +1128,103 @@ mod tests {
         assert!(parsed_result.is_ok(), "Generated code has syntax errors: {:?}", parsed_result.err());
     }
     #[test]
+    fn test_transpile_enum() {
+        let faction = Enum {
+            name: SmartString::from("Faction"),
+            description: Some("阵营".into()),
+            variants: vec!["Ally".into(), "Enemy".into(), "Neutral".into()],
+            location: SourceLocation::default(),
+        };
+        let transpiled = format_rust_code(faction.transpile().unwrap()).unwrap();
+        println!("{}", transpiled);
+        assert!(transpiled.contains("pub enum Faction {"));
+        assert!(transpiled.contains("#[default]\n    Ally,"));
+        assert!(transpiled.contains("Self::Neutral => \"Neutral\""));
+        let parsed_result = syn::parse_str::<syn::File>(&transpiled);
+        assert!(parsed_result.is_ok(), "Generated code has syntax errors: {:?}", parsed_result.err());
+
+        //枚举类型字段的默认值
+        let component = Component {
+            name: SmartString::from("Allegiance"),
+            description: None,
+            fields: Some(vec![
+                ComponentField {
+                    name: SmartString::from("faction"),
+                    type_spec: AethaumType::from_type_str("Faction"),
+                    default_value: Some(toml::Value::String("Neutral".into())),
+                    description: None,
+                    location: SourceLocation::default(),
+                },
+            ]),
+            location: SourceLocation::default(),
+        };
+        let transpiled = format_rust_code(component.transpile().unwrap()).unwrap();
+        assert!(transpiled.contains("pub faction: Faction,"));
+        assert!(transpiled.contains("faction: Faction::Neutral"));
+    }
+    #[test]
+    fn test_transpile_record() {
+        let toml_str = r#"
+            [[records]]
+            name = "Vec2"
+            description = "二维向量"
+
+            [[records.fields]]
+            name = "x"
+            type = "float"
+            default = 0.0
+
+            [[records.fields]]
+            name = "y"
+            type = "float"
+        "#;
+        let (_, raw_records) = toml::from_str::<RawTypeFile>(toml_str).unwrap().into_pieces();
+        let explore = EcsModule::new_empty("explore".into())
+            .with_records(raw_records.into_iter().map(Record::from).collect());
+        let record = &explore.records.as_ref().unwrap()[0];
+        let transpiled = format_rust_code(record.transpile().unwrap()).unwrap();
+        println!("{}", transpiled);
+        assert!(transpiled.contains("pub struct Vec2 {"));
+        assert!(transpiled.contains("x: 0f32,"));
+        assert!(transpiled.contains("impl mlua::FromLua for Vec2"));
+        let parsed_result = syn::parse_str::<syn::File>(&transpiled);
+        assert!(parsed_result.is_ok(), "Generated code has syntax errors: {:?}", parsed_result.err());
+
+        //其他模块中以内联表给出的默认值
+        let component = Component {
+            name: SmartString::from("Knockback"),
+            description: None,
+            fields: Some(vec![
+                ComponentField {
+                    name: SmartString::from("direction"),
+                    type_spec: AethaumType::from_type_str("explore::Vec2"),
+                    default_value: toml::from_str::<toml::Table>("v = { y = 1.0 }").unwrap().remove("v"),
+                    description: None,
+                    location: SourceLocation::default(),
+                },
+            ]),
+            location: SourceLocation::default(),
+        };
+        let types = CustomTypes::default().with_module(&explore);
+        let context = TranspileContext::new("combat", types);
+        let transpiled = format_rust_code(component.transpile_with(&context).unwrap()).unwrap();
+        println!("{}", transpiled);
+        assert!(transpiled.contains("pub direction: crate::explore::Vec2,"));
+        assert!(transpiled.contains("direction: crate::explore::Vec2 {\n                y: 1f32,\n                ..Default::default()\n            }"));
+        //缺少类型信息时无法生成记录默认值
+        assert!(matches!(component.transpile(), Err(TranspileError::UnresolvedType(_))));
+    }
+    #[test]
     fn test_transpile_entity_protos() {
         let event = EntityProto {
             name: "TestEntity".into(),
             description: Some("This is a test entity".into()),
+            extends: None,
             components: vec![
-                ComponentRef::new(None::<&str>, "position"),
-                ComponentRef::new(Some("TestComponent"), "test_component")
-            ]
+                ComponentRef::new(None::<&str>, "position").into(),
+                ComponentRef::new(Some("TestComponent"), "test_component").into()
+            ],
+            location: SourceLocation::default(),
         };
         let transpiled = event.transpile().unwrap();
         println!("{}", transpiled);
@@ -326,4 +1233,185 @@ mod tests {
         let parsed_result = syn::parse_str::<syn::File>(&transpiled);
         assert!(parsed_result.is_ok(), "Generated code has syntax errors: {:?}", parsed_result.err());
     }
+    #[test]
+    fn test_transpile_cross_module_refs() {
+        let context = TranspileContext::new("explore", CustomTypes::default());
+        let proto = EntityProto {
+            name: "Player".into(),
+            description: None,
+            extends: None,
+            components: vec![
+                ComponentRef::new(Some("combat"), "Health").into(),
+                ComponentRef::new(Some("explore"), "Position").into(),
+                ComponentRef::new(None::<&str>, "Velocity").into(),
+            ],
+            location: SourceLocation::default(),
+        };
+        let transpiled = format_rust_code(proto.transpile_with(&context).unwrap()).unwrap();
+        assert!(transpiled.contains("pub health: crate::combat::Health,"));
+        assert!(transpiled.contains("pub position: Position,"));
+        assert!(transpiled.contains("pub velocity: Velocity,"));
+        assert!(transpiled.contains(r#""Player" | "explore::Player""#));
+
+        // 两个模块都定义了 Player 时只能通过模块限定的名字生成
+        let player = |module: &str| EcsModule::new_empty(module.into()).with_entity_protos(vec![EntityProto {
+            name: "Player".into(),
+            description: None,
+            extends: None,
+            components: vec![ComponentRef::new(None::<&str>, "Position").into()],
+            location: SourceLocation::default(),
+        }]);
+        let (combat, explore) = (player("combat"), player("explore"));
+        let combat_context = TranspileContext::new("combat", CustomTypes::default()).with_prototypes_of([&combat, &explore]);
+        let transpiled = format_rust_code(combat.entity_protos.as_ref().unwrap()[0].transpile_with(&combat_context).unwrap()).unwrap();
+        assert!(transpiled.contains(r#"matches!(event.prototype_name.as_str(), "combat::Player")"#), "{}", transpiled);
+
+        let query = SystemQuery {
+            name: "living".into(),
+            description: None,
+            component_constraint: (Some(vec!["combat::Health".into()]), Some(vec!["Dead".into()])).try_into().unwrap(),
+            location: SourceLocation::default(),
+        };
+        let transpiled = query.transpile_with(&context).unwrap().to_string();
+        assert_eq!(transpiled, quote! { Query<Entity, (With<crate::combat::Health>, Without<Dead>)> }.to_string());
+    }
+    #[test]
+    fn test_transpile_proto_overrides() {
+        let components = r#"
+            [[components]]
+            name = "Health"
+
+            [[components.fields]]
+            name = "value"
+            type = "float"
+            default = 100.0
+
+            [[components.fields]]
+            name = "max_value"
+            type = "float"
+            default = 100.0
+        "#;
+        let protos = r#"
+            [[entity_protos]]
+            name = "Boss"
+            components = [{ ref = "combat::Health", value = 150.0 }]
+        "#;
+        let components = toml::from_str::<RawComponentFile>(components).unwrap().into_pieces();
+        let combat = EcsModule::new_empty("combat".into()).with_components(components.into_iter().map(Component::from).collect());
+        let proto = toml::from_str::<RawEntityProtoFile>(protos).unwrap().into_pieces().pop().unwrap();
+        let proto = EntityProto::try_from(proto).unwrap();
+        let context = TranspileContext::new("explore", CustomTypes::default().with_module(&combat));
+        let transpiled = format_rust_code(proto.transpile_with(&context).unwrap()).unwrap();
+        assert!(transpiled.contains("health: crate::combat::Health {"), "{}", transpiled);
+        assert!(transpiled.contains("value: 150f32"), "{}", transpiled);
+        assert!(transpiled.contains("..Default::default()"), "{}", transpiled);
+        assert!(!transpiled.contains("max_value"), "{}", transpiled);
+        assert!(transpiled.contains("bundle.health = apply_overrides(component, &bundle.health, fields)?;"), "{}", transpiled);
+    }
+    #[test]
+    fn test_transpile_system() {
+        let toml_str = r#"
+            [normal]
+            name = "HealthSystem"
+            description = "处理实体健康值更新"
+            priority = 100
+
+            [[queries]]
+            name = "living_entities"
+            components_include = ["Health", "Position"]
+            components_exclude = ["Test"]
+
+            [[queries]]
+            name = "damaged_entities"
+            components_include = ["Health", "Damage"]
+
+            [update]
+            interval = 0.1
+            query = "damaged_entities"
+            condition = '''
+            return entity.health.value > 0
+            '''
+            logic = '''
+            entity.health.value = entity.health.value - entity.damage.amount
+            '''
+
+            [[event_handlers]]
+            watch_for = "EntityDamaged"
+            priority = 10
+            logic = '''
+            entity.health.value = entity.health.value - event.damage
+            '''
+
+            [[event_handlers]]
+            watch_for = "EntityHealed"
+            priority = 20
+            logic_file = "scripts/entity_heal.lua"
+        "#;
+        let system = System::try_from(toml::from_str::<RawSystemFile>(toml_str).unwrap()).unwrap();
+        let transpiled = system.transpile().unwrap();
+        let transpiled = format_rust_code(transpiled).unwrap();
+        println!("{}", transpiled);
+        assert!(transpiled.contains("pub fn health_system("));
+        assert!(transpiled.contains("mut event_handler_1: EventReader<EntityHealed>"));
+        // update 只处理指定的查询，事件处理器对每个查询分别执行
+        assert!(transpiled.contains("for queried in [&aethaum_queried[1usize]]"));
+        assert!(transpiled.contains("for queried in aethaum_queried.iter()"));
+        assert!(transpiled.contains("(Option<&mut Health>, Option<&mut Position>, Option<&mut Damage>)"));
+        assert!(transpiled.contains("mut update_timer: Local<IntervalTimer>"));
+        assert!(transpiled.contains("::std::time::Duration::from_nanos(100000000u64)"));
+        assert!(transpiled.contains("update_condition.call::<bool>(())?"));
+        // 脚本按 (系统, 位置) 编译一次并缓存
+        assert!(transpiled.contains("ScriptKey::new(\"HealthSystem\", ScriptSlot::UpdateCondition)"));
+        assert!(transpiled.contains("ScriptKey::new(\"HealthSystem\", ScriptSlot::EventHandler(1usize))"));
+        assert!(transpiled.contains("ScriptSource::File(::std::path::Path::new(\"scripts/entity_heal.lua\"))"));
+        // EntityHealed 的处理器优先级更高，应当先执行
+        assert!(transpiled.find("event_handler_1.read()").unwrap() < transpiled.find("event_handler_0.read()").unwrap());
+        // 脚本通过 aethaum 全局表访问 Commands、事件与查询
+        assert!(transpiled.contains("globals.set(\"aethaum\", aethaum_api)?;"));
+        assert!(transpiled.contains("crate::emit_event("));
+        assert!(transpiled.contains("aethaum_queries.set(\"damaged_entities\", entities)?;"));
+        assert!(transpiled.contains("\"health\" | \"position\" | \"damage\""));
+        assert!(transpiled.contains("target.set_if_neq(value);"));
+        // 每次调用前重置指令预算，错误标明系统与处理器
+        assert!(transpiled.contains("reset_instruction_budget(&lua);"));
+        assert!(transpiled.contains("ScriptError::new(\"HealthSystem\", handler, error)"));
+        assert!(transpiled.contains("\"event_handlers[1]\","));
+        let registration = transpile_system_registration(&system, "explore").to_string();
+        assert!(registration.contains("AethaumSystemSet :: new (100u32 , \"explore::HealthSystem\")"));
+        let parsed_result = syn::parse_str::<syn::File>(&transpiled);
+        assert!(parsed_result.is_ok(), "Generated code has syntax errors: {:?}", parsed_result.err());
+
+        let unknown = toml_str.replace(r#"query = "damaged_entities""#, r#"query = "dead_entities""#);
+        let system = System::try_from(toml::from_str::<RawSystemFile>(&unknown).unwrap()).unwrap();
+        assert!(matches!(system.transpile(), Err(TranspileError::UnknownQuery(..))));
+    }
+    #[test]
+    fn test_transpile_batch_system() {
+        let toml_str = r#"
+            event_handlers = []
+
+            [normal]
+            name = "DriftSystem"
+
+            [[queries]]
+            name = "drifting"
+            components_include = ["Position"]
+
+            [update]
+            interval = 0.5
+            mode = "batch"
+            logic = "for _, view in ipairs(entities) do view.position.x = view.position.x + 1 end"
+        "#;
+        let system = System::try_from(toml::from_str::<RawSystemFile>(toml_str).unwrap()).unwrap();
+        assert_eq!(system.update.as_ref().unwrap().mode, UpdateMode::Batch);
+        let transpiled = format_rust_code(system.transpile().unwrap()).unwrap();
+        println!("{}", transpiled);
+        // 所有实体视图一次性传入，脚本每次更新只调用一次
+        assert!(transpiled.contains("globals.set(\"entities\", entity_views)?;"));
+        assert!(transpiled.contains("batch_views.get(&target.0)"));
+        assert!(!transpiled.contains("let current_view"));
+
+        let invalid = toml_str.replace(r#"mode = "batch""#, r#"mode = "bulk""#);
+        assert!(System::try_from(toml::from_str::<RawSystemFile>(&invalid).unwrap()).is_err());
+    }
 }
@@ -4,4 +4,54 @@ use crate::code_generator::TranspileError;
 pub fn format_rust_code(stream : TokenStream) -> Result<String, TranspileError> {
     let syntax_tree = syn::parse2::<syn::File>(stream)?;
     Ok(prettyplease::unparse(&syntax_tree))
-}
\ No newline at end of file
+}
+///将驼峰命名转换为蛇形命名，如 HealthSystem -> health_system, AIControlled -> ai_controlled
+pub fn to_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, ch) in chars.iter().enumerate() {
+        if ch.is_uppercase() {
+            let prev = i.checked_sub(1).map(|j| chars[j]);
+            let next = chars.get(i + 1);
+            let word_start = match prev {
+                Some(prev) if prev.is_lowercase() || prev.is_ascii_digit() => true,
+                Some(prev) if prev.is_uppercase() => next.is_some_and(|next| next.is_lowercase()),
+                _ => false,
+            };
+            if word_start {
+                snake.push('_');
+            }
+            snake.extend(ch.to_lowercase());
+        } else {
+            snake.push(*ch);
+        }
+    }
+    snake
+}
+///将蛇形命名转换为驼峰命名，如 combat -> Combat, status_effects -> StatusEffects
+pub fn to_pascal_case(name: &str) -> String {
+    name.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+        })
+        .collect()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_to_snake_case() {
+        assert_eq!(to_snake_case("HealthSystem"), "health_system");
+        assert_eq!(to_snake_case("Health"), "health");
+        assert_eq!(to_snake_case("AIControlled"), "ai_controlled");
+        assert_eq!(to_snake_case("already_snake"), "already_snake");
+    }
+    #[test]
+    fn test_to_pascal_case() {
+        assert_eq!(to_pascal_case("combat"), "Combat");
+        assert_eq!(to_pascal_case("status_effects"), "StatusEffects");
+        assert_eq!(to_pascal_case("Explore"), "Explore");
+    }
+}
@@ -1,15 +1,18 @@
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
+use miette::Diagnostic;
 use thiserror::Error;
 use crate::ecs::checker::context::{ModuleCheckContext, ModuleCheckTree};
-use crate::ecs::checker::cross_module::{CrossModuleCheckError, CrossModuleCheckable};
+use crate::ecs::checker::cross_module::{CrossModuleCheckError, CrossModuleCheckable, CrossModuleChecker};
 use crate::ecs::checker::in_module::{InModuleCheckError, InModuleCheckable};
-use crate::ecs::checker::type_checker::{TypeCheckError, TypeCheckable};
-use crate::ecs::module::{AethaumProject, EcsModule, EcsThingRef};
+use crate::ecs::checker::type_checker::{TypeCheckContext, TypeCheckError, TypeCheckable};
+use crate::ecs::module::{AethaumProject, CustomTypes, EcsModule, EcsThingRef};
+use crate::toml_parser::parsed::World;
+use crate::toml_parser::source::SourceLocation;
 
-mod context;
-mod type_checker;
-mod in_module;
-mod cross_module;
+pub mod context;
+pub mod type_checker;
+pub mod in_module;
+pub mod cross_module;
 
 #[derive(Debug)]
 pub enum CheckStage {
@@ -18,22 +21,36 @@ pub enum CheckStage {
     CrossModule
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Error, Diagnostic)]
 pub enum CheckerError {
     #[error("Type Error: {0}")]
-    Type(#[from] TypeCheckError),
+    #[diagnostic(transparent)]
+    Type(TypeCheckError),
     #[error("Unfiltered In Module Check Error: {0}")]
     InModule(InModuleCheckError, Box<ModuleCheckContext>),
     #[error("In Module Check Error: {0}")]
+    #[diagnostic(transparent)]
     FilteredInModule(InModuleCheckError),
     #[error("Cross Module Check Error: {0}")]
-    CrossModule(#[from] CrossModuleCheckError),
-    #[error("Multiple errors occurred during checking:\n{}",
-        .errors.iter().map(|e| format!("  - {}", e)).collect::<Vec<_>>().join("\n"))]
+    #[diagnostic(transparent)]
+    CrossModule(CrossModuleCheckError),
+    #[error("{} errors occurred during checking", .errors.len())]
     Multiple {
+        #[related]
         errors: Vec<CheckerError>,
     }
 }
+//不作为 source 传递，内部错误已经包含在信息中，避免报告中重复出现
+impl From<TypeCheckError> for CheckerError {
+    fn from(error: TypeCheckError) -> Self {
+        CheckerError::Type(error)
+    }
+}
+impl From<CrossModuleCheckError> for CheckerError {
+    fn from(error: CrossModuleCheckError) -> Self {
+        CheckerError::CrossModule(error)
+    }
+}
 impl CheckerError {
     pub fn raise_multiple(errors: Vec<CheckerError>) -> Self {
         Self::Multiple {
@@ -41,6 +58,8 @@ impl CheckerError {
         }
     }
 }
+///需要在跨模块阶段检查的引用，以及引用所在的位置
+type PropagatedCheck = (EcsThingRef, Option<SourceLocation>);
 pub struct CheckedEcs {
     modules: Vec<EcsModule>,
 }
@@ -54,17 +73,22 @@ where
         }
     }
 }
+impl CheckedEcs {
+    pub fn get_modules(&self) -> &[EcsModule] {
+        &self.modules
+    }
+}
 
 pub struct SingleEcsModuleChecker;
 
 
 impl SingleEcsModuleChecker {
-    pub fn run_checks(module: &EcsModule, project_root: PathBuf) -> Result<ModuleCheckContext, CheckerError> {
+    pub fn run_checks(module: &EcsModule, project_root: PathBuf, type_context: &TypeCheckContext) -> Result<ModuleCheckContext, CheckerError> {
         // 按阶段执行检查
         // 1. 类型检查
         // 2. 模块内检查
         let mut module_check_context = ModuleCheckContext::new(module.name.clone(), project_root);
-        module.check_type()?;
+        module.check_type(type_context)?;
         let in_module_check_res = module.check_in_module(&mut module_check_context);
         if let Err(err) = in_module_check_res {
             return Err(CheckerError::InModule(err, Box::new(module_check_context)));
@@ -74,13 +98,15 @@ impl SingleEcsModuleChecker {
 }
 pub struct CrossEcsModuleChecker;
 impl CrossEcsModuleChecker {
-    pub fn run_checks<'a>(ref_to_check: impl IntoIterator<Item = &'a EcsThingRef>, module_tree: &ModuleCheckTree) -> Result<(), CrossModuleCheckError> {
+    ///location 为引用所在的位置，用于错误定位
+    pub fn run_checks<'a>(ref_to_check: impl IntoIterator<Item = (&'a EcsThingRef, Option<&'a SourceLocation>)>, module_tree: &ModuleCheckTree) -> Result<(), CrossModuleCheckError> {
         //3.模块间检查
         let mut errors = Vec::new();
-        for thing_ref in ref_to_check {
-            match EcsThingRef::check_cross_module(thing_ref, module_tree) {
-                Ok(_) => {}
-                Err(e) => errors.push(e),
+        for (thing_ref, location) in ref_to_check {
+            match (EcsThingRef::check_cross_module(thing_ref, module_tree), location) {
+                (Ok(_), _) => {}
+                (Err(e), Some(location)) => errors.push(e.located(location)),
+                (Err(e), None) => errors.push(e),
             }
         }
         if errors.is_empty() {
@@ -94,22 +120,46 @@ impl CrossEcsModuleChecker {
 }
 pub struct AethaumChecker;
 impl AethaumChecker {
-    pub fn run_check(project: AethaumProject) -> Result<AethaumProject, CheckerError> {
+    ///检查通过后展开原型继承，返回的项目中原型不再包含 extends
+    pub fn run_check(mut project: AethaumProject) -> Result<AethaumProject, CheckerError> {
         let mut errors = Vec::new();
         let mut module_contexts = Vec::new();
-        for module in project.module_tree.get_modules() {
-            match SingleEcsModuleChecker::run_checks(module,project.root.clone()) {
+        let modules = project.module_tree.get_modules();
+        //自定义类型可以跨模块引用，类型检查需要看到整个项目的类型
+        let mut type_context = TypeCheckContext::new("", CustomTypes::default().with_modules(modules.iter().copied()));
+        for module in modules {
+            type_context.enter_module(module.name.as_str());
+            match SingleEcsModuleChecker::run_checks(module, project.root.clone(), &type_context) {
                 Ok(module_context) => module_contexts.push(module_context),
                 Err(err) => errors.push(err),
             }
         }
-        let (filtered_module_contexts, propagated_checks) = Self::extract_propagated_check(errors)?;
+        let (filtered_module_contexts, mut propagated_checks) = Self::extract_propagated_check(errors)?;
+        propagated_checks.extend(Self::environment_refs(&project.world));
         module_contexts.extend(filtered_module_contexts);
         let module_tree = ModuleCheckTree::new().with_module_contexts(module_contexts);
-        CrossEcsModuleChecker::run_checks(propagated_checks.iter(), &module_tree)?;
+        CrossEcsModuleChecker::run_checks(
+            propagated_checks.iter().map(|(thing_ref, location)| (thing_ref, location.as_ref())),
+            &module_tree
+        )?;
+        CrossModuleChecker::check_proto_inheritance(&project.module_tree)?;
+        project.module_tree.flatten_entity_protos();
         Ok(project)
     }
-    fn extract_propagated_check(errors: Vec<CheckerError>) -> Result<(Vec<ModuleCheckContext>, Vec<EcsThingRef>), CheckerError> {
+    ///[environment] 不属于任何模块，其中的原型与组件引用都需要带模块限定，在跨模块阶段检查
+    fn environment_refs(world: &World) -> Vec<PropagatedCheck> {
+        let Some(environment) = &world.environment else {
+            return Vec::new();
+        };
+        let protos = environment.scene.iter()
+            .map(|spawn| (EcsThingRef::from(spawn.prototype.clone()), Some(spawn.location.clone())));
+        let components = environment.queries.iter().flat_map(|query| {
+            query.component_constraint.chained_iter()
+                .map(|component_ref| (EcsThingRef::from(component_ref.clone()), Some(query.location.clone())))
+        });
+        protos.chain(components).collect()
+    }
+    fn extract_propagated_check(errors: Vec<CheckerError>) -> Result<(Vec<ModuleCheckContext>, Vec<PropagatedCheck>), CheckerError> {
         let mut true_errors = Vec::new();
         let mut module_contexts = Vec::new();
         let mut propagated_checks = Vec::new();
@@ -118,15 +168,15 @@ impl AethaumChecker {
                 CheckerError::InModule(err, module_context) => {
                     module_contexts.push(*module_context);
                     match err {
-                        InModuleCheckError::PropagateToCrossCheck {thing_ref} => {
-                            propagated_checks.push(thing_ref);
+                        InModuleCheckError::PropagateToCrossCheck {thing_ref, location} => {
+                            propagated_checks.push((thing_ref, location));
 
                         },
                         InModuleCheckError::Multiple { errors} => {
                             for err in errors {
                                 match err {
-                                    InModuleCheckError::PropagateToCrossCheck {thing_ref} => {
-                                        propagated_checks.push(thing_ref);
+                                    InModuleCheckError::PropagateToCrossCheck {thing_ref, location} => {
+                                        propagated_checks.push((thing_ref, location));
                                     },
                                     _ => true_errors.push(CheckerError::FilteredInModule(err)),
                                 }
@@ -135,6 +185,9 @@ impl AethaumChecker {
                         _ => true_errors.push(CheckerError::FilteredInModule(err)),
                     }
                 }
+                CheckerError::Type(TypeCheckError::Multiple { errors }) => {
+                    true_errors.extend(errors.into_iter().map(CheckerError::Type))
+                }
                 _ => true_errors.push(error),
             }
         }
@@ -153,7 +206,236 @@ mod tests {
     use super::*;
     #[test]
     fn test_project_checker() {
-        let project = ProjectLoader::new(r#"D:\Aethaum\test_project"#.into()).load().unwrap();
+        let project = ProjectLoader::new(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_project"))).load().unwrap();
         AethaumChecker::run_check(project).unwrap();
     }
-}
\ No newline at end of file
+    ///收集诊断树中的叶子诊断，叶子诊断都应带有源码与标签
+    fn collect_leaves(diagnostic: &dyn Diagnostic, leaves: &mut Vec<String>) {
+        match diagnostic.related() {
+            Some(related) => related.for_each(|diagnostic| collect_leaves(diagnostic, leaves)),
+            None => {
+                assert!(diagnostic.source_code().is_some(), "{} has no source code", diagnostic);
+                assert_eq!(diagnostic.labels().map(|labels| labels.count()), Some(1));
+                leaves.push(diagnostic.to_string());
+            }
+        }
+    }
+    #[test]
+    fn test_checker_error_location() {
+        let root = std::env::temp_dir().join("aethaum_test_checker_error_location");
+        let _ = std::fs::remove_dir_all(&root);
+        let write = |path: &str, content: &str| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("world.toml", r#"
+[world]
+name = "Broken"
+version = "0.1.0"
+author = "test"
+
+[modules]
+combat = "modules/combat"
+explore = "modules/explore"
+"#);
+        write("modules/combat/components/health.toml", r#"[[components]]
+name = "Health"
+
+[[components.fields]]
+name = "value"
+type = "float"
+default = "full"
+"#);
+        write("modules/explore/entity_protos/protos.toml", r#"[[entity_protos]]
+name = "Player"
+components = ["combat::Health"]
+
+[[entity_protos]]
+name = "Enemy"
+components = ["Damage"]
+"#);
+        let project = ProjectLoader::new(root.clone()).load().unwrap();
+        let error = AethaumChecker::run_check(project).err().unwrap();
+        let mut leaves = Vec::new();
+        collect_leaves(&error, &mut leaves);
+        assert_eq!(leaves.len(), 2, "{:?}", leaves);
+        //错误指向出错定义的表头
+        assert!(leaves.iter().any(|e| e.contains("health.toml:4:1: Type mismatch")), "{:?}", leaves);
+        assert!(leaves.iter().any(|e| e.contains("protos.toml:5:1: 'Component \"Damage\"' is not defined")), "{:?}", leaves);
+        //跨模块引用的错误保留引用所在的位置
+        write("modules/combat/components/health.toml", "[[components]]\nname = \"Health\"\n");
+        write("modules/explore/entity_protos/protos.toml", "[[entity_protos]]\nname = \"Player\"\ncomponents = [\"combat::Mana\"]\n");
+        let project = ProjectLoader::new(root.clone()).load().unwrap();
+        let error = AethaumChecker::run_check(project).err().unwrap();
+        assert!(matches!(error, CheckerError::CrossModule(CrossModuleCheckError::Located { .. })), "{}", error);
+        assert!(error.to_string().contains("protos.toml:1:1"), "{}", error);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+    #[test]
+    fn test_checker_unknown_query() {
+        let root = std::env::temp_dir().join("aethaum_test_checker_unknown_query");
+        let _ = std::fs::remove_dir_all(&root);
+        let write = |path: &str, content: &str| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("world.toml", r#"
+[world]
+name = "Queries"
+version = "0.1.0"
+author = "test"
+
+[modules]
+combat = "modules/combat"
+"#);
+        write("modules/combat/components/health.toml", "[[components]]\nname = \"Health\"\n");
+        write("modules/combat/events/hit.toml", "[[events]]\nname = \"Hit\"\n");
+        write("modules/combat/systems/health.toml", r#"[normal]
+name = "HealthSystem"
+
+[[queries]]
+name = "living"
+components_include = ["Health"]
+
+[update]
+interval = 1
+query = "living"
+logic = "entity.health.value = 0"
+
+[[event_handlers]]
+watch_for = "Hit"
+query = "dead"
+logic = "entity.health.value = 0"
+"#);
+        let project = ProjectLoader::new(root.clone()).load().unwrap();
+        let error = AethaumChecker::run_check(project).err().unwrap();
+        let mut leaves = Vec::new();
+        collect_leaves(&error, &mut leaves);
+        assert_eq!(leaves.len(), 1, "{:?}", leaves);
+        assert!(leaves[0].contains("health.toml:13:1: Query 'dead' is not defined in system 'HealthSystem'"), "{:?}", leaves);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+    #[test]
+    fn test_checker_environment_refs() {
+        let root = std::env::temp_dir().join("aethaum_test_checker_environment_refs");
+        let _ = std::fs::remove_dir_all(&root);
+        let write = |path: &str, content: &str| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("world.toml", r#"
+[world]
+name = "Environment"
+version = "0.1.0"
+author = "test"
+
+[modules]
+combat = "modules/combat"
+
+[[environment.scene]]
+prototype = "combat::Ghost"
+
+[[environment.queries]]
+name = "living"
+components_include = ["Health"]
+"#);
+        write("modules/combat/components/health.toml", "[[components]]\nname = \"Health\"\n");
+        let project = ProjectLoader::new(root.clone()).load().unwrap();
+        let error = AethaumChecker::run_check(project).err().unwrap();
+        let mut leaves = Vec::new();
+        collect_leaves(&error, &mut leaves);
+        assert_eq!(leaves.len(), 2, "{:?}", leaves);
+        assert!(leaves.iter().any(|e| e.contains("world.toml:10:1: 'Entity Proto \"combat::Ghost\"' is not defined")), "{:?}", leaves);
+        //world.toml 不属于任何模块，组件引用必须带模块限定
+        assert!(leaves.iter().any(|e| e.contains("world.toml:13:1: Unexpected Module name missing")), "{:?}", leaves);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+    #[test]
+    fn test_checker_proto_inheritance() {
+        let root = std::env::temp_dir().join("aethaum_test_checker_proto_inheritance");
+        let _ = std::fs::remove_dir_all(&root);
+        let write = |path: &str, content: &str| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("world.toml", r#"
+[world]
+name = "Inheritance"
+version = "0.1.0"
+author = "test"
+
+[modules]
+combat = "modules/combat"
+explore = "modules/explore"
+"#);
+        write("modules/combat/components/health.toml", r#"[[components]]
+name = "Health"
+
+[[components.fields]]
+name = "value"
+type = "float"
+default = 100.0
+
+[[components.fields]]
+name = "max_value"
+type = "float"
+default = 100.0
+
+[[components]]
+name = "Armor"
+"#);
+        write("modules/combat/entity_protos/enemy.toml", r#"[[entity_protos]]
+name = "Enemy"
+components = [{ ref = "Health", value = 50.0, max_value = 50.0 }, "Armor"]
+"#);
+        write("modules/explore/components/position.toml", "[[components]]\nname = \"Position\"\n");
+        write("modules/explore/entity_protos/elite.toml", r#"[[entity_protos]]
+name = "EliteEnemy"
+extends = "combat::Enemy"
+components = [{ ref = "combat::Health", value = 200.0 }, "Position"]
+"#);
+        let project = ProjectLoader::new(root.clone()).load().unwrap();
+        let project = AethaumChecker::run_check(project).unwrap();
+        let explore = project.module_tree.get_module("explore").unwrap();
+        let elite = &explore.entity_protos.as_ref().unwrap()[0];
+        assert!(elite.extends.is_none());
+        let components = elite.components.iter().map(|component| component.component_ref.to_string()).collect::<Vec<_>>();
+        assert_eq!(components, vec!["combat::Health", "combat::Armor", "explore::Position"]);
+        //子原型的覆盖值优先，未覆盖的字段保留父原型的值
+        let health = &elite.components[0].overrides;
+        assert_eq!(health["value"].as_float(), Some(200.0));
+        assert_eq!(health["max_value"].as_float(), Some(50.0));
+
+        //未知的父原型
+        write("modules/explore/entity_protos/elite.toml", "[[entity_protos]]\nname = \"EliteEnemy\"\nextends = \"Boss\"\n");
+        let project = ProjectLoader::new(root.clone()).load().unwrap();
+        let error = AethaumChecker::run_check(project).err().unwrap();
+        assert!(error.to_string().contains("elite.toml:1:1"), "{}", error);
+        assert!(error.to_string().contains("\"Boss\""), "{}", error);
+
+        //继承循环，循环上的每个原型都会报告
+        write("modules/explore/entity_protos/elite.toml", r#"[[entity_protos]]
+name = "A"
+extends = "B"
+
+[[entity_protos]]
+name = "B"
+extends = "A"
+
+[[entity_protos]]
+name = "C"
+extends = "A"
+"#);
+        let project = ProjectLoader::new(root.clone()).load().unwrap();
+        let error = AethaumChecker::run_check(project).err().unwrap();
+        let mut leaves = Vec::new();
+        collect_leaves(&error, &mut leaves);
+        assert_eq!(leaves.len(), 2, "{:?}", leaves);
+        assert!(leaves.iter().any(|e| e.contains("Prototype 'explore::A' inherits from itself: explore::A -> explore::B -> explore::A")), "{:?}", leaves);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
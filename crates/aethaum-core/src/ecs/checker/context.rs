@@ -1,14 +1,17 @@
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use smart_string::SmartString;
-use crate::toml_parser::parsed::{AethaumType, ComponentRef, EntityProtoRef, EventRef, PrimitiveType, SystemRef};
-use anyhow::Result;
-use crate::ecs::module::EcsModule;
+use crate::toml_parser::parsed::{ComponentRef, EntityProtoRef, EventRef, SystemRef};
 
 pub struct ModuleCheckTree {
     modules: HashMap<String, ModuleCheckContext> // 模块名 -> 模块上下文
     //目前，模块是扁平的结构，暂时不需要树结构
 }
+impl Default for ModuleCheckTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl ModuleCheckTree {
     pub fn new() -> Self {
         Self {
@@ -1,10 +1,13 @@
 use smart_string::SmartString;
+use miette::{Diagnostic, SourceSpan};
 use thiserror::Error;
 use crate::ecs::checker::context::{ModuleCheckContext, ModuleCheckTree};
-use crate::ecs::module::{EcsThingRef, ModulePath};
-use crate::toml_parser::parsed::{ComponentRef, EntityProto, EntityProtoRef, EventRef, SystemEventHandler, SystemQuery, SystemRef};
+use itertools::Itertools;
+use crate::ecs::module::{EcsModuleTree, EcsThingRef};
+use crate::toml_parser::parsed::EntityProtoRef;
+use crate::toml_parser::source::{display_location, Locatable, SourceFile, SourceLocation};
 
-#[derive(Debug,Error)]
+#[derive(Debug,Error,Diagnostic)]
 pub enum CrossModuleCheckError {
     #[error("Module '{module_name} has multiple definition.'")]
     MultiDefinition {
@@ -22,9 +25,22 @@ pub enum CrossModuleCheckError {
     UnexpectedModuleNameMissing {
         thing_ref: EcsThingRef,
     },
-    #[error("Multiple errors occurred during checking:\n{}",
-        .errors.iter().map(|e| format!("  - {}", e)).collect::<Vec<_>>().join("\n"))]
+    #[error("Prototype '{proto}' inherits from itself: {}", .chain.iter().join(" -> "))]
+    InheritanceCycle {
+        proto: EntityProtoRef,
+        chain: Vec<EntityProtoRef>,
+    },
+    #[error("{}: {error}", display_location(source_code, span))]
+    Located {
+        error: Box<CrossModuleCheckError>,
+        #[source_code]
+        source_code: SourceFile,
+        #[label]
+        span: SourceSpan,
+    },
+    #[error("{} errors occurred during checking", .errors.len())]
     Multiple {
+        #[related]
         errors: Vec<CrossModuleCheckError>,
     }
 
@@ -42,9 +58,26 @@ impl CrossModuleCheckError {
     pub fn raise_unexpected_module_name_missing(thing_ref: EcsThingRef) -> Self {
         Self::UnexpectedModuleNameMissing { thing_ref }
     }
+    pub fn raise_inheritance_cycle(proto: EntityProtoRef, chain: Vec<EntityProtoRef>) -> Self {
+        Self::InheritanceCycle { proto, chain }
+    }
     pub fn raise_multiple(errors: Vec<CrossModuleCheckError>) -> Self {
         Self::Multiple { errors }
     }
+    ///为错误附加源码位置，已有位置的错误保持不变
+    pub fn located(self, location: &SourceLocation) -> Self {
+        match self {
+            Self::Multiple { errors } => Self::raise_multiple(
+                errors.into_iter().map(|error| error.located(location)).collect()
+            ),
+            error @ Self::Located { .. } => error,
+            error => Self::Located {
+                error: Box::new(error),
+                source_code: location.source.clone(),
+                span: location.span,
+            }
+        }
+    }
 }
 
 pub struct CrossModuleChecker;
@@ -60,6 +93,27 @@ impl CrossModuleChecker {
         tree.get_tree_mut().insert(String::from(name), context);
         Ok(())
     }
+    ///检查原型继承中的循环，父原型的引用应当已经通过检查；只报告位于循环上的原型
+    pub fn check_proto_inheritance(module_tree: &EcsModuleTree) -> Result<(), CrossModuleCheckError> {
+        let mut modules = module_tree.get_modules();
+        modules.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut errors = Vec::new();
+        for module in modules {
+            for proto in module.entity_protos.iter().flatten() {
+                let chain = module_tree.entity_proto_ancestors(module.name.as_str(), proto);
+                if chain.len() > 1 && chain.first() == chain.last() {
+                    errors.push(
+                        CrossModuleCheckError::raise_inheritance_cycle(chain[0].clone(), chain).located(proto.location())
+                    );
+                }
+            }
+        }
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.pop().unwrap()),
+            _ => Err(CrossModuleCheckError::raise_multiple(errors)),
+        }
+    }
     ///检查跨模块引用，应当在所有module都被注册完后调用
     pub fn check_cross_module_ref(thing_ref: &EcsThingRef, tree: &ModuleCheckTree) -> Result<(), CrossModuleCheckError> {
         if thing_ref.module_name().is_empty() {
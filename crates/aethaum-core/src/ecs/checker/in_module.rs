@@ -1,10 +1,13 @@
 use std::path::PathBuf;
+use smart_string::SmartString;
+use miette::{Diagnostic, SourceSpan};
 use thiserror::Error;
 use crate::ecs::checker::context::{ModuleCheckContext};
 use crate::ecs::module::{EcsModule, EcsThingRef};
 use crate::toml_parser::parsed::{ComponentRef, EntityProto, EntityProtoRef, EventRef, LuaScript, System, SystemEventHandler, SystemQuery, SystemRef};
+use crate::toml_parser::source::{display_location, Locatable, SourceFile, SourceLocation};
 
-#[derive(Debug,Error)]
+#[derive(Debug,Error,Diagnostic)]
 pub enum InModuleCheckError {
     #[error("'{}' is already defined.",thing_ref.as_error_str())]
     AlreadyDefined {
@@ -21,12 +24,23 @@ pub enum InModuleCheckError {
     #[error("'{thing_ref}' should be checked in Cross Module Check stage.")]
     PropagateToCrossCheck {
         thing_ref: EcsThingRef,
+        location: Option<SourceLocation>, //引用所在的位置，跨模块检查出错时使用
     },
     #[error("Lua script '{0}' not found in system '{1}'.")]
     LuaScriptNotFound(PathBuf, SystemRef),
-    #[error("Multiple errors occurred during checking:\n{}",
-        .errors.iter().map(|e| format!("  - {}", e)).collect::<Vec<_>>().join("\n"))]
+    #[error("Query '{0}' is not defined in system '{1}'.")]
+    QueryNotDefined(SmartString, SystemRef),
+    #[error("{}: {error}", display_location(source_code, span))]
+    Located {
+        error: Box<InModuleCheckError>,
+        #[source_code]
+        source_code: SourceFile,
+        #[label]
+        span: SourceSpan,
+    },
+    #[error("{} errors occurred during checking", .errors.len())]
     Multiple {
+        #[related]
         errors: Vec<InModuleCheckError>,
     }
 }
@@ -41,14 +55,35 @@ impl InModuleCheckError {
         Self::NotDefined { thing_ref }
     }
     pub fn raise_propagate_to_cross_check(thing_ref: EcsThingRef) -> Self {
-        Self::PropagateToCrossCheck { thing_ref }
+        Self::PropagateToCrossCheck { thing_ref, location: None }
     }
     pub fn raise_lua_script_not_found(lua_script: PathBuf, system_ref: SystemRef) -> Self {
         Self::LuaScriptNotFound(lua_script, system_ref)
     }
+    pub fn raise_query_not_defined(query_name: SmartString, system_ref: SystemRef) -> Self {
+        Self::QueryNotDefined(query_name, system_ref)
+    }
     pub fn raise_multiple(errors: Vec<Self>) -> Self {
         Self::Multiple { errors }
     }
+    ///为错误附加源码位置，已有位置的错误保持不变；待跨模块检查的引用只记录位置
+    pub fn located(self, location: &SourceLocation) -> Self {
+        match self {
+            Self::Multiple { errors } => Self::raise_multiple(
+                errors.into_iter().map(|error| error.located(location)).collect()
+            ),
+            Self::PropagateToCrossCheck { thing_ref, location: None } => Self::PropagateToCrossCheck {
+                thing_ref,
+                location: Some(location.clone()),
+            },
+            error @ (Self::Located { .. } | Self::PropagateToCrossCheck { .. }) => error,
+            error => Self::Located {
+                error: Box::new(error),
+                source_code: location.source.clone(),
+                span: location.span,
+            }
+        }
+    }
     pub fn need_cross_module_check(&self) -> bool {
         matches!(self, InModuleCheckError::PropagateToCrossCheck { .. })
     }
@@ -60,13 +95,12 @@ impl InModuleChecker {
     pub fn try_register(thing: EcsThingRef, module_context: &mut ModuleCheckContext) -> Result<(),InModuleCheckError> {
         match thing {
             EcsThingRef::Component(component_ref) => {
-                if let Some(module_name) = component_ref.module_name.as_ref() {
-                    if module_name.as_str() != module_context.name.as_str() {
-                        //TODO: remove the clone
-                        return Err(
-                            InModuleCheckError::raise_define_external(EcsThingRef::Component(component_ref.clone()))
-                        );
-                    }
+                if let Some(module_name) = component_ref.module_name.as_ref()
+                    && module_name.as_str() != module_context.name.as_str() {
+                    //TODO: remove the clone
+                    return Err(
+                        InModuleCheckError::raise_define_external(EcsThingRef::Component(component_ref.clone()))
+                    );
                 }
                 if module_context.defined_components.contains(&component_ref) {
                     return Err(
@@ -77,13 +111,12 @@ impl InModuleChecker {
                 Ok(())
             },
             EcsThingRef::Event(event_ref) => {
-                if let Some(module_name) = event_ref.module_name.as_ref() {
-                    if module_name.as_str() != module_context.name.as_str() {
-                        //TODO: remove the clone
-                        return Err(
-                            InModuleCheckError::raise_define_external(EcsThingRef::Event(event_ref.clone()))
-                        );
-                    }
+                if let Some(module_name) = event_ref.module_name.as_ref()
+                    && module_name.as_str() != module_context.name.as_str() {
+                    //TODO: remove the clone
+                    return Err(
+                        InModuleCheckError::raise_define_external(EcsThingRef::Event(event_ref.clone()))
+                    );
                 }
                 if module_context.defined_events.contains(&event_ref) {
                     return Err(
@@ -94,13 +127,12 @@ impl InModuleChecker {
                 Ok(())
             },
             EcsThingRef::EntityProto(entity_proto_ref) => {
-                if let Some(module_name) = entity_proto_ref.module_name.as_ref() {
-                    if module_name.as_str() != module_context.name.as_str() {
-                        //TODO: remove the clone
-                        return Err(
-                            InModuleCheckError::raise_define_external(EcsThingRef::EntityProto(entity_proto_ref.clone()))
-                        );
-                    }
+                if let Some(module_name) = entity_proto_ref.module_name.as_ref()
+                    && module_name.as_str() != module_context.name.as_str() {
+                    //TODO: remove the clone
+                    return Err(
+                        InModuleCheckError::raise_define_external(EcsThingRef::EntityProto(entity_proto_ref.clone()))
+                    );
                 }
                 if module_context.defined_entity_protos.contains(&entity_proto_ref) {
                     return Err(
@@ -111,13 +143,12 @@ impl InModuleChecker {
                 Ok(())
             },
             EcsThingRef::System(system_ref) => {
-                if let Some(module_name) = system_ref.module_name.as_ref() {
-                    if module_name.as_str() != module_context.name.as_str() {
-                        //TODO: remove the clone
-                        return Err(
-                            InModuleCheckError::raise_define_external(EcsThingRef::System(system_ref.clone()))
-                        );
-                    }
+                if let Some(module_name) = system_ref.module_name.as_ref()
+                    && module_name.as_str() != module_context.name.as_str() {
+                    //TODO: remove the clone
+                    return Err(
+                        InModuleCheckError::raise_define_external(EcsThingRef::System(system_ref.clone()))
+                    );
                 }
                 if module_context.defined_systems.contains(&system_ref) {
                     return Err(
@@ -201,7 +232,7 @@ impl InModuleChecker {
     }
 }
 pub trait InModuleCheckable {
-    fn check_in_module(&self, module_context: &mut ModuleCheckContext) -> Result<(), InModuleCheckError> {
+    fn check_in_module(&self, _module_context: &mut ModuleCheckContext) -> Result<(), InModuleCheckError> {
         Ok(())
     }
 }
@@ -222,9 +253,9 @@ impl InModuleCheckable for SystemQuery {
 
         if !errors.is_empty() {
             if errors.len() == 1 {
-                return Err(errors.pop().unwrap());
+                return Err(errors.pop().unwrap().located(self.location()));
             }
-            return Err(InModuleCheckError::raise_multiple(errors));
+            return Err(InModuleCheckError::raise_multiple(errors).located(self.location()));
         }
 
         Ok(())
@@ -233,6 +264,7 @@ impl InModuleCheckable for SystemQuery {
 impl InModuleCheckable for SystemEventHandler {
     fn check_in_module(&self, module_context: &mut ModuleCheckContext) -> Result<(), InModuleCheckError> {
         InModuleChecker::check_in_module_event_ref(&self.watch_for, module_context)
+            .map_err(|e| e.located(self.location()))
     }
 }
 impl InModuleCheckable for System {
@@ -260,33 +292,44 @@ impl InModuleCheckable for System {
                 }
             }
         }
-        if let Some(updates) = &self.update {
-            if let Some(condition_path) = &updates.condition {
-                if let LuaScript::File(path) = condition_path {
-                    let path = module_context.project_root.join(path);
-                    if !path.exists() {
-                       errors.push(
-                            InModuleCheckError::raise_lua_script_not_found(
-                                path,
-                                SystemRef::new(Some(module_context.name.clone()),self.normal.name.clone())
-                            )
-                       )
-                    }
-                }
+        // update 与事件处理器指定的查询必须是本系统声明过的查询
+        let selected_queries = self.update.iter().map(|update| (&update.query, update.location()))
+            .chain(self.event_handlers.iter().map(|handler| (&handler.query, handler.location())));
+        for (query_name, location) in selected_queries {
+            if let Some(query_name) = query_name
+                && !self.queries.iter().any(|query| &query.name == query_name) {
+                errors.push(
+                    InModuleCheckError::raise_query_not_defined(
+                        query_name.clone(),
+                        SystemRef::new(Some(module_context.name.clone()),self.normal.name.clone())
+                    ).located(location)
+                )
+            }
+        }
+        if let Some(updates) = &self.update
+            && let Some(condition_path) = &updates.condition
+            && let LuaScript::File(path) = condition_path {
+            let path = module_context.project_root.join(path);
+            if !path.exists() {
+                errors.push(
+                    InModuleCheckError::raise_lua_script_not_found(
+                        path,
+                        SystemRef::new(Some(module_context.name.clone()),self.normal.name.clone())
+                    ).located(updates.location())
+                )
             }
         } //TODO: reduce the clones
         for handler in self.event_handlers.iter() {
-            if let Some(script) = &handler.logic {
-                if let LuaScript::File(path) = &script {
-                    let path = module_context.project_root.join(path);
-                    if !path.exists() {
-                        errors.push(
-                            InModuleCheckError::raise_lua_script_not_found(
-                                path,
-                                SystemRef::new(Some(module_context.name.clone()),self.normal.name.clone())
-                            )
-                        )
-                    }
+            if let Some(script) = &handler.logic
+                && let LuaScript::File(path) = &script {
+                let path = module_context.project_root.join(path);
+                if !path.exists() {
+                    errors.push(
+                        InModuleCheckError::raise_lua_script_not_found(
+                            path,
+                            SystemRef::new(Some(module_context.name.clone()),self.normal.name.clone())
+                        ).located(handler.location())
+                    )
                 }
             }
         }
@@ -304,8 +347,12 @@ impl InModuleCheckable for EntityProto {
     fn check_in_module(&self, module_context: &mut ModuleCheckContext) -> Result<(), InModuleCheckError> {
         let mut errors = Vec::new();
 
-        for component_ref in self.components.iter() {
-            if let Err(e) = InModuleChecker::check_in_module_component_ref(component_ref, module_context) {
+        if let Some(parent) = &self.extends
+            && let Err(e) = InModuleChecker::check_in_module_entity_proto_ref(parent, module_context) {
+            errors.push(e);
+        }
+        for component in self.components.iter() {
+            if let Err(e) = InModuleChecker::check_in_module_component_ref(&component.component_ref, module_context) {
                 match e {
                     InModuleCheckError::Multiple { errors: inner_errors } => {
                         errors.extend(inner_errors)
@@ -317,9 +364,9 @@ impl InModuleCheckable for EntityProto {
 
         if !errors.is_empty() {
             if errors.len() == 1 {
-                return Err(errors.pop().unwrap());
+                return Err(errors.pop().unwrap().located(self.location()));
             }
-            return Err(InModuleCheckError::raise_multiple(errors));
+            return Err(InModuleCheckError::raise_multiple(errors).located(self.location()));
         }
 
         Ok(())
@@ -333,7 +380,7 @@ impl InModuleCheckable for EcsModule {
             for component in components {
                 let comp_ref = ComponentRef::from((self.name.as_str(), component.name.as_str()));
                 if let Err(e) = InModuleChecker::try_register(comp_ref.into(), module_context) {
-                    errors.push(e);
+                    errors.push(e.located(component.location()));
                 }
             }
         }
@@ -341,7 +388,7 @@ impl InModuleCheckable for EcsModule {
             for event in events {
                 let event_ref = EventRef::from((self.name.as_str(), event.name.as_str()));
                 if let Err(e) = InModuleChecker::try_register(event_ref.into(), module_context) {
-                    errors.push(e);
+                    errors.push(e.located(event.location()));
                 }
             }
         }
@@ -349,7 +396,7 @@ impl InModuleCheckable for EcsModule {
             for entity_proto in entity_protos {
                 let entity_proto_ref = EntityProtoRef::from((self.name.as_str(), entity_proto.name.as_str()));
                 if let Err(e) = InModuleChecker::try_register(entity_proto_ref.into(), module_context) {
-                    errors.push(e);
+                    errors.push(e.located(entity_proto.location()));
                 }
             }
         }
@@ -357,11 +404,10 @@ impl InModuleCheckable for EcsModule {
             for system in systems {
                 let system_ref = SystemRef::from((self.name.as_str(), system.normal.name.as_str()));
                 if let Err(e) = InModuleChecker::try_register(system_ref.into(), module_context) {
-                    errors.push(e);
+                    errors.push(e.located(system.location()));
                 }
             }
         }
-        println!(" module: {:?}", module_context);
         //ref checking
         if let Some(systems) = &self.systems {
             for system in systems {
@@ -402,9 +448,9 @@ mod tests {
     use super::*;
     #[test]
     fn test_in_module_check_pass() {
-        let module = ModuleFileLoader::new(r#"D:\Aethaum\test_project\modules\explore"#.into(), "explore".into())
+        let module = ModuleFileLoader::new(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_project/modules/explore")), "explore".into())
             .load().unwrap();
-        let mut module_context = ModuleCheckContext::new("explore".into(),r#"D:\Aethaum\test_project"#.into());
+        let mut module_context = ModuleCheckContext::new("explore".into(),PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_project")));
         let res = module.check_in_module(&mut module_context);
         assert!(res.is_err());
         if let Err(e) = res {
@@ -1,14 +1,66 @@
+use std::collections::HashSet;
+use smart_string::SmartString;
+use miette::{Diagnostic, SourceSpan};
 use thiserror::Error;
-use crate::ecs::module::EcsModule;
-use crate::toml_parser::parsed::{AethaumType, ComponentField, EventField, PrimitiveType};
+use crate::ecs::module::{CustomTypeDef, CustomTypes, EcsModule};
+use crate::toml_parser::parsed::{AethaumType, ComponentField, ComponentRef, EntityProto, Enum, EventField, PrimitiveType, Record, TypeRef};
+use crate::toml_parser::source::{display_location, Locatable, SourceFile, SourceLocation};
 
-#[derive(Debug,Error)]
+#[derive(Debug,Error,Diagnostic)]
 pub enum TypeCheckError {
     #[error("Type mismatch: expected {0}, got {1}")]
     TypeMismatch(AethaumType, toml::Value),
-    #[error("Multiple errors occurred during checking:\n{}",
-        .errors.iter().map(|e| format!("  - {}", e)).collect::<Vec<_>>().join("\n"))]
+    #[error("Integer {0} is out of range for int, expected a value between {min} and {max}", min = i32::MIN, max = i32::MAX)]
+    IntOutOfRange(i64),
+    #[error("Unknown type '{0}'")]
+    UnknownType(TypeRef),
+    #[error("'{variant}' is not a variant of enum '{enum_name}'")]
+    UnknownVariant {
+        enum_name: SmartString,
+        variant: String,
+    },
+    #[error("Enum '{0}' must have at least one variant")]
+    EmptyEnum(SmartString),
+    #[error("Variant '{variant}' of enum '{enum_name}' is not a valid identifier")]
+    InvalidVariantName {
+        enum_name: SmartString,
+        variant: SmartString,
+    },
+    #[error("Variant '{variant}' is defined more than once in enum '{enum_name}'")]
+    DuplicateVariant {
+        enum_name: SmartString,
+        variant: SmartString,
+    },
+    #[error("Record '{record_name}' has no field '{field}'")]
+    UnknownField {
+        record_name: SmartString,
+        field: String,
+    },
+    #[error("Component '{component}' has no field '{field}'")]
+    UnknownComponentField {
+        component: ComponentRef,
+        field: SmartString,
+    },
+    #[error("Field '{field}' is defined more than once in record '{record_name}'")]
+    DuplicateField {
+        record_name: SmartString,
+        field: SmartString,
+    },
+    #[error("Record '{0}' contains itself, wrap the field in a list to break the cycle")]
+    RecursiveRecord(SmartString),
+    #[error("Type '{0}' is already defined in this module")]
+    DuplicateType(SmartString),
+    #[error("{}: {error}", display_location(source_code, span))]
+    Located {
+        error: Box<TypeCheckError>,
+        #[source_code]
+        source_code: SourceFile,
+        #[label]
+        span: SourceSpan,
+    },
+    #[error("{} errors occurred during checking", .errors.len())]
     Multiple {
+        #[related]
         errors: Vec<TypeCheckError>,
     }
 }
@@ -16,54 +68,331 @@ impl TypeCheckError {
     pub fn raise_type_mismatch(expected: AethaumType, got: toml::Value) -> Self {
         Self::TypeMismatch(expected, got)
     }
+    pub fn raise_int_out_of_range(value: i64) -> Self {
+        Self::IntOutOfRange(value)
+    }
+    pub fn raise_unknown_type(type_ref: TypeRef) -> Self {
+        Self::UnknownType(type_ref)
+    }
+    pub fn raise_unknown_variant(enum_name: SmartString, variant: String) -> Self {
+        Self::UnknownVariant { enum_name, variant }
+    }
+    pub fn raise_empty_enum(enum_name: SmartString) -> Self {
+        Self::EmptyEnum(enum_name)
+    }
+    pub fn raise_invalid_variant_name(enum_name: SmartString, variant: SmartString) -> Self {
+        Self::InvalidVariantName { enum_name, variant }
+    }
+    pub fn raise_duplicate_variant(enum_name: SmartString, variant: SmartString) -> Self {
+        Self::DuplicateVariant { enum_name, variant }
+    }
+    pub fn raise_unknown_field(record_name: SmartString, field: String) -> Self {
+        Self::UnknownField { record_name, field }
+    }
+    pub fn raise_unknown_component_field(component: ComponentRef, field: SmartString) -> Self {
+        Self::UnknownComponentField { component, field }
+    }
+    pub fn raise_duplicate_field(record_name: SmartString, field: SmartString) -> Self {
+        Self::DuplicateField { record_name, field }
+    }
+    pub fn raise_recursive_record(record_name: SmartString) -> Self {
+        Self::RecursiveRecord(record_name)
+    }
+    pub fn raise_duplicate_type(type_name: SmartString) -> Self {
+        Self::DuplicateType(type_name)
+    }
     pub fn raise_multiple(errors: Vec<Self>) -> Self {
         Self::Multiple { errors }
     }
+    ///为错误附加源码位置，已有位置的错误保持不变
+    pub fn located(self, location: &SourceLocation) -> Self {
+        match self {
+            Self::Multiple { errors } => Self::raise_multiple(
+                errors.into_iter().map(|error| error.located(location)).collect()
+            ),
+            error @ Self::Located { .. } => error,
+            error => Self::Located {
+                error: Box::new(error),
+                source_code: location.source.clone(),
+                span: location.span,
+            }
+        }
+    }
+    ///去掉位置信息后的错误本身
+    pub fn unlocated(&self) -> &Self {
+        match self {
+            Self::Located { error, .. } => error.unlocated(),
+            error => error,
+        }
+    }
+    ///将收集到的错误合并为一个结果
+    fn collect(mut errors: Vec<Self>) -> Result<(), Self> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.pop().unwrap()),
+            _ => Err(Self::raise_multiple(errors)),
+        }
+    }
+}
+
+///类型检查时可见的自定义类型，以及未限定模块的类型引用所属的当前模块
+#[derive(Default)]
+pub struct TypeCheckContext<'a> {
+    module_name: &'a str,
+    types: CustomTypes<'a>,
+}
+impl<'a> TypeCheckContext<'a> {
+    pub fn new(module_name: &'a str, types: CustomTypes<'a>) -> Self {
+        Self { module_name, types }
+    }
+    pub fn from_module(module: &'a EcsModule) -> Self {
+        Self::new(module.name.as_str(), CustomTypes::default().with_module(module))
+    }
+    pub fn enter_module(&mut self, module_name: &'a str) {
+        self.module_name = module_name;
+    }
+    pub fn module_name(&self) -> &'a str {
+        self.module_name
+    }
+    fn resolve(&self, type_ref: &TypeRef, module_name: &str) -> Result<(&'a str, CustomTypeDef<'a>), TypeCheckError> {
+        self.types.resolve(type_ref, module_name)
+            .ok_or_else(|| TypeCheckError::raise_unknown_type(type_ref.clone()))
+    }
 }
 
 pub struct TypeChecker;
 impl TypeChecker {
-    pub fn check_type_value_match(type_spec: &AethaumType, default_value: &Option<toml::Value>) -> Result<(), TypeCheckError> {
+    pub fn check_type_value_match(type_spec: &AethaumType, default_value: &Option<toml::Value>, context: &TypeCheckContext) -> Result<(), TypeCheckError> {
+        Self::check_type_defined(type_spec, context)?;
+        match default_value {
+            Some(value) => Self::check_value(type_spec, value, context.module_name, context),
+            None => Ok(()),
+        }
+    }
+    ///检查类型中引用的自定义类型是否存在
+    pub fn check_type_defined(type_spec: &AethaumType, context: &TypeCheckContext) -> Result<(), TypeCheckError> {
+        match type_spec {
+            AethaumType::Primitive(_) => Ok(()),
+            AethaumType::List(element_type) => Self::check_type_defined(element_type, context),
+            AethaumType::Custom(type_ref) => context.resolve(type_ref, context.module_name).map(|_| ()),
+        }
+    }
+    ///module_name 为值所在定义的模块，记录字段中的类型引用相对于记录所在模块解析
+    fn check_value(type_spec: &AethaumType, value: &toml::Value, module_name: &str, context: &TypeCheckContext) -> Result<(), TypeCheckError> {
         match type_spec {
             AethaumType::Primitive(primitive_type) => {
-                match (primitive_type, default_value) {
-                    (PrimitiveType::Bool, Some(toml::Value::Boolean(_))) => Ok(()),
-                    (PrimitiveType::Int, Some(toml::Value::Integer(_))) => Ok(()),
-                    (PrimitiveType::Float, Some(toml::Value::Float(_))) => Ok(()),
-                    (PrimitiveType::Str, Some(toml::Value::String(_))) => Ok(()),
-                    (_, None) => Ok(()),
-                    _ => return Err(TypeCheckError::raise_type_mismatch(type_spec.clone(), default_value.as_ref().unwrap().clone())),
-                    //ROBUST: None is early returned
+                match (primitive_type, value) {
+                    (PrimitiveType::Bool, toml::Value::Boolean(_)) => Ok(()),
+                    //int 对应 i32，超出范围的整数不能静默截断
+                    (PrimitiveType::Int, toml::Value::Integer(i)) => i32::try_from(*i)
+                        .map(|_| ())
+                        .map_err(|_| TypeCheckError::raise_int_out_of_range(*i)),
+                    (PrimitiveType::Float, toml::Value::Float(_)) => Ok(()),
+                    (PrimitiveType::Str, toml::Value::String(_)) => Ok(()),
+                    //实体只在运行时产生，不能写默认值
+                    (PrimitiveType::EntityId, _) => Err(TypeCheckError::raise_type_mismatch(type_spec.clone(), value.clone())),
+                    _ => Err(TypeCheckError::raise_type_mismatch(type_spec.clone(), value.clone())),
                 }
             }
-            AethaumType::Custom(_) => unreachable!("Custom type not supported in this version")
+            AethaumType::List(element_type) => {
+                let toml::Value::Array(elements) = value else {
+                    return Err(TypeCheckError::raise_type_mismatch(type_spec.clone(), value.clone()));
+                };
+                //逐个检查数组元素，收集全部不匹配的元素
+                let errors = elements.iter()
+                    .filter_map(|element| Self::check_value(element_type, element, module_name, context).err())
+                    .collect::<Vec<_>>();
+                TypeCheckError::collect(errors)
+            }
+            AethaumType::Custom(type_ref) => {
+                let (type_module, type_def) = context.resolve(type_ref, module_name)?;
+                match (type_def, value) {
+                    //枚举默认值以变体名字符串的形式给出
+                    (CustomTypeDef::Enum(enum_def), toml::Value::String(variant)) => {
+                        if enum_def.has_variant(variant) {
+                            Ok(())
+                        } else {
+                            Err(TypeCheckError::raise_unknown_variant(enum_def.name.clone(), variant.clone()))
+                        }
+                    }
+                    //记录默认值以内联表的形式给出，缺省的字段使用记录自身的默认值
+                    (CustomTypeDef::Record(record), toml::Value::Table(table)) => {
+                        let errors = table.iter()
+                            .filter_map(|(field_name, field_value)| match record.get_field(field_name) {
+                                Some(field) => Self::check_value(&field.type_spec, field_value, type_module, context).err(),
+                                None => Some(TypeCheckError::raise_unknown_field(record.name.clone(), field_name.clone())),
+                            })
+                            .collect::<Vec<_>>();
+                        TypeCheckError::collect(errors)
+                    }
+                    _ => Err(TypeCheckError::raise_type_mismatch(type_spec.clone(), value.clone())),
+                }
+            }
+        }
+    }
+    ///记录是否（不经过列表）直接或间接包含目标记录
+    fn contains_record(record: &Record, module_name: &str, target: (&str, &str), visited: &mut HashSet<(String, String)>, context: &TypeCheckContext) -> bool {
+        for field in record.fields.iter().flatten() {
+            let AethaumType::Custom(type_ref) = &field.type_spec else {
+                continue;
+            };
+            let Ok((field_module, CustomTypeDef::Record(field_record))) = context.resolve(type_ref, module_name) else {
+                continue;
+            };
+            if (field_module, field_record.name.as_str()) == target {
+                return true;
+            }
+            if visited.insert((field_module.to_string(), field_record.name.to_string()))
+                && Self::contains_record(field_record, field_module, target, visited, context) {
+                return true;
+            }
+        }
+        false
+    }
+}
+///Rust 的关键字与保留字，不能用作生成代码中的标识符
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn", "for",
+    "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self",
+    "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "abstract", "become", "box", "do", "final", "macro", "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && name != "_" && !RUST_KEYWORDS.contains(&name)
         }
+        _ => false,
     }
 }
 pub trait TypeCheckable {
-    fn check_type(&self) -> Result<(), TypeCheckError> {
+    fn check_type(&self, _context: &TypeCheckContext) -> Result<(), TypeCheckError> {
         Ok(())
     }
 }
 impl TypeCheckable for ComponentField {
-    fn check_type(&self) -> Result<(), TypeCheckError> {
-        TypeChecker::check_type_value_match(&self.type_spec, &self.default_value)
+    fn check_type(&self, context: &TypeCheckContext) -> Result<(), TypeCheckError> {
+        TypeChecker::check_type_value_match(&self.type_spec, &self.default_value, context)
+            .map_err(|e| e.located(self.location()))
+    }
+}
+impl TypeCheckable for EventField {
+    fn check_type(&self, context: &TypeCheckContext) -> Result<(), TypeCheckError> {
+        TypeChecker::check_type_defined(&self.type_spec, context)
+            .map_err(|e| e.located(self.location()))
+    }
+}
+impl TypeCheckable for Enum {
+    fn check_type(&self, _context: &TypeCheckContext) -> Result<(), TypeCheckError> {
+        if self.variants.is_empty() {
+            return Err(TypeCheckError::raise_empty_enum(self.name.clone()).located(self.location()));
+        }
+        let mut errors = Vec::new();
+        let mut seen = HashSet::new();
+        for variant in self.variants.iter() {
+            if !is_valid_identifier(variant) {
+                errors.push(TypeCheckError::raise_invalid_variant_name(self.name.clone(), variant.clone()));
+            } else if !seen.insert(variant.as_str()) {
+                errors.push(TypeCheckError::raise_duplicate_variant(self.name.clone(), variant.clone()));
+            }
+        }
+        TypeCheckError::collect(errors).map_err(|e| e.located(self.location()))
+    }
+}
+impl TypeCheckable for Record {
+    fn check_type(&self, context: &TypeCheckContext) -> Result<(), TypeCheckError> {
+        let mut errors = Vec::new();
+        let mut seen = HashSet::new();
+        for field in self.fields.iter().flatten() {
+            if !seen.insert(field.name.as_str()) {
+                errors.push(TypeCheckError::raise_duplicate_field(self.name.clone(), field.name.clone()).located(field.location()));
+            }
+            if let Err(e) = field.check_type(context) {
+                errors.push(e);
+            }
+        }
+        let target = (context.module_name, self.name.as_str());
+        if TypeChecker::contains_record(self, context.module_name, target, &mut HashSet::new(), context) {
+            errors.push(TypeCheckError::raise_recursive_record(self.name.clone()));
+        }
+        TypeCheckError::collect(errors).map_err(|e| e.located(self.location()))
+    }
+}
+impl TypeCheckable for EntityProto {
+    ///检查覆盖值与组件字段的类型是否匹配，组件引用本身由模块内与跨模块检查负责
+    fn check_type(&self, context: &TypeCheckContext) -> Result<(), TypeCheckError> {
+        let mut errors = Vec::new();
+        for component in self.components.iter().filter(|component| !component.overrides.is_empty()) {
+            let Some((component_module, component_def)) = context.types.resolve_component(&component.component_ref, context.module_name) else {
+                continue;
+            };
+            for (field_name, value) in component.overrides.iter() {
+                match component_def.get_field(field_name) {
+                    Some(field) => if let Err(e) = TypeChecker::check_value(&field.type_spec, value, component_module, context) {
+                        errors.push(e);
+                    },
+                    None => errors.push(TypeCheckError::raise_unknown_component_field(component.component_ref.clone(), field_name.clone())),
+                }
+            }
+        }
+        TypeCheckError::collect(errors).map_err(|e| e.located(self.location()))
     }
 }
 impl TypeCheckable for EcsModule {
-    fn check_type(&self) -> Result<(), TypeCheckError> {
+    fn check_type(&self, context: &TypeCheckContext) -> Result<(), TypeCheckError> {
         let mut errors = Vec::new();
+        //自定义类型与组件、事件共享生成代码中的命名空间
+        let mut type_names = HashSet::new();
+        for name in self.components.iter().flatten().map(|component| &component.name)
+            .chain(self.events.iter().flatten().map(|event| &event.name)) {
+            type_names.insert(name.as_str());
+        }
+        for (name, location) in self.enums.iter().flatten().map(|enum_def| (&enum_def.name, &enum_def.location))
+            .chain(self.records.iter().flatten().map(|record| (&record.name, &record.location))) {
+            if !type_names.insert(name.as_str()) {
+                errors.push(TypeCheckError::raise_duplicate_type(name.clone()).located(location));
+            }
+        }
+        if let Some(ref enums) = self.enums {
+            for enum_def in enums {
+                if let Err(e) = enum_def.check_type(context) {
+                    errors.push(e);
+                }
+            }
+        }
+        if let Some(ref records) = self.records {
+            for record in records {
+                if let Err(e) = record.check_type(context) {
+                    errors.push(e);
+                }
+            }
+        }
         if let Some(ref components) = self.components {
             for component in components {
                 if let Some(fields) = &component.fields {
                     for field in fields {
-                        if let Err(e) = field.check_type() {
+                        if let Err(e) = field.check_type(context) {
                             errors.push(e);
                         }
                     }
                 }
             }
         };
+        if let Some(ref events) = self.events {
+            for event in events {
+                for field in event.fields.iter().flatten() {
+                    if let Err(e) = field.check_type(context) {
+                        errors.push(e);
+                    }
+                }
+            }
+        }
+        for entity_proto in self.entity_protos.iter().flatten() {
+            if let Err(e) = entity_proto.check_type(context) {
+                errors.push(e);
+            }
+        }
         if !errors.is_empty() {
             return Err(TypeCheckError::raise_multiple(errors));
         }
@@ -73,7 +402,7 @@ impl TypeCheckable for EcsModule {
 #[cfg(test)]
 mod tests {
     use crate::toml_parser::parsed::Component;
-    use crate::toml_parser::raw::{RawComponentFile, RawTomlCodeFile};
+    use crate::toml_parser::raw::{RawComponentFile, RawEntityProtoFile, RawTomlCodeFile, RawTypeFile};
     use super::*;
     #[test]
     fn test_type_check_passed() {
@@ -123,7 +452,7 @@ mod tests {
             .collect::<Vec<Component>>();
         for comp in components {
             for fields in comp.fields.unwrap() {
-                fields.check_type().unwrap();
+                fields.check_type(&TypeCheckContext::default()).unwrap();
             }
         }
     }
@@ -176,11 +505,221 @@ mod tests {
         let mut errors = Vec::new();
         for comp in components {
             for fields in comp.fields.unwrap() {
-                if let Err(e) = fields.check_type() {
+                if let Err(e) = fields.check_type(&TypeCheckContext::default()) {
                     errors.push(e);
                 }
             }
         }
         assert_eq!(errors.len(), 2);
     }
+    #[test]
+    fn test_type_check_int_range() {
+        let toml_file = r#"
+            [[components]]
+            name = "Counter"
+
+            [[components.fields]]
+            name = "min"
+            type = "int"
+            default = -2147483648
+
+            [[components.fields]]
+            name = "overflow"
+            type = "int"
+            default = 3000000000
+        "#;
+        let components = toml::from_str::<RawComponentFile>(toml_file).unwrap().into_pieces();
+        let component = Component::from(components.into_iter().next().unwrap());
+        let fields = component.fields.unwrap();
+        assert!(fields[0].check_type(&TypeCheckContext::default()).is_ok());
+        let error = fields[1].check_type(&TypeCheckContext::default()).unwrap_err();
+        assert!(matches!(error, TypeCheckError::Located { .. }), "{:?}", error);
+        assert!(matches!(error.unlocated(), TypeCheckError::IntOutOfRange(3000000000)));
+    }
+    #[test]
+    fn test_type_check_list() {
+        let list_of_int = AethaumType::from_type_str("list<int>");
+        let ok = toml::Value::Array(vec![toml::Value::Integer(1), toml::Value::Integer(2)]);
+        assert!(TypeChecker::check_type_value_match(&list_of_int, &Some(ok), &TypeCheckContext::default()).is_ok());
+        assert!(TypeChecker::check_type_value_match(&list_of_int, &Some(toml::Value::Array(vec![])), &TypeCheckContext::default()).is_ok());
+        //非数组默认值
+        let not_array = toml::Value::Integer(1);
+        assert!(matches!(
+            TypeChecker::check_type_value_match(&list_of_int, &Some(not_array), &TypeCheckContext::default()),
+            Err(TypeCheckError::TypeMismatch(AethaumType::List(_), _))
+        ));
+        //元素类型不匹配
+        let bad_element = toml::Value::Array(vec![toml::Value::Integer(1), toml::Value::String("a".into())]);
+        assert!(matches!(
+            TypeChecker::check_type_value_match(&list_of_int, &Some(bad_element), &TypeCheckContext::default()),
+            Err(TypeCheckError::TypeMismatch(AethaumType::Primitive(PrimitiveType::Int), _))
+        ));
+        //嵌套列表
+        let nested = AethaumType::from_type_str("list<list<str>>");
+        let value = toml::Value::Array(vec![toml::Value::Array(vec![toml::Value::String("a".into())])]);
+        assert!(TypeChecker::check_type_value_match(&nested, &Some(value), &TypeCheckContext::default()).is_ok());
+    }
+    #[test]
+    fn test_type_check_entity_id() {
+        let entity_id = AethaumType::from_type_str("EntityId");
+        assert_eq!(entity_id, AethaumType::Primitive(PrimitiveType::EntityId));
+        assert!(TypeChecker::check_type_value_match(&entity_id, &None, &TypeCheckContext::default()).is_ok());
+        assert!(TypeChecker::check_type_value_match(&entity_id, &Some(toml::Value::Integer(0)), &TypeCheckContext::default()).is_err());
+    }
+    #[test]
+    fn test_type_check_enum() {
+        let faction = Enum {
+            name: "Faction".into(),
+            description: None,
+            variants: vec!["Ally".into(), "Enemy".into(), "Neutral".into()],
+            location: SourceLocation::default(),
+        };
+        faction.check_type(&TypeCheckContext::default()).unwrap();
+        let module = EcsModule::new_empty("combat".into()).with_enums(vec![faction]);
+        let context = TypeCheckContext::from_module(&module);
+        let faction_type = AethaumType::from_type_str("Faction");
+        assert!(TypeChecker::check_type_value_match(&faction_type, &Some(toml::Value::String("Enemy".into())), &context).is_ok());
+        assert!(matches!(
+            TypeChecker::check_type_value_match(&faction_type, &Some(toml::Value::String("Friend".into())), &context),
+            Err(TypeCheckError::UnknownVariant { .. })
+        ));
+        assert!(matches!(
+            TypeChecker::check_type_value_match(&faction_type, &Some(toml::Value::Integer(1)), &context),
+            Err(TypeCheckError::TypeMismatch(..))
+        ));
+        let factions = AethaumType::from_type_str("list<Faction>");
+        let value = toml::Value::Array(vec![toml::Value::String("Ally".into())]);
+        assert!(TypeChecker::check_type_value_match(&factions, &Some(value), &context).is_ok());
+        //未定义的类型
+        assert!(matches!(
+            TypeChecker::check_type_value_match(&faction_type, &None, &TypeCheckContext::default()),
+            Err(TypeCheckError::UnknownType(_))
+        ));
+    }
+    #[test]
+    fn test_type_check_enum_definition() {
+        let empty = Enum { name: "Empty".into(), description: None, variants: vec![], location: SourceLocation::default() };
+        assert!(matches!(empty.check_type(&TypeCheckContext::default()).unwrap_err().unlocated(), TypeCheckError::EmptyEnum(_)));
+        let invalid = Enum {
+            name: "Invalid".into(),
+            description: None,
+            variants: vec!["Ok".into(), "Ok".into(), "1st".into(), "has space".into(), "Self".into(), "type".into(), "fn".into()],
+            location: SourceLocation::default(),
+        };
+        match invalid.check_type(&TypeCheckContext::default()) {
+            Err(TypeCheckError::Multiple { errors }) => assert_eq!(errors.len(), 6),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+    fn vec2_module() -> EcsModule {
+        let toml_str = r#"
+            [[records]]
+            name = "Vec2"
+
+            [[records.fields]]
+            name = "x"
+            type = "float"
+            default = 0.0
+
+            [[records.fields]]
+            name = "y"
+            type = "float"
+        "#;
+        let (_, raw_records) = toml::from_str::<RawTypeFile>(toml_str).unwrap().into_pieces();
+        EcsModule::new_empty("explore".into()).with_records(raw_records.into_iter().map(Record::from).collect())
+    }
+    #[test]
+    fn test_type_check_record() {
+        let explore = vec2_module();
+        let combat = EcsModule::new_empty("combat".into());
+        let types = CustomTypes::default().with_modules([&explore, &combat]);
+        let context = TypeCheckContext::new("combat", types);
+        let vec2 = AethaumType::from_type_str("explore::Vec2");
+        let value = toml::from_str::<toml::Table>("v = { x = 1.0 }").unwrap().remove("v");
+        assert!(TypeChecker::check_type_value_match(&vec2, &value, &context).is_ok());
+        //字段逐个检查
+        let value = toml::from_str::<toml::Table>("v = { x = \"a\", z = 1.0 }").unwrap().remove("v");
+        match TypeChecker::check_type_value_match(&vec2, &value, &context) {
+            Err(TypeCheckError::Multiple { errors }) => {
+                assert_eq!(errors.len(), 2);
+                assert!(errors.iter().any(|e| matches!(e, TypeCheckError::UnknownField { .. })));
+                assert!(errors.iter().any(|e| matches!(e, TypeCheckError::TypeMismatch(..))));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        //未限定模块的引用只在当前模块中查找
+        assert!(matches!(
+            TypeChecker::check_type_defined(&AethaumType::from_type_str("Vec2"), &context),
+            Err(TypeCheckError::UnknownType(_))
+        ));
+    }
+    #[test]
+    fn test_type_check_recursive_record() {
+        let toml_str = r#"
+            [[records]]
+            name = "Node"
+
+            [[records.fields]]
+            name = "next"
+            type = "Link"
+
+            [[records]]
+            name = "Link"
+
+            [[records.fields]]
+            name = "node"
+            type = "Node"
+
+            [[records]]
+            name = "Tree"
+
+            [[records.fields]]
+            name = "children"
+            type = "list<Tree>"
+        "#;
+        let (_, raw_records) = toml::from_str::<RawTypeFile>(toml_str).unwrap().into_pieces();
+        let module = EcsModule::new_empty("explore".into()).with_records(raw_records.into_iter().map(Record::from).collect());
+        let context = TypeCheckContext::from_module(&module);
+        let records = module.records.as_ref().unwrap();
+        assert!(matches!(records[0].check_type(&context).unwrap_err().unlocated(), TypeCheckError::RecursiveRecord(_)));
+        assert!(matches!(records[1].check_type(&context).unwrap_err().unlocated(), TypeCheckError::RecursiveRecord(_)));
+        //列表打破了递归
+        assert!(records[2].check_type(&context).is_ok());
+    }
+    #[test]
+    fn test_type_check_proto_overrides() {
+        let combat_components = r#"
+            [[components]]
+            name = "Health"
+
+            [[components.fields]]
+            name = "value"
+            type = "float"
+            default = 100.0
+        "#;
+        let protos = r#"
+            [[entity_protos]]
+            name = "Boss"
+            components = [{ ref = "combat::Health", value = 150.0 }]
+
+            [[entity_protos]]
+            name = "Broken"
+            components = [{ ref = "combat::Health", value = "a", shield = 1.0 }, { ref = "Unknown", value = 1 }]
+        "#;
+        let components = toml::from_str::<RawComponentFile>(combat_components).unwrap().into_pieces();
+        let combat = EcsModule::new_empty("combat".into()).with_components(components.into_iter().map(Component::from).collect());
+        let protos = toml::from_str::<RawEntityProtoFile>(protos).unwrap().into_pieces();
+        let protos = protos.into_iter().map(|proto| EntityProto::try_from(proto).unwrap()).collect::<Vec<_>>();
+        let context = TypeCheckContext::new("explore", CustomTypes::default().with_module(&combat));
+        assert!(protos[0].check_type(&context).is_ok());
+        //未知组件由引用检查负责，这里只报告覆盖值的错误
+        match protos[1].check_type(&context).unwrap_err() {
+            TypeCheckError::Multiple { errors } => {
+                assert_eq!(errors.len(), 2);
+                assert!(errors.iter().any(|e| matches!(e.unlocated(), TypeCheckError::TypeMismatch(..))));
+                assert!(errors.iter().any(|e| matches!(e.unlocated(), TypeCheckError::UnknownComponentField { field, .. } if field.as_str() == "shield")));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
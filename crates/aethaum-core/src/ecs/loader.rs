@@ -1,40 +1,80 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use serde::Deserialize;
 use smart_string::SmartString;
 use crate::ecs::module::{AethaumProject, EcsModule, EcsModuleTree};
-use crate::toml_parser::parsed::{Component, EntityProto, Event, System, World};
-use crate::toml_parser::raw::{RawComponent, RawComponentFile, RawEntityProto, RawEntityProtoFile, RawEvent, RawEventFile, RawSystem, RawSystemFile, RawTomlCodeFile, RawWorld};
+use crate::toml_parser::parsed::{Component, EntityProto, Enum, Event, Record, System, World};
+use crate::toml_parser::raw::{RawComponent, RawComponentFile, RawEntityProto, RawEntityProtoFile, RawEnum, RawEvent, RawEventFile, RawRecord, RawSystem, RawSystemFile, RawTomlCodeFile, RawTypeFile, RawWorld};
+use crate::toml_parser::source::{display_location, source_file, Locatable, SourceFile, SourceLocation};
 use itertools::Itertools;
+use miette::{Diagnostic, SourceSpan};
 use one_or_many::OneOrMany;
 use thiserror::Error;
+use toml::Spanned;
 
-#[derive(Debug,Error)]
+#[derive(Debug,Error,Diagnostic)]
 pub enum ModuleFileLoaderError {
     #[error("Failed to load module file, {0}")]
     IoError(#[from] std::io::Error),
-    #[error("Illegal toml file, {0}")]
-    IllegalToml(#[from] toml::de::Error),
+    #[error("Illegal toml file {}, {message}", source_code.name())]
+    IllegalToml {
+        message: String,
+        #[source_code]
+        source_code: SourceFile,
+        #[label("{message}")]
+        span: Option<SourceSpan>,
+    },
     #[error("fail to convert: {0}")]
     ConversionError(#[from] anyhow::Error),
-    #[error("Multiple errors occurred during loading:\n{}",
-        .errors.iter().map(|e| format!("  - {}", e)).collect::<Vec<_>>().join("\n"))]
+    #[error("{}: {error}", display_location(source_code, span))]
+    Located {
+        error: Box<ModuleFileLoaderError>,
+        #[source_code]
+        source_code: SourceFile,
+        #[label]
+        span: SourceSpan,
+    },
+    #[error("{} errors occurred during loading", .errors.len())]
     Multiple {
+        #[related]
         errors: Vec<ModuleFileLoaderError>
     }
 }
 impl ModuleFileLoaderError {
+    pub fn raise_illegal_toml(error: toml::de::Error, source_code: SourceFile) -> ModuleFileLoaderError {
+        ModuleFileLoaderError::IllegalToml {
+            message: error.message().to_string(),
+            source_code,
+            span: error.span().map(Into::into),
+        }
+    }
     pub fn raise_multiple(errors: Vec<ModuleFileLoaderError>) -> ModuleFileLoaderError {
         ModuleFileLoaderError::Multiple { errors }
     }
+    ///为错误附加源码位置，已有位置的错误保持不变
+    pub fn located(self, location: &SourceLocation) -> ModuleFileLoaderError {
+        match self {
+            ModuleFileLoaderError::Multiple { errors } => ModuleFileLoaderError::raise_multiple(
+                errors.into_iter().map(|error| error.located(location)).collect()
+            ),
+            error @ (ModuleFileLoaderError::Located { .. } | ModuleFileLoaderError::IllegalToml { .. }) => error,
+            error => ModuleFileLoaderError::Located {
+                error: Box::new(error),
+                source_code: location.source.clone(),
+                span: location.span,
+            }
+        }
+    }
 }
-#[derive(Debug, Error)]
+#[derive(Debug, Error, Diagnostic)]
 pub enum ProjectLoaderError {
     #[error("world.toml not found in the project.")]
     MissingWorld,
     #[error("module '{0}' not found")]
     MissingModule(SmartString),
-    #[error("{0}")]
+    #[error(transparent)]
+    #[diagnostic(transparent)]
     FileError(#[from] ModuleFileLoaderError)
 }
 impl From<std::io::Error> for ProjectLoaderError {
@@ -42,11 +82,6 @@ impl From<std::io::Error> for ProjectLoaderError {
         ProjectLoaderError::FileError(ModuleFileLoaderError::IoError(error))
     }
 }
-impl From<toml::de::Error> for ProjectLoaderError {
-    fn from(error: toml::de::Error) -> Self {
-        ProjectLoaderError::FileError(ModuleFileLoaderError::IllegalToml(error))
-    }
-}
 impl From<anyhow::Error> for ProjectLoaderError {
     fn from(error: anyhow::Error) -> Self {
         ProjectLoaderError::FileError(ModuleFileLoaderError::ConversionError(error))
@@ -75,11 +110,12 @@ impl Loadable for System {
     type RawType = RawSystem;
     type Error = ModuleFileLoaderError;
     fn try_load(path: &Path) -> Result<OneOrMany<Self>, Self::Error> {
-        let file_content = load_file(path)?;
-        let raw_system_file: RawSystemFile = toml::from_str(&file_content)?;
+        let (raw_system_file, source): (RawSystemFile, _) = load_toml(path)?;
         let raw_system = raw_system_file.into_pieces();
-        let system = System::try_from(raw_system)?;
-        Ok(OneOrMany::One(Box::new(system)))
+        let location = SourceLocation::new(source.clone(), raw_system.normal.span());
+        let system = System::try_from(raw_system)
+            .map_err(|error| ModuleFileLoaderError::from(error).located(&location))?;
+        Ok(OneOrMany::One(Box::new(system.with_source(&source))))
     }
 }
 impl Loadable for Component {
@@ -87,11 +123,10 @@ impl Loadable for Component {
     type RawType = RawComponent;
     type Error = ModuleFileLoaderError;
     fn try_load(path: &Path) -> Result<OneOrMany<Self>, Self::Error> {
-        let file_content = load_file(path)?;
-        let raw_component_file: RawComponentFile = toml::from_str(&file_content)?;
+        let (raw_component_file, source): (RawComponentFile, _) = load_toml(path)?;
         let raw_components = raw_component_file.into_pieces();
         Ok(OneOrMany::Many(
-            raw_components.into_iter().map(|raw_component| Component::from(raw_component)).collect()
+            raw_components.into_iter().map(|raw| Component::from(raw).with_source(&source)).collect()
         ))
     }
 }
@@ -100,11 +135,46 @@ impl Loadable for Event {
     type RawType = RawEvent;
     type Error = ModuleFileLoaderError;
     fn try_load(path: &Path) -> Result<OneOrMany<Self>, Self::Error> {
-        let file_content = load_file(path)?;
-        let raw_event_file: RawEventFile = toml::from_str(&file_content)?;
+        let (raw_event_file, source): (RawEventFile, _) = load_toml(path)?;
         let raw_events = raw_event_file.into_pieces();
         Ok(OneOrMany::Many(
-            raw_events.into_iter().map(|raw_event| Event::from(raw_event)).collect()
+            raw_events.into_iter().map(|raw| Event::from(raw).with_source(&source)).collect()
+        ))
+    }
+}
+impl Loadable for Enum {
+    type RawFile = RawTypeFile;
+    type RawType = RawEnum;
+    type Error = ModuleFileLoaderError;
+    fn try_load(path: &Path) -> Result<OneOrMany<Self>, Self::Error> {
+        let (raw_type_file, source): (RawTypeFile, _) = load_toml(path)?;
+        let (raw_enums, _) = raw_type_file.into_pieces();
+        //简写形式的枚举经过 flatten 后没有位置，按顶层键重新定位
+        let top_level_spans: BTreeMap<SmartString, Spanned<toml::Value>> = toml::from_str(source.inner())
+            .map_err(|error| ModuleFileLoaderError::raise_illegal_toml(error, source.clone()))?;
+        Ok(OneOrMany::Many(
+            raw_enums.into_iter()
+                .map(|raw| {
+                    let mut enum_def = Enum::from(raw).with_source(&source);
+                    if enum_def.location.span.is_empty()
+                        && let Some(value) = top_level_spans.get(&enum_def.name) {
+                        enum_def.location = SourceLocation::new(source.clone(), value.span());
+                    }
+                    enum_def
+                })
+                .collect()
+        ))
+    }
+}
+impl Loadable for Record {
+    type RawFile = RawTypeFile;
+    type RawType = RawRecord;
+    type Error = ModuleFileLoaderError;
+    fn try_load(path: &Path) -> Result<OneOrMany<Self>, Self::Error> {
+        let (raw_type_file, source): (RawTypeFile, _) = load_toml(path)?;
+        let (_, raw_records) = raw_type_file.into_pieces();
+        Ok(OneOrMany::Many(
+            raw_records.into_iter().map(|raw| Record::from(raw).with_source(&source)).collect()
         ))
     }
 }
@@ -113,16 +183,16 @@ impl Loadable for EntityProto {
     type RawType = RawEntityProto;
     type Error = ModuleFileLoaderError;
     fn try_load(path: &Path) -> Result<OneOrMany<Self>, Self::Error> {
-        let file_content = load_file(path)?;
-        let raw_entity_proto_file: RawEntityProtoFile = toml::from_str(&file_content)?;
+        let (raw_entity_proto_file, source): (RawEntityProtoFile, _) = load_toml(path)?;
         let raw_entity_protos = raw_entity_proto_file.into_pieces();
 
         let mut errors = Vec::new();
         let mut entity_protos = Vec::new();
         for raw_entity_proto in raw_entity_protos {
+            let location = SourceLocation::new(source.clone(), raw_entity_proto.span());
             match EntityProto::try_from(raw_entity_proto) {
-                Ok(entity_proto) => entity_protos.push(entity_proto),
-                Err(error) => errors.push(error.into())
+                Ok(entity_proto) => entity_protos.push(entity_proto.with_source(&source)),
+                Err(error) => errors.push(ModuleFileLoaderError::from(error).located(&location))
             }
         }
         if !errors.is_empty() {
@@ -140,9 +210,8 @@ impl Loadable for World {
     type RawType = RawWorld;
     type Error = ProjectLoaderError;
     fn try_load(path: &Path) -> Result<OneOrMany<Self>, Self::Error> {
-        let file_content = load_file(path)?;
-        let raw_world: RawWorld = toml::from_str(&file_content)?;
-        let world = World::from(raw_world);
+        let (raw_world, source): (RawWorld, _) = load_toml(path)?;
+        let world = World::try_from(raw_world)?.with_source(&source);
         Ok(OneOrMany::One(Box::new(world)))
     }
 }
@@ -163,12 +232,16 @@ impl ModuleFileLoader {
         let components = self.load_parts(self.base_path.join("components"))?;
         let events = self.load_parts(self.base_path.join("events"))?;
         let entity_protos = self.load_parts(self.base_path.join("entity_protos"))?;
+        let enums = self.load_parts(self.base_path.join("types"))?;
+        let records = self.load_parts(self.base_path.join("types"))?;
         Ok(
             EcsModule::new_empty(self.module_name)
                 .with_option_components(components)
                 .with_option_events(events)
                 .with_option_entity_protos(entity_protos)
                 .with_option_systems(systems)
+                .with_option_enums(enums)
+                .with_option_records(records)
         )
     }
     fn load_parts<T: Loadable<Error = ModuleFileLoaderError>>(&self, dir_path: impl AsRef<Path>) -> Result<Option<Vec<T>>, <T as Loadable>::Error>
@@ -201,6 +274,14 @@ impl ModuleFileLoader {
         Ok(Some(parts.into_iter().flatten().collect())) //TODO: try reduce the collect call
     }
 }
+///读取并解析toml文件，同时返回供诊断使用的源文件
+fn load_toml<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<(T, SourceFile), ModuleFileLoaderError> {
+    let source = source_file(path.display().to_string(), load_file(path)?);
+    match toml::from_str(source.inner()) {
+        Ok(raw) => Ok((raw, source)),
+        Err(error) => Err(ModuleFileLoaderError::raise_illegal_toml(error, source)),
+    }
+}
 fn load_file(path: impl AsRef<Path>) -> Result<String,std::io::Error> {
     if path.as_ref().exists() {
         std::fs::read_to_string(path)
@@ -261,15 +342,14 @@ impl ProjectLoader {
 mod tests {
     use super::*;
     use std::path::Path;
-    use std::fs;
     #[test]
     fn test_load_file() {
-        let string = load_file(Path::new(r#"D:\Aethaum\test_project\world.toml"#)).unwrap();
+        let string = load_file(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_project/world.toml"))).unwrap();
         println!("{}", string)
     }
     #[test]
     fn test_list_dir() {
-        let base_path = Path::new(r#"D:\Aethaum\test_project\modules\explore"#);
+        let base_path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_project/modules/explore"));
         let paths = list_dir(base_path.join("components")).unwrap();
         for path in paths {
             println!("{}", path.display());
@@ -277,14 +357,40 @@ mod tests {
     }
     #[test]
     fn test_load_single_module() {
-        let base_path = Path::new(r#"D:\Aethaum\test_project\modules\explore"#);
+        let base_path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_project/modules/explore"));
         let module = ModuleFileLoader::new(base_path.to_path_buf(), "explore".into()).load().unwrap();
         assert_eq!(module.name, "explore")
     }
     #[test]
     fn test_load_project() {
-        let base_path = Path::new(r#"D:\Aethaum\test_project"#);
+        let base_path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_project"));
         let project = ProjectLoader::new(base_path.to_path_buf()).load().unwrap();
         assert_eq!(project.world.normal.name, "MyAIWorld");
     }
+    #[test]
+    fn test_load_error_location() {
+        let dir = std::env::temp_dir().join("aethaum_test_load_error_location");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let illegal = dir.join("illegal.toml");
+        fs::write(&illegal, "[[components]]\nname = Health\n").unwrap();
+        match Component::try_load(&illegal) {
+            Err(ModuleFileLoaderError::IllegalToml { span: Some(span), .. }) => assert_eq!(span.offset(), 22),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        //转换失败的定义指向其表头
+        let protos = dir.join("protos.toml");
+        fs::write(&protos, "[[entity_protos]]\nname = \"Ok\"\ncomponents = []\n\n[[entity_protos]]\nname = \"Nested\"\ncomponents = [\"a::b::C\"]\n").unwrap();
+        let error = EntityProto::try_load(&protos).err().unwrap();
+        assert!(matches!(error, ModuleFileLoaderError::Located { .. }));
+        assert!(error.to_string().starts_with(&format!("{}:5:1: fail to convert", protos.display())), "{}", error);
+        //简写枚举按顶层键定位
+        let types = dir.join("types.toml");
+        fs::write(&types, "Faction = [\"Ally\"]\n").unwrap();
+        let OneOrMany::Many(enums) = Enum::try_load(&types).unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(enums[0].location.span.offset(), 10);
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
\ No newline at end of file
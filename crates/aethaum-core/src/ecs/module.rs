@@ -1,11 +1,7 @@
 use std::collections::HashMap;
-use std::fs;
-use std::path::{Path, PathBuf};
-use serde::Deserialize;
+use std::path::PathBuf;
 use smart_string::SmartString;
-use thiserror::Error;
-use crate::toml_parser::parsed::{Component, ComponentRef, EntityProto, EntityProtoRef, Event, EventRef, System, SystemRef, World};
-use crate::toml_parser::raw::{RawComponent, RawEntityProto, RawEvent, RawSystem};
+use crate::toml_parser::parsed::{AethaumRef, Component, ComponentRef, EntityProto, EntityProtoRef, Enum, Event, EventRef, ProtoComponent, Record, System, SystemRef, TypeRef, World};
 
 #[derive(Debug)]
 #[derive(Clone)]
@@ -107,6 +103,8 @@ pub struct EcsModule {
     pub events: Option<Vec<Event>>,
     pub entity_protos: Option<Vec<EntityProto>>,
     pub systems: Option<Vec<System>>,
+    pub enums: Option<Vec<Enum>>,
+    pub records: Option<Vec<Record>>,
 }
 impl EcsModule {
     pub fn new_empty(name: SmartString) -> Self {
@@ -116,6 +114,8 @@ impl EcsModule {
             events: None,
             entity_protos: None,
             systems: None,
+            enums: None,
+            records: None,
         }
     }
     pub fn with_components(mut self, components: Vec<Component>) -> Self {
@@ -134,30 +134,101 @@ impl EcsModule {
         self.systems = Some(systems);
         self
     }
-    pub fn with_option_components(mut self, components: Option<Vec<Component>>) -> Self {
+    pub fn with_enums(mut self, enums: Vec<Enum>) -> Self {
+        self.enums = Some(enums);
+        self
+    }
+    pub fn with_records(mut self, records: Vec<Record>) -> Self {
+        self.records = Some(records);
+        self
+    }
+    pub fn with_option_components(self, components: Option<Vec<Component>>) -> Self {
         match components {
             Some(components) => self.with_components(components),
             None => self
         }
     }
-    pub fn with_option_events(mut self, events: Option<Vec<Event>>) -> Self {
+    pub fn with_option_events(self, events: Option<Vec<Event>>) -> Self {
         match events {
             Some(events) => self.with_events(events),
             None => self
         }
     }
-    pub fn with_option_entity_protos(mut self, entity_protos: Option<Vec<EntityProto>>) -> Self {
+    pub fn with_option_entity_protos(self, entity_protos: Option<Vec<EntityProto>>) -> Self {
         match entity_protos {
             Some(entity_protos) => self.with_entity_protos(entity_protos),
             None => self
         }
     }
-    pub fn with_option_systems(mut self, systems: Option<Vec<System>>) -> Self {
+    pub fn with_option_systems(self, systems: Option<Vec<System>>) -> Self {
         match systems {
             Some(systems) => self.with_systems(systems),
             None => self
         }
     }
+    pub fn with_option_enums(self, enums: Option<Vec<Enum>>) -> Self {
+        match enums {
+            Some(enums) => self.with_enums(enums),
+            None => self
+        }
+    }
+    pub fn with_option_records(self, records: Option<Vec<Record>>) -> Self {
+        match records {
+            Some(records) => self.with_records(records),
+            None => self
+        }
+    }
+}
+#[derive(Debug, Clone, Copy)]
+pub enum CustomTypeDef<'a> {
+    Enum(&'a Enum),
+    Record(&'a Record),
+}
+impl<'a> CustomTypeDef<'a> {
+    pub fn name(&self) -> &'a str {
+        match self {
+            CustomTypeDef::Enum(enum_def) => enum_def.name.as_str(),
+            CustomTypeDef::Record(record) => record.name.as_str(),
+        }
+    }
+}
+///项目中可见的自定义类型，模块名 -> 类型名 -> 定义
+///组件单独记录，原型覆盖组件字段时需要查找组件的定义
+#[derive(Debug, Default, Clone)]
+pub struct CustomTypes<'a> {
+    modules: HashMap<&'a str, HashMap<&'a str, CustomTypeDef<'a>>>,
+    components: HashMap<&'a str, HashMap<&'a str, &'a Component>>,
+}
+impl<'a> CustomTypes<'a> {
+    pub fn with_module(mut self, module: &'a EcsModule) -> Self {
+        let types = self.modules.entry(module.name.as_str()).or_default();
+        for enum_def in module.enums.iter().flatten() {
+            types.insert(enum_def.name.as_str(), CustomTypeDef::Enum(enum_def));
+        }
+        for record in module.records.iter().flatten() {
+            types.insert(record.name.as_str(), CustomTypeDef::Record(record));
+        }
+        let components = self.components.entry(module.name.as_str()).or_default();
+        for component in module.components.iter().flatten() {
+            components.insert(component.name.as_str(), component);
+        }
+        self
+    }
+    pub fn with_modules(self, modules: impl IntoIterator<Item = &'a EcsModule>) -> Self {
+        modules.into_iter().fold(self, |types, module| types.with_module(module))
+    }
+    ///解析类型引用，未指定模块时在 current_module 中查找，返回定义所在的模块名与定义
+    pub fn resolve(&self, type_ref: &TypeRef, current_module: &str) -> Option<(&'a str, CustomTypeDef<'a>)> {
+        let module_name = type_ref.module_name.as_deref().unwrap_or(current_module);
+        let (&module_name, types) = self.modules.get_key_value(module_name)?;
+        types.get(type_ref.name.as_str()).map(|type_def| (module_name, *type_def))
+    }
+    ///解析组件引用，规则与 resolve 相同
+    pub fn resolve_component(&self, component_ref: &ComponentRef, current_module: &str) -> Option<(&'a str, &'a Component)> {
+        let module_name = component_ref.module_name.as_deref().unwrap_or(current_module);
+        let (&module_name, components) = self.components.get_key_value(module_name)?;
+        components.get(component_ref.name.as_str()).map(|component| (module_name, *component))
+    }
 }
 pub struct EcsModuleTree {
     tree: HashMap<SmartString, EcsModule>
@@ -186,6 +257,72 @@ impl EcsModuleTree {
     pub fn get_modules(&self) -> Vec<&EcsModule> {
         self.tree.values().collect()
     }
+    ///解析原型引用，未指定模块时在 current_module 中查找，返回原型所在的模块名与原型
+    pub fn resolve_entity_proto(&self, proto_ref: &EntityProtoRef, current_module: &str) -> Option<(&str, &EntityProto)> {
+        let module_name = proto_ref.module_name.as_deref().unwrap_or(current_module);
+        let module = self.tree.get(module_name)?;
+        module.entity_protos.iter().flatten()
+            .find(|proto| proto.name == proto_ref.name)
+            .map(|proto| (module.name.as_str(), proto))
+    }
+    ///原型的继承链，第一个元素为原型自身；继承关系中出现循环时在回到已访问的原型处截止，末尾为重复出现的原型
+    pub fn entity_proto_ancestors(&self, module_name: &str, proto: &EntityProto) -> Vec<EntityProtoRef> {
+        let mut chain = vec![EntityProtoRef::from((module_name, proto.name.as_str()))];
+        let mut current = (module_name, proto);
+        while let Some(parent_ref) = &current.1.extends
+            && let Some(parent) = self.resolve_entity_proto(parent_ref, current.0) {
+            let parent_ref = EntityProtoRef::from((parent.0, parent.1.name.as_str()));
+            let visited = chain.contains(&parent_ref);
+            chain.push(parent_ref);
+            if visited {
+                break;
+            }
+            current = parent;
+        }
+        chain
+    }
+    ///展开原型的继承：父原型的组件在前，子原型的组件追加在后；同一组件的覆盖值合并，子原型优先
+    ///展开后的组件引用都带有模块名，需要在继承关系通过检查后调用
+    pub fn flatten_entity_protos(&mut self) {
+        let mut flattened = Vec::new();
+        for module in self.tree.values() {
+            for (index, proto) in module.entity_protos.iter().flatten().enumerate() {
+                if proto.extends.is_some() {
+                    flattened.push((module.name.clone(), index, self.flattened_components(module.name.as_str(), proto)));
+                }
+            }
+        }
+        for (module_name, index, components) in flattened {
+            let proto = &mut self.tree.get_mut(&module_name).unwrap().entity_protos.as_mut().unwrap()[index]; //ROBUST: collected from the same tree above
+            proto.components = components;
+            proto.extends = None;
+        }
+    }
+    fn flattened_components(&self, module_name: &str, proto: &EntityProto) -> Vec<ProtoComponent> {
+        let chain = self.entity_proto_ancestors(module_name, proto);
+        let mut components: Vec<ProtoComponent> = Vec::new();
+        //从最远的祖先开始合并，循环的继承在检查阶段已被拒绝
+        for proto_ref in chain.iter().rev() {
+            let Some((proto_module, proto)) = self.resolve_entity_proto(proto_ref, module_name) else {
+                continue;
+            };
+            for component in proto.components.iter() {
+                //未限定模块的引用相对于声明它的原型所在模块
+                let component_ref = match &component.component_ref.module_name {
+                    Some(_) => component.component_ref.clone(),
+                    None => component.component_ref.clone().to_global_ref(proto_module.into()),
+                };
+                match components.iter_mut().find(|existing| existing.component_ref == component_ref) {
+                    Some(existing) => existing.overrides.extend(component.overrides.clone()),
+                    None => components.push(ProtoComponent {
+                        component_ref,
+                        overrides: component.overrides.clone(),
+                    }),
+                }
+            }
+        }
+        components
+    }
 }
 pub struct AethaumProject {
     pub root: PathBuf,
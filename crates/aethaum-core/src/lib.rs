@@ -1,13 +1,13 @@
-mod toml_parser;
-mod ecs;
-mod code_generator;
+pub mod toml_parser;
+pub mod ecs;
+pub mod code_generator;
 
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use super::*;
 
     #[test]
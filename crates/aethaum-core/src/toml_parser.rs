@@ -2,12 +2,12 @@ use std::marker::PhantomData;
 use one_or_many::OneOrMany;
 use crate::toml_parser::parsed::TomlCode;
 use anyhow::Result;
-use crate::toml_parser;
 use crate::toml_parser::raw::RawTomlCodeFile;
 
 pub mod raw;
 pub mod parsed;
 pub mod error;
+pub mod source;
 
 pub struct Parser<T> {
     raw_content: String,
@@ -154,6 +154,7 @@ mod tests {
             [[events]]
             name = "EntityHealed"
             description = "实体被治疗"
+            tags = ["agent-action"]
 
             [[events.fields]]
             name = "amount"
@@ -180,6 +181,8 @@ mod tests {
                 let fields = vec[0].fields.as_ref().unwrap();
                 assert_eq!(fields.len(), 2);
                 assert_eq!(fields[0].name, "damage");
+                assert!(vec[0].tags.is_empty());
+                assert_eq!(vec[1].tags, vec!["agent-action"]);
             },
             _ => panic!("Expected Many variant"),
         }
@@ -293,6 +296,7 @@ mod tests {
         let systems = result.unwrap();
         match systems {
             OneOrMany::One(sys) => {
+                assert_eq!(sys.normal.priority, 100);
                 assert_eq!(sys.queries.len(),2);
                 assert_eq!(sys.event_handlers.len(),2);
                 assert_eq!(sys.update.unwrap().interval, Duration::from_secs_f64(0.1));
@@ -301,6 +305,38 @@ mod tests {
         }
     }
 
+    /// 测试System优先级校验
+    /// 验证错误处理：负数与非整数优先级
+    #[test]
+    fn test_parse_system_invalid_priority() {
+        for priority in ["-1", "\"high\"", "1.5"] {
+            let toml_content = format!(r#"
+                queries = []
+                event_handlers = []
+
+                [normal]
+                name = "HealthSystem"
+                priority = {}
+            "#, priority);
+            let mut parser: Parser<System> = Parser::new(toml_content);
+            assert!(parser.parse().is_err(), "priority {} should be rejected", priority);
+        }
+    }
+
+    /// 测试列表类型解析
+    /// 验证 list<T> 被解析为 List 并映射为 Vec<T>
+    #[test]
+    fn test_parse_list_type() {
+        use crate::toml_parser::parsed::{AethaumType, PrimitiveType};
+        let list_type = AethaumType::from_type_str("list<int>");
+        assert_eq!(list_type, AethaumType::List(Box::new(AethaumType::Primitive(PrimitiveType::Int))));
+        assert_eq!(list_type.to_string(), "list<int>");
+        let rust_type = list_type.to_rust_type();
+        assert_eq!(quote::quote!(#rust_type).to_string(), "Vec < i32 >");
+        let nested = AethaumType::from_type_str("list<list<str>>").to_rust_type();
+        assert_eq!(quote::quote!(#nested).to_string(), "Vec < Vec < String > >");
+    }
+
     /// 测试无效TOML格式解析
     /// 验证错误处理：TOML解析失败情况
     #[test]
@@ -1,8 +1,9 @@
-use std::collections::HashMap;
-use std::fmt::Display;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
-use crate::toml_parser::raw::{RawComponent, RawComponentField, RawComponentFile, RawEntityProto, RawEntityProtoFile, RawEvent, RawEventField, RawEventFile, RawSystem, RawSystemEventHandler, RawSystemFile, RawSystemNormal, RawSystemQuery, RawSystemUpdate, RawTomlCodeFile, RawWorld, RawWorldBuild, RawWorldCargo, RawWorldModules, RawWorldNormal};
+use crate::toml_parser::raw::{RawComponent, RawComponentField, RawComponentFile, RawEntityProto, RawEntityProtoFile, RawProtoComponent, RawEnum, RawEvent, RawEventField, RawEventFile, RawRecord, RawSystem, RawSystemEventHandler, RawSystemFile, RawSystemNormal, RawSystemQuery, RawSystemUpdate, RawTomlCodeFile, RawTypeFile, RawWorld, RawWorldBuild, RawWorldCargo, RawWorldModules, RawWorldNormal, RawWorldSandbox, RawWorldEnvironment, RawEnvironmentSpawn};
 use smart_string::SmartString;
+use toml::Spanned;
+use crate::toml_parser::source::{Locatable, SourceFile, SourceLocation};
 use std::time::Duration;
 use anyhow::Error;
 use itertools::Itertools;
@@ -38,7 +39,10 @@ pub trait TomlCode: Sized { //标记Trait, 用于约束Parser泛型
 }
 pub trait Field {
     fn name_as_rust_ident(&self) -> Ident;
-    fn type_as_rust_ident(&self) -> Ident;
+    fn type_spec(&self) -> &AethaumType;
+    fn type_as_rust_type(&self) -> syn::Type {
+        self.type_spec().to_rust_type()
+    }
 }
 pub trait Describable {
     fn description(&self) -> Option<&str> {
@@ -59,6 +63,7 @@ pub enum PrimitiveType {
     Int,
     Bool,
     Str,
+    EntityId, //实体引用，对应 bevy_ecs::entity::Entity
     //TODO: 添加更多类型
 }
 impl std::fmt::Display for PrimitiveType {
@@ -68,6 +73,7 @@ impl std::fmt::Display for PrimitiveType {
             PrimitiveType::Int => write!(f, "int"),
             PrimitiveType::Bool => write!(f, "bool"),
             PrimitiveType::Str => write!(f, "str"),
+            PrimitiveType::EntityId => write!(f, "EntityId"),
         }
     }
 }
@@ -75,13 +81,15 @@ impl std::fmt::Display for PrimitiveType {
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum AethaumType {
     Primitive(PrimitiveType),
-    Custom(SmartString)
+    List(Box<AethaumType>), //list<T>
+    Custom(TypeRef)
 }
 impl std::fmt::Display for AethaumType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             AethaumType::Primitive(primitive) => write!(f, "{}", primitive),
-            AethaumType::Custom(custom) => write!(f, "{}", custom),
+            AethaumType::List(element_type) => write!(f, "list<{}>", element_type),
+            AethaumType::Custom(type_ref) => write!(f, "{}", type_ref),
         }
     }
 }
@@ -90,46 +98,114 @@ impl AethaumType {
     pub fn is_primitive(&self) -> bool {
         matches!(self, AethaumType::Primitive(_))
     }
+    pub fn is_list(&self) -> bool {
+        matches!(self, AethaumType::List(_))
+    }
     pub fn is_custom(&self) -> bool {
         matches!(self, AethaumType::Custom(_))
     }
     pub fn from_type_str(type_str: &str) -> AethaumType {
+        let type_str = type_str.trim();
         match type_str {
             "float" => AethaumType::Primitive(PrimitiveType::Float),
             "int" => AethaumType::Primitive(PrimitiveType::Int),
             "bool" => AethaumType::Primitive(PrimitiveType::Bool),
             "str" => AethaumType::Primitive(PrimitiveType::Str),
-
-            _ => AethaumType::Custom(type_str.into()),
+            "EntityId" => AethaumType::Primitive(PrimitiveType::EntityId),
+            _ => match type_str.strip_prefix("list<").and_then(|rest| rest.strip_suffix('>')) {
+                Some(element_type) => AethaumType::List(Box::new(AethaumType::from_type_str(element_type))),
+                None => AethaumType::Custom(TypeRef::from_type_str(type_str)),
+            }
         }
     }
-    pub fn to_rust_type(&self) -> Ident {
+    pub fn to_rust_type(&self) -> syn::Type {
         match self {
             AethaumType::Primitive(primitive) => match primitive {
-                PrimitiveType::Float => Ident::new("f32", Span::call_site()),
-                PrimitiveType::Int => Ident::new("i32", Span::call_site()),
-                PrimitiveType::Bool => Ident::new("bool", Span::call_site()),
-                PrimitiveType::Str => Ident::new("String", Span::call_site()),
+                PrimitiveType::Float => syn::parse_quote! { f32 },
+                PrimitiveType::Int => syn::parse_quote! { i32 },
+                PrimitiveType::Bool => syn::parse_quote! { bool },
+                PrimitiveType::Str => syn::parse_quote! { String },
+                PrimitiveType::EntityId => syn::parse_quote! { Entity },
+            },
+            AethaumType::List(element_type) => {
+                let element_type = element_type.to_rust_type();
+                syn::parse_quote! { Vec<#element_type> }
+            },
+            AethaumType::Custom(type_ref) => {
+                let name = Ident::new(&type_ref.name, Span::call_site());
+                match &type_ref.module_name {
+                    Some(module_name) => {
+                        let module_name = Ident::new(module_name, Span::call_site());
+                        syn::parse_quote! { crate::#module_name::#name }
+                    }
+                    None => syn::parse_quote! { #name },
+                }
             },
-            AethaumType::Custom(custom) => Ident::new(custom, Span::call_site()),
         }
     }
 }
 
+///生成代码中定义的路径，未指定模块或位于当前模块时直接使用名字，否则使用 crate::模块::名字
+fn rust_path(module_name: Option<&str>, name: &str, current_module: &str) -> syn::Path {
+    let name = Ident::new(name, Span::call_site());
+    match module_name {
+        Some(module_name) if module_name != current_module => {
+            let module_name = Ident::new(module_name, Span::call_site());
+            syn::parse_quote! { crate::#module_name::#name }
+        }
+        _ => syn::parse_quote! { #name },
+    }
+}
+///自定义类型（枚举或记录）的引用，未指定模块时在当前模块中查找
+#[derive(Debug, PartialEq, Clone, Eq, Hash)]
+pub struct TypeRef {
+    pub name: SmartString,
+    pub module_name: Option<SmartString>,
+}
+impl TypeRef {
+    pub fn new(module_name: Option<impl Into<SmartString>>, name: impl Into<SmartString>) -> Self {
+        Self { name: name.into(), module_name: module_name.map(|s| s.into()) }
+    }
+    pub fn from_type_str(type_str: &str) -> Self {
+        match type_str.split_once("::") {
+            Some((module_name, name)) => Self::new(Some(module_name), name),
+            None => Self::new(None::<SmartString>, type_str),
+        }
+    }
+    pub fn as_path_str(&self) -> String {
+        match &self.module_name {
+            None => self.name.to_string(),
+            Some(module_name) => format!("{}::{}", module_name, self.name)
+        }
+    }
+}
+impl std::fmt::Display for TypeRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_path_str())
+    }
+}
+
 //Component
 #[derive(Debug,PartialEq, Clone)]
 pub struct ComponentField {
     pub name: SmartString,
     pub type_spec : AethaumType,
     pub default_value: Option<toml::Value>,
-    pub description: Option<SmartString>
+    pub description: Option<SmartString>,
+    pub location: SourceLocation,
 }
 
 #[derive(Debug,PartialEq, Clone)]
 pub struct Component {
     pub name: SmartString,
     pub description: Option<SmartString>,
-    pub fields: Option<Vec<ComponentField>>
+    pub fields: Option<Vec<ComponentField>>,
+    pub location: SourceLocation,
+}
+impl Component {
+    pub fn get_field(&self, field_name: &str) -> Option<&ComponentField> {
+        self.fields.iter().flatten().find(|field| field.name.as_str() == field_name)
+    }
 }
 #[derive(Debug,PartialEq,Clone,Eq,Hash)]
 pub struct ComponentRef {
@@ -146,6 +222,10 @@ impl ComponentRef {
             Some(module_name) => format!("{}::{}", module_name, self.name)
         }
     }
+    ///current_module 为引用所在的模块
+    pub fn to_rust_path(&self, current_module: &str) -> syn::Path {
+        rust_path(self.module_name.as_deref(), &self.name, current_module)
+    }
 }
 impl From<(&str, &str)> for ComponentRef {
     fn from((module_name,name): (&str,&str)) -> Self {
@@ -207,14 +287,18 @@ impl TryFrom<(Option<Vec<SmartString>>, Option<Vec<SmartString>>)> for Component
 pub struct EventField {
     pub name: SmartString,
     pub type_spec : AethaumType,
-    pub description: Option<SmartString>
+    pub description: Option<SmartString>,
+    pub location: SourceLocation,
 }
 
 #[derive(Debug,PartialEq,Clone)]
 pub struct Event {
     pub name: SmartString,
     pub description: Option<SmartString>,
-    pub fields: Option<Vec<EventField>>
+    ///事件的标签，如 "agent-action" 标记可由智能体发送的事件
+    pub tags: Vec<SmartString>,
+    pub fields: Option<Vec<EventField>>,
+    pub location: SourceLocation,
 }
 #[derive(Debug,PartialEq,Clone, Eq, Hash)]
 pub struct EventRef {
@@ -231,6 +315,10 @@ impl EventRef {
             Some(module_name) => format!("{}::{}", module_name, self.name)
         }
     }
+    ///current_module 为引用所在的模块
+    pub fn to_rust_path(&self, current_module: &str) -> syn::Path {
+        rust_path(self.module_name.as_deref(), &self.name, current_module)
+    }
 }
 impl From<(&str,&str)> for EventRef {
     fn from((module_name,name): (&str, &str)) -> Self {
@@ -259,12 +347,65 @@ impl std::fmt::Display for EventRef {
         write!(f, "{}", self.as_path_str())
     }
 }
+//Enum
+#[derive(Debug,PartialEq,Clone)]
+pub struct Enum {
+    pub name: SmartString,
+    pub description: Option<SmartString>,
+    pub variants: Vec<SmartString>,
+    pub location: SourceLocation,
+}
+impl Enum {
+    pub fn has_variant(&self, variant: &str) -> bool {
+        self.variants.iter().any(|v| v.as_str() == variant)
+    }
+}
+
+//Record
+#[derive(Debug,PartialEq,Clone)]
+pub struct Record {
+    pub name: SmartString,
+    pub description: Option<SmartString>,
+    pub fields: Option<Vec<ComponentField>>,
+    pub location: SourceLocation,
+}
+impl Record {
+    pub fn get_field(&self, field_name: &str) -> Option<&ComponentField> {
+        self.fields.iter().flatten().find(|field| field.name.as_str() == field_name)
+    }
+}
+
 //Entity Protos
 #[derive(Debug,PartialEq,Clone)]
 pub struct EntityProto {
     pub name: SmartString,
     pub description: Option<SmartString>,
-    pub components: Vec<ComponentRef>
+    pub extends: Option<EntityProtoRef>, //父原型，检查通过后展开到 components 中
+    pub components: Vec<ProtoComponent>,
+    pub location: SourceLocation,
+}
+///原型包含的组件，overrides 覆盖组件字段的默认值
+#[derive(Debug,PartialEq,Clone)]
+pub struct ProtoComponent {
+    pub component_ref: ComponentRef,
+    pub overrides: BTreeMap<SmartString, toml::Value>,
+}
+impl From<ComponentRef> for ProtoComponent {
+    fn from(component_ref: ComponentRef) -> Self {
+        Self { component_ref, overrides: BTreeMap::new() }
+    }
+}
+impl TryFrom<RawProtoComponent> for ProtoComponent {
+    type Error = anyhow::Error;
+    fn try_from(value: RawProtoComponent) -> Result<Self, Self::Error> {
+        match value {
+            RawProtoComponent::Ref(component) => Ok(ComponentRef::try_from(component)?.into()),
+            RawProtoComponent::WithOverrides { component, overrides } => Ok(Self {
+                component_ref: component.try_into()?,
+                overrides,
+            }),
+        }
+    }
 }
 #[derive(Debug,PartialEq,Clone, Eq, Hash)]
 pub struct EntityProtoRef {
@@ -281,6 +422,10 @@ impl EntityProtoRef {
             Some(module_name) => format!("{}::{}", module_name, self.name)
         }
     }
+    ///current_module 为引用所在的模块
+    pub fn to_rust_path(&self, current_module: &str) -> syn::Path {
+        rust_path(self.module_name.as_deref(), &self.name, current_module)
+    }
 }
 impl From<(&str,&str)> for EntityProtoRef {
     fn from((module_name, name): (&str, &str)) -> Self {
@@ -310,31 +455,64 @@ impl std::fmt::Display for EntityProtoRef {
     }
 }
 //System
-pub type SystemNormal = RawSystemNormal;
+#[derive(Debug,PartialEq,Clone)]
+pub struct SystemNormal {
+    pub name: SmartString,
+    pub description: Option<SmartString>,
+    pub category: Option<SmartString>,
+    pub priority: u32, //优先级高的系统先执行
+}
 #[derive(Debug,PartialEq,Clone)]
 pub struct SystemQuery {
     pub name: SmartString,
     pub description: Option<SmartString>,
-    pub component_constraint: ComponentConstraint
+    pub component_constraint: ComponentConstraint,
+    pub location: SourceLocation,
 }
 #[derive(Debug,PartialEq,Clone)]
 pub struct SystemEventHandler {
     pub watch_for: EventRef,
     pub priority: u32,
-    pub logic: Option<LuaScript>
+    ///只对该查询的实体执行，未指定时对每个查询分别执行
+    pub query: Option<SmartString>,
+    pub logic: Option<LuaScript>,
+    pub location: SourceLocation,
+}
+///update 逻辑的执行方式
+#[derive(Debug,Default,PartialEq,Eq,Clone,Copy)]
+pub enum UpdateMode {
+    ///对查询到的每个实体执行一次，实体视图为 entity
+    #[default]
+    PerEntity,
+    ///每个查询每次更新只执行一次，查询到的实体视图以数组 entities 的形式传入
+    Batch,
+}
+impl UpdateMode {
+    fn parse(mode: Option<&str>) -> Result<Self, anyhow::Error> {
+        match mode {
+            None | Some("entity") => Ok(UpdateMode::PerEntity),
+            Some("batch") => Ok(UpdateMode::Batch),
+            Some(other) => anyhow::bail!("Unknown update mode \"{}\", expected \"entity\" or \"batch\"", other),
+        }
+    }
 }
 #[derive(Debug,PartialEq,Clone)]
 pub struct SystemUpdate {
     pub interval: Duration,
+    pub mode: UpdateMode,
+    ///只对该查询的实体执行，未指定时对每个查询分别执行
+    pub query: Option<SmartString>,
     pub condition: Option<LuaScript>,
-    pub logic: Option<LuaScript>
+    pub logic: Option<LuaScript>,
+    pub location: SourceLocation,
 }
 #[derive(Debug,PartialEq,Clone)]
 pub struct System {
     pub normal: SystemNormal,
     pub queries: Vec<SystemQuery>,
     pub update: Option<SystemUpdate>,
-    pub event_handlers: Vec<SystemEventHandler>
+    pub event_handlers: Vec<SystemEventHandler>,
+    pub location: SourceLocation, //[normal] 表的位置
 }
 #[derive(Debug,PartialEq,Clone, Eq, Hash)]
 pub struct SystemRef {
@@ -396,13 +574,29 @@ impl TomlCode for Event {
         ))
     }
 }
+impl TomlCode for Enum {
+    type RawFile = RawTypeFile;
+    fn from_raw_file((raw_enums, _): <Self::RawFile as RawTomlCodeFile>::RawPieces) -> Result<OneOrMany<Self>, Error> {
+        Ok(OneOrMany::from_iter(
+            raw_enums.into_iter().map(|x| x.into())
+        ))
+    }
+}
+impl TomlCode for Record {
+    type RawFile = RawTypeFile;
+    fn from_raw_file((_, raw_records): <Self::RawFile as RawTomlCodeFile>::RawPieces) -> Result<OneOrMany<Self>, Error> {
+        Ok(OneOrMany::from_iter(
+            raw_records.into_iter().map(|x| x.into())
+        ))
+    }
+}
 impl TomlCode for EntityProto {
     type RawFile = RawEntityProtoFile;
     fn from_raw_file(raw: <Self::RawFile as RawTomlCodeFile>::RawPieces) -> Result<OneOrMany<Self>, Error> {
         let protos: Vec<_> = raw.into_iter().map(|x| x.try_into()).try_collect()?;
         //TODO: remove the try_collect
         Ok(OneOrMany::from_iter(
-            protos.into_iter()
+            protos
         ))
     }
 }
@@ -415,91 +609,152 @@ impl TomlCode for System {
 
 
 //Raw Transformation
-impl From<RawComponentField> for ComponentField {
-    fn from(value: RawComponentField) -> Self {
+impl From<Spanned<RawComponentField>> for ComponentField {
+    fn from(value: Spanned<RawComponentField>) -> Self {
+        let location = SourceLocation::from_span(value.span());
+        let value = value.into_inner();
         ComponentField {
             name: value.name,
             type_spec: AethaumType::from_type_str(&value.type_spec),
             default_value: value.default,
             description: value.description,
+            location,
         }
     }
 }
-impl From<RawComponent> for Component {
-    fn from(value: RawComponent) -> Self {
+impl From<Spanned<RawComponent>> for Component {
+    fn from(value: Spanned<RawComponent>) -> Self {
+        let location = SourceLocation::from_span(value.span());
+        let value = value.into_inner();
         Component {
             name: value.name,
             fields: value.fields.map(|fields| fields.into_iter().map(|x| x.into()).collect()),
             description: value.description,
+            location,
         }
     }
 }
-impl From<RawEventField> for EventField {
-    fn from(value: RawEventField) -> Self {
+impl From<Spanned<RawEventField>> for EventField {
+    fn from(value: Spanned<RawEventField>) -> Self {
+        let location = SourceLocation::from_span(value.span());
+        let value = value.into_inner();
         EventField {
             name: value.name,
             type_spec: AethaumType::from_type_str(&value.type_spec),
             description: value.description,
+            location,
         }
     }
 }
-impl From<RawEvent> for Event {
-    fn from(value: RawEvent) -> Self {
+impl From<Spanned<RawEvent>> for Event {
+    fn from(value: Spanned<RawEvent>) -> Self {
+        let location = SourceLocation::from_span(value.span());
+        let value = value.into_inner();
         Event {
             name: value.name,
             fields: value.fields.map(|fields| fields.into_iter().map(|x| x.into()).collect()),
             description: value.description,
+            tags: value.tags.unwrap_or_default(),
+            location,
         }
     }
 }
-impl TryFrom<RawEntityProto> for EntityProto {
+impl From<Spanned<RawEnum>> for Enum {
+    fn from(value: Spanned<RawEnum>) -> Self {
+        let location = SourceLocation::from_span(value.span());
+        let value = value.into_inner();
+        Enum {
+            name: value.name,
+            description: value.description,
+            variants: value.variants,
+            location,
+        }
+    }
+}
+impl From<Spanned<RawRecord>> for Record {
+    fn from(value: Spanned<RawRecord>) -> Self {
+        let location = SourceLocation::from_span(value.span());
+        let value = value.into_inner();
+        Record {
+            name: value.name,
+            fields: value.fields.map(|fields| fields.into_iter().map(|x| x.into()).collect()),
+            description: value.description,
+            location,
+        }
+    }
+}
+impl TryFrom<Spanned<RawEntityProto>> for EntityProto {
     type Error = anyhow::Error;
-    fn try_from(value: RawEntityProto) -> Result<Self, Self::Error> {
+    fn try_from(value: Spanned<RawEntityProto>) -> Result<Self, Self::Error> {
+        let location = SourceLocation::from_span(value.span());
+        let value = value.into_inner();
         Ok(EntityProto {
             name: value.name,
             description: value.description,
+            extends: value.extends.map(EntityProtoRef::try_from).transpose()?,
             components: value.components.into_iter().map(|x| x.try_into()).try_collect()?,
+            location,
         })
     }
 }
-impl TryFrom<RawSystemQuery> for SystemQuery {
+impl TryFrom<Spanned<RawSystemQuery>> for SystemQuery {
     type Error = anyhow::Error;
-    fn try_from(value: RawSystemQuery) -> Result<Self, Self::Error> {
+    fn try_from(value: Spanned<RawSystemQuery>) -> Result<Self, Self::Error> {
+        let location = SourceLocation::from_span(value.span());
+        let value = value.into_inner();
         Ok(SystemQuery {
             name: value.name,
             description: value.description,
             component_constraint: ComponentConstraint::try_from(
                 (value.components_include, value.components_exclude)
-            )?
+            )?,
+            location,
         })
     }
 }
-impl TryFrom<RawSystemEventHandler> for SystemEventHandler {
+///系统与事件处理器共用的优先级解析，缺省为0
+fn parse_priority(priority: Option<toml::Value>) -> Result<u32, anyhow::Error> {
+    match priority {
+        Some(toml::Value::Integer(i)) => {
+            if i < 0 {
+                anyhow::bail!("Priority must be positive");
+            }
+            u32::try_from(i).map_err(|_| anyhow::anyhow!("Priority must not exceed {}", u32::MAX))
+        },
+        Some(_) => anyhow::bail!("Priority must be an integer"),
+        None => Ok(0)
+    }
+}
+impl TryFrom<RawSystemNormal> for SystemNormal {
+    type Error = anyhow::Error;
+    fn try_from(value: RawSystemNormal) -> Result<Self, Self::Error> {
+        Ok(SystemNormal {
+            name: value.name,
+            description: value.description,
+            category: value.category,
+            priority: parse_priority(value.priority)?,
+        })
+    }
+}
+impl TryFrom<Spanned<RawSystemEventHandler>> for SystemEventHandler {
     type Error = anyhow::Error; //TODO: better error type further
-    fn try_from(value: RawSystemEventHandler) -> Result<Self, Self::Error> {
+    fn try_from(value: Spanned<RawSystemEventHandler>) -> Result<Self, Self::Error> {
+        let location = SourceLocation::from_span(value.span());
+        let value = value.into_inner();
         Ok(SystemEventHandler {
             watch_for: value.watch_for.try_into()?,
-            priority: match value.priority {
-                Some(priority) => {
-                   match priority {
-                       toml::Value::Integer(i) => {
-                           if i < 0 {
-                               return Err(anyhow::anyhow!("Priority must be positive"));
-                           }
-                           i as u32
-                       },
-                       _ => return Err(anyhow::anyhow!("Priority must be an integer")),
-                   }
-                },
-                None => 0
-            },
-            logic: LuaScript::from_embed_or_file(value.logic, value.logic_file.map(|x| PathBuf::from(x.as_str())))? // For Further Version, LuaScript type might not be SmartString
+            priority: parse_priority(value.priority)?,
+            query: value.query,
+            logic: LuaScript::from_embed_or_file(value.logic, value.logic_file.map(|x| PathBuf::from(x.as_str())))?, // For Further Version, LuaScript type might not be SmartString
+            location,
         })
     }
 }
-impl TryFrom<RawSystemUpdate> for SystemUpdate {
+impl TryFrom<Spanned<RawSystemUpdate>> for SystemUpdate {
     type Error = anyhow::Error; //TODO: better error type further
-    fn try_from(value: RawSystemUpdate) -> Result<Self, Self::Error> {
+    fn try_from(value: Spanned<RawSystemUpdate>) -> Result<Self, Self::Error> {
+        let location = SourceLocation::from_span(value.span());
+        let value = value.into_inner();
         Ok(SystemUpdate {
             interval: match value.interval {
                 toml::Value::Integer(i) => {
@@ -516,25 +771,136 @@ impl TryFrom<RawSystemUpdate> for SystemUpdate {
                 }
                 _ => return Err(anyhow::anyhow!("Interval must be a number")),
             },
+            mode: UpdateMode::parse(value.mode.as_deref())?,
+            query: value.query,
             condition: LuaScript::from_embed_or_file(value.condition, value.condition_file.map(|x| PathBuf::from(x.as_str())))?,
             logic: LuaScript::from_embed_or_file(value.logic, value.logic_file.map(|x| PathBuf::from(x.as_str())))?,
+            location,
         })
     }
 }
 impl TryFrom<RawSystem> for System {
     type Error = anyhow::Error; //TODO: better error type further
     fn try_from(value: RawSystem) -> Result<Self, Self::Error> {
+        let location = SourceLocation::from_span(value.normal.span());
         Ok(System {
-            normal: value.normal.into(),
+            normal: value.normal.into_inner().try_into()?,
             queries: value.queries.into_iter().map(|q| q.try_into()).try_collect()?,
             update: value.update.map(TryInto::try_into).transpose()?,
             event_handlers: value.event_handlers
                 .into_iter()
                 .map(|h| h.try_into())
                 .try_collect()?,
+            location,
         })
     }
 }
+//Locatable Implementation
+impl Locatable for ComponentField {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+    fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.location.with_source(source);
+        self
+    }
+}
+impl Locatable for Component {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+    fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.location.with_source(source);
+        self.fields = self.fields.map(|fields| fields.into_iter().map(|field| field.with_source(source)).collect());
+        self
+    }
+}
+impl Locatable for EventField {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+    fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.location.with_source(source);
+        self
+    }
+}
+impl Locatable for Event {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+    fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.location.with_source(source);
+        self.fields = self.fields.map(|fields| fields.into_iter().map(|field| field.with_source(source)).collect());
+        self
+    }
+}
+impl Locatable for Enum {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+    fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.location.with_source(source);
+        self
+    }
+}
+impl Locatable for Record {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+    fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.location.with_source(source);
+        self.fields = self.fields.map(|fields| fields.into_iter().map(|field| field.with_source(source)).collect());
+        self
+    }
+}
+impl Locatable for EntityProto {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+    fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.location.with_source(source);
+        self
+    }
+}
+impl Locatable for SystemQuery {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+    fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.location.with_source(source);
+        self
+    }
+}
+impl Locatable for SystemEventHandler {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+    fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.location.with_source(source);
+        self
+    }
+}
+impl Locatable for SystemUpdate {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+    fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.location.with_source(source);
+        self
+    }
+}
+impl Locatable for System {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+    fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.location.with_source(source);
+        self.queries = self.queries.into_iter().map(|query| query.with_source(source)).collect();
+        self.update = self.update.map(|update| update.with_source(source));
+        self.event_handlers = self.event_handlers.into_iter().map(|handler| handler.with_source(source)).collect();
+        self
+    }
+}
 //Ref Trait Register
 impl AethaumRef for ComponentRef {
     fn to_global_ref(self, module_name: SmartString) -> Self {
@@ -622,21 +988,100 @@ impl From<RawWorldModules> for WorldModules {
 #[derive(Debug,PartialEq,Clone)]
 pub struct WorldBuild {
     pub output_dir: SmartString,
+    ///aethaum-runtime 的源码目录，相对于项目根目录
+    pub runtime: Option<PathBuf>,
 }
 impl From<RawWorldBuild> for WorldBuild {
     fn from(value: RawWorldBuild) -> Self {
         WorldBuild {
             output_dir: value.output_dir,
+            runtime: value.runtime.map(|runtime| PathBuf::from(runtime.as_str())),
         }
     }
 }
-#[derive(Debug,PartialEq,Clone)]
+///生成项目的 Cargo 配置，依赖与 features 会与生成器的默认值合并，同名时以此为准
+#[derive(Debug,Default,PartialEq,Clone)]
 pub struct WorldCargo {
-
+    pub edition: Option<SmartString>,
+    pub dependencies: BTreeMap<SmartString, toml::Value>,
+    pub features: BTreeMap<SmartString, Vec<SmartString>>,
 }
 impl From<RawWorldCargo> for WorldCargo {
-    fn from(_value: RawWorldCargo) -> Self {
-        WorldCargo {}
+    fn from(value: RawWorldCargo) -> Self {
+        WorldCargo {
+            edition: value.edition,
+            dependencies: value.dependencies,
+            features: value.features,
+        }
+    }
+}
+///Lua 脚本沙箱的限制，0 表示不限制
+#[derive(Debug,Default,PartialEq,Clone)]
+pub struct WorldSandbox {
+    ///单次脚本调用最多执行的指令数
+    pub instruction_limit: Option<u64>,
+    ///Lua 虚拟机可以使用的内存（MB）
+    pub memory_limit_mb: Option<u64>,
+}
+impl From<RawWorldSandbox> for WorldSandbox {
+    fn from(value: RawWorldSandbox) -> Self {
+        WorldSandbox {
+            instruction_limit: value.instruction_limit,
+            memory_limit_mb: value.memory_limit_mb,
+        }
+    }
+}
+///强化学习环境，每回合重新构建世界并生成初始场景
+#[derive(Debug,PartialEq,Clone)]
+pub struct WorldEnvironment {
+    ///回合最多的步数，达到后回合被截断
+    pub max_steps: Option<u64>,
+    ///每次 step 推进的帧数
+    pub frames_per_step: u32,
+    ///每步之后求值的 Lua 表达式，查询结果以查询名作为变量，缺省时奖励为0
+    pub reward: Option<SmartString>,
+    ///与 reward 相同，结果为真时回合结束
+    pub done: Option<SmartString>,
+    pub scene: Vec<EnvironmentSpawn>,
+    ///组件引用需要带模块限定
+    pub queries: Vec<SystemQuery>,
+}
+impl TryFrom<RawWorldEnvironment> for WorldEnvironment {
+    type Error = anyhow::Error;
+    fn try_from(value: RawWorldEnvironment) -> Result<Self, Self::Error> {
+        let frames_per_step = value.frames_per_step.unwrap_or(1);
+        if frames_per_step == 0 {
+            anyhow::bail!("frames_per_step must be positive");
+        }
+        Ok(WorldEnvironment {
+            max_steps: value.max_steps,
+            frames_per_step,
+            reward: value.reward,
+            done: value.done,
+            scene: value.scene.into_iter().flatten().map(EnvironmentSpawn::try_from).try_collect()?,
+            queries: value.queries.into_iter().flatten().map(SystemQuery::try_from).try_collect()?,
+        })
+    }
+}
+///初始场景中的实体，overrides 与 aethaum.spawn 中的覆盖相同：组件名（蛇形）-> { 字段名: 值 }
+#[derive(Debug,PartialEq,Clone)]
+pub struct EnvironmentSpawn {
+    pub prototype: EntityProtoRef,
+    pub count: u32,
+    pub overrides: Option<toml::Table>,
+    pub location: SourceLocation,
+}
+impl TryFrom<Spanned<RawEnvironmentSpawn>> for EnvironmentSpawn {
+    type Error = anyhow::Error;
+    fn try_from(value: Spanned<RawEnvironmentSpawn>) -> Result<Self, Self::Error> {
+        let location = SourceLocation::from_span(value.span());
+        let value = value.into_inner();
+        Ok(EnvironmentSpawn {
+            prototype: EntityProtoRef::try_from(value.prototype)?,
+            count: value.count.unwrap_or(1),
+            overrides: value.overrides,
+            location,
+        })
     }
 }
 #[derive(Debug,PartialEq,Clone)]
@@ -645,15 +1090,34 @@ pub struct World {
     pub modules: WorldModules,
     pub build: Option<WorldBuild>,
     pub cargo: Option<WorldCargo>,
+    pub sandbox: Option<WorldSandbox>,
+    pub environment: Option<WorldEnvironment>,
+}
+impl World {
+    ///补全 [environment] 中定义的源文件
+    pub fn with_source(mut self, source: &SourceFile) -> Self {
+        if let Some(environment) = &mut self.environment {
+            for spawn in environment.scene.iter_mut() {
+                spawn.location = spawn.location.clone().with_source(source);
+            }
+            for query in environment.queries.iter_mut() {
+                query.location = query.location.clone().with_source(source);
+            }
+        }
+        self
+    }
 }
-impl From<RawWorld> for World {
-    fn from(value: RawWorld) -> Self {
-        World {
+impl TryFrom<RawWorld> for World {
+    type Error = anyhow::Error;
+    fn try_from(value: RawWorld) -> Result<Self, Self::Error> {
+        Ok(World {
             normal: value.normal.into(),
             modules: value.modules.into(),
             build: value.build.map(Into::into),
             cargo: value.cargo.map(Into::into),
-        }
+            sandbox: value.sandbox.map(Into::into),
+            environment: value.environment.map(WorldEnvironment::try_from).transpose()?,
+        })
     }
 }
 //Field Trait Implementation
@@ -662,8 +1126,8 @@ impl Field for ComponentField {
         Ident::new(&self.name, Span::call_site())
     }
 
-    fn type_as_rust_ident(&self) -> Ident {
-        self.type_spec.to_rust_type()
+    fn type_spec(&self) -> &AethaumType {
+        &self.type_spec
     }
 }
 impl Field for &ComponentField {
@@ -671,8 +1135,8 @@ impl Field for &ComponentField {
         Ident::new(&self.name, Span::call_site())
     }
 
-    fn type_as_rust_ident(&self) -> Ident {
-        self.type_spec.to_rust_type()
+    fn type_spec(&self) -> &AethaumType {
+        &self.type_spec
     }
 }
 impl Field for EventField {
@@ -680,8 +1144,8 @@ impl Field for EventField {
         Ident::new(&self.name, Span::call_site())
     }
 
-    fn type_as_rust_ident(&self) -> Ident {
-        self.type_spec.to_rust_type()
+    fn type_spec(&self) -> &AethaumType {
+        &self.type_spec
     }
 }
 impl Field for &EventField {
@@ -689,8 +1153,8 @@ impl Field for &EventField {
         Ident::new(&self.name, Span::call_site())
     }
 
-    fn type_as_rust_ident(&self) -> Ident {
-        self.type_spec.to_rust_type()
+    fn type_spec(&self) -> &AethaumType {
+        &self.type_spec
     }
 }
 //Describable trait
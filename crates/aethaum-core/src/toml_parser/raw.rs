@@ -1,7 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 ///原始toml解析，类型，模块路径在后续处理
 use serde::{Deserialize, Serialize};
 use smart_string::SmartString;
+use toml::Spanned;
 
 pub trait RawTomlCodeFile: Sized {
     type RawPieces: for<'de> Deserialize<'de>; //TODO: try using Iterator
@@ -27,7 +28,7 @@ pub struct RawComponentField {
 pub struct RawComponent {
     pub name: SmartString,
     pub description: Option<SmartString>,
-    pub fields: Option<Vec<RawComponentField>>,
+    pub fields: Option<Vec<Spanned<RawComponentField>>>,
 }
 
 #[derive(Debug,Serialize,Deserialize)]
@@ -35,10 +36,10 @@ pub struct RawComponentFile {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub normal: Option<RawNormal>,
     #[serde(rename = "components")]
-    pub component_list: Vec<RawComponent>,
+    pub component_list: Vec<Spanned<RawComponent>>,
 }
 impl RawTomlCodeFile for RawComponentFile {
-    type RawPieces = Vec<RawComponent>;
+    type RawPieces = Vec<Spanned<RawComponent>>;
     fn into_pieces(self) -> Self::RawPieces {
         self.component_list
     }
@@ -55,35 +56,84 @@ pub struct RawEventField {
 pub struct RawEvent {
     pub name: SmartString,
     pub description: Option<SmartString>,
-    pub fields: Option<Vec<RawEventField>>,
+    pub tags: Option<Vec<SmartString>>,
+    pub fields: Option<Vec<Spanned<RawEventField>>>,
 }
 #[derive(Debug,Serialize,Deserialize)]
 pub struct RawEventFile {
     pub normal: Option<RawNormal>,
     #[serde(rename = "events")]
-    pub event_list: Vec<RawEvent>
+    pub event_list: Vec<Spanned<RawEvent>>
 }
 impl RawTomlCodeFile for RawEventFile {
-    type RawPieces = Vec<RawEvent>;
+    type RawPieces = Vec<Spanned<RawEvent>>;
     fn into_pieces(self) -> Self::RawPieces {
         self.event_list
     }
 }
+//Enum
+#[derive(Debug,Serialize,Deserialize)]
+pub struct RawEnum {
+    pub name: SmartString,
+    pub description: Option<SmartString>,
+    pub variants: Vec<SmartString>,
+}
+//Record
+#[derive(Debug,Serialize,Deserialize)]
+pub struct RawRecord {
+    pub name: SmartString,
+    pub description: Option<SmartString>,
+    pub fields: Option<Vec<Spanned<RawComponentField>>>,
+}
+#[derive(Debug,Serialize,Deserialize)]
+pub struct RawTypeFile {
+    pub normal: Option<RawNormal>,
+    #[serde(rename = "enums", default)]
+    pub enum_list: Vec<Spanned<RawEnum>>,
+    #[serde(rename = "records", default)]
+    pub record_list: Vec<Spanned<RawRecord>>,
+    //简写形式：Faction = ["Ally", "Enemy", "Neutral"]
+    //flatten 的字段拿不到位置信息，加载时再补全
+    #[serde(flatten)]
+    pub shorthand_enums: BTreeMap<SmartString, Vec<SmartString>>,
+}
+impl RawTomlCodeFile for RawTypeFile {
+    type RawPieces = (Vec<Spanned<RawEnum>>, Vec<Spanned<RawRecord>>); //(枚举，记录)
+    fn into_pieces(self) -> Self::RawPieces {
+        let shorthand_enums = self.shorthand_enums.into_iter()
+            .map(|(name, variants)| Spanned::new(0..0, RawEnum { name, description: None, variants }));
+        (self.enum_list.into_iter().chain(shorthand_enums).collect(), self.record_list)
+    }
+}
 //EntityProto
 #[derive(Debug,Serialize,Deserialize)]
 pub struct RawEntityProto {
     pub name: SmartString,
     pub description: Option<SmartString>,
-    pub components: Vec<SmartString>,
+    pub extends: Option<SmartString>,
+    #[serde(default)]
+    pub components: Vec<RawProtoComponent>,
+}
+///原型中的组件，可以只写组件引用，也可以用内联表覆盖字段的初始值
+#[derive(Debug,Serialize,Deserialize)]
+#[serde(untagged)]
+pub enum RawProtoComponent {
+    Ref(SmartString),
+    WithOverrides {
+        #[serde(rename = "ref")]
+        component: SmartString,
+        #[serde(flatten)]
+        overrides: BTreeMap<SmartString, toml::Value>,
+    },
 }
 #[derive(Debug,Serialize,Deserialize)]
 pub struct RawEntityProtoFile {
     pub normal: Option<RawNormal>,
     #[serde(rename = "entity_protos")]
-    pub entity_proto_list: Vec<RawEntityProto>
+    pub entity_proto_list: Vec<Spanned<RawEntityProto>>
 }
 impl RawTomlCodeFile for RawEntityProtoFile {
-    type RawPieces = Vec<RawEntityProto>;
+    type RawPieces = Vec<Spanned<RawEntityProto>>;
     fn into_pieces(self) -> Self::RawPieces {
         self.entity_proto_list
     }
@@ -101,12 +151,15 @@ pub struct RawSystemQuery {
 pub struct RawSystemEventHandler {
     pub watch_for: SmartString,
     pub priority: Option<toml::Value>,
+    pub query: Option<SmartString>,
     pub logic: Option<SmartString>,
     pub logic_file: Option<SmartString>
 }
 #[derive(Debug,Serialize,Deserialize)]
 pub struct RawSystemUpdate {
     pub interval: toml::Value,
+    pub mode: Option<SmartString>,
+    pub query: Option<SmartString>,
     pub condition: Option<SmartString>,
     pub condition_file: Option<SmartString>,
     pub logic: Option<SmartString>,
@@ -121,10 +174,10 @@ pub struct RawSystemNormal {
 }
 #[derive(Debug,Serialize,Deserialize)]
 pub struct RawSystem {
-    pub normal: RawSystemNormal,
-    pub queries: Vec<RawSystemQuery>,
-    pub update: Option<RawSystemUpdate>,
-    pub event_handlers: Vec<RawSystemEventHandler>,
+    pub normal: Spanned<RawSystemNormal>,
+    pub queries: Vec<Spanned<RawSystemQuery>>,
+    pub update: Option<Spanned<RawSystemUpdate>>,
+    pub event_handlers: Vec<Spanned<RawSystemEventHandler>>,
 }
 pub type RawSystemFile = RawSystem;
 impl RawTomlCodeFile for RawSystemFile {
@@ -147,10 +200,39 @@ pub struct RawWorldModules {
 #[derive(Debug,Serialize,Deserialize)]
 pub struct RawWorldBuild {
     pub output_dir: SmartString,
+    pub runtime: Option<SmartString>,
 }
-#[derive(Debug,Serialize,Deserialize)]
+///原样传递给生成项目 Cargo.toml 的配置
+#[derive(Debug,Default,Serialize,Deserialize)]
 pub struct RawWorldCargo {
-
+    pub edition: Option<SmartString>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<SmartString, toml::Value>,
+    #[serde(default)]
+    pub features: BTreeMap<SmartString, Vec<SmartString>>,
+}
+///Lua 脚本沙箱的限制，未设置的项使用运行时的默认值
+#[derive(Debug,Default,Serialize,Deserialize)]
+pub struct RawWorldSandbox {
+    pub instruction_limit: Option<u64>,
+    pub memory_limit_mb: Option<u64>,
+}
+///强化学习环境：每回合生成的初始场景，以及基于查询的奖励与终止条件
+#[derive(Debug,Default,Serialize,Deserialize)]
+pub struct RawWorldEnvironment {
+    pub max_steps: Option<u64>,
+    pub frames_per_step: Option<u32>,
+    pub reward: Option<SmartString>,
+    pub done: Option<SmartString>,
+    pub scene: Option<Vec<Spanned<RawEnvironmentSpawn>>>,
+    pub queries: Option<Vec<Spanned<RawSystemQuery>>>,
+}
+///初始场景中按原型生成的实体
+#[derive(Debug,Serialize,Deserialize)]
+pub struct RawEnvironmentSpawn {
+    pub prototype: SmartString,
+    pub count: Option<u32>,
+    pub overrides: Option<toml::Table>,
 }
 #[derive(Debug,Serialize,Deserialize)]
 pub struct RawWorld {
@@ -160,8 +242,10 @@ pub struct RawWorld {
     pub modules: RawWorldModules,
     pub build: Option<RawWorldBuild>,
     pub cargo: Option<RawWorldCargo>,
+    pub sandbox: Option<RawWorldSandbox>,
+    pub environment: Option<RawWorldEnvironment>,
 }
-type RawWorldFile = RawWorld;
+pub type RawWorldFile = RawWorld;
 
 #[cfg(test)]
 mod test {
@@ -250,7 +334,7 @@ mod test {
         description = "治疗者ID"
         "#;
         let raw_event : RawEventFile = toml::from_str(toml_str).unwrap();
-        assert_eq!(raw_event.event_list[0].name, "EntityDamaged");
+        assert_eq!(raw_event.event_list[0].get_ref().name, "EntityDamaged");
         assert_eq!(raw_event.event_list.len(), 2);
     }
     #[test]
@@ -283,6 +367,76 @@ mod test {
         assert_eq!(raw_entity_proto.entity_proto_list.len(), 3);
     }
     #[test]
+    fn test_parse_entity_proto_overrides() {
+        let toml_str = r#"
+        [[entity_protos]]
+        name = "Boss"
+        components = [{ ref = "combat::Health", value = 150.0, max_value = 150.0 }, "Position"]
+        "#;
+        let raw_entity_proto : RawEntityProtoFile = toml::from_str(toml_str).unwrap();
+        let components = &raw_entity_proto.entity_proto_list[0].get_ref().components;
+        match &components[0] {
+            RawProtoComponent::WithOverrides { component, overrides } => {
+                assert_eq!(component.as_str(), "combat::Health");
+                assert_eq!(overrides.len(), 2);
+                assert_eq!(overrides["value"].as_float(), Some(150.0));
+            }
+            other => panic!("Expected overrides, got {:?}", other),
+        }
+        assert!(matches!(&components[1], RawProtoComponent::Ref(component) if component.as_str() == "Position"));
+    }
+    #[test]
+    fn test_parse_entity_proto_extends() {
+        let toml_str = r#"
+        [[entity_protos]]
+        name = "EliteEnemy"
+        extends = "combat::Enemy"
+        "#;
+        let raw_entity_proto : RawEntityProtoFile = toml::from_str(toml_str).unwrap();
+        let proto = raw_entity_proto.entity_proto_list[0].get_ref();
+        assert_eq!(proto.extends.as_deref(), Some("combat::Enemy"));
+        assert!(proto.components.is_empty());
+    }
+    #[test]
+    fn test_parse_type_file() {
+        let toml_str = r#"
+        Faction = ["Ally", "Enemy", "Neutral"]
+
+        [normal]
+        description = "阵营相关类型"
+
+        [[enums]]
+        name = "DamageKind"
+        description = "伤害类型"
+        variants = ["Physical", "Magic"]
+
+        [[records]]
+        name = "Vec2"
+        description = "二维向量"
+
+        [[records.fields]]
+        name = "x"
+        type = "float"
+        default = 0.0
+
+        [[records.fields]]
+        name = "y"
+        type = "float"
+        default = 0.0
+        "#;
+        let raw_type_file: RawTypeFile = toml::from_str(toml_str).unwrap();
+        let (enums, records) = raw_type_file.into_pieces();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].get_ref().fields.as_ref().unwrap().len(), 2);
+        assert_eq!(enums.len(), 2);
+        assert_eq!(enums[0].get_ref().name, "DamageKind");
+        assert_eq!(enums[1].get_ref().name, "Faction");
+        assert_eq!(enums[1].get_ref().variants, vec!["Ally", "Enemy", "Neutral"]);
+        //表头的位置会被保留
+        assert_eq!(&toml_str[enums[0].span()], "[[enums]]");
+        assert_eq!(&toml_str[records[0].span()], "[[records]]");
+    }
+    #[test]
     fn test_parse_system_file() {
         let toml_str = r#"
         #一个toml文件中，只能定义一个系统
@@ -339,10 +493,10 @@ mod test {
         let raw_system : RawSystemFile = toml::from_str(toml_str).unwrap();
         assert_eq!(raw_system.event_handlers.len(), 2);
         assert_eq!(raw_system.queries.len(),2);
-        assert_eq!(raw_system.event_handlers[0].watch_for, "EntityDamaged");
-        assert_eq!(raw_system.event_handlers[1].watch_for, "EntityHealed");
-        assert_eq!(raw_system.queries[0].name, "living_entities");
-        assert_eq!(raw_system.queries[1].name, "damaged_entities");
+        assert_eq!(raw_system.event_handlers[0].get_ref().watch_for, "EntityDamaged");
+        assert_eq!(raw_system.event_handlers[1].get_ref().watch_for, "EntityHealed");
+        assert_eq!(raw_system.queries[0].get_ref().name, "living_entities");
+        assert_eq!(raw_system.queries[1].get_ref().name, "damaged_entities");
     }
     #[test]
     fn test_parse_world_file() {
@@ -358,6 +512,7 @@ mod test {
 
         [build]
         output_dir = "generated"
+        runtime = "../aethaum/crates/aethaum-runtime"
 
         [cargo]
         # 标准 Cargo 配置
@@ -366,7 +521,85 @@ mod test {
         assert_eq!(raw_world.normal.name, "MyAIWorld");
         assert_eq!(raw_world.normal.version, "0.1.0");
         assert_eq!(raw_world.normal.author, "Your Name");
-        assert_eq!(raw_world.build.unwrap().output_dir, "generated");
+        let build = raw_world.build.unwrap();
+        assert_eq!(build.output_dir, "generated");
+        assert_eq!(build.runtime.as_deref(), Some("../aethaum/crates/aethaum-runtime"));
+        assert!(raw_world.cargo.unwrap().dependencies.is_empty());
         println!("{:?}", raw_world.modules)
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_parse_world_cargo() {
+        let toml_str = r#"
+        [world]
+        name = "MyAIWorld"
+        version = "0.1.0"
+        author = "Your Name"
+
+        [modules]
+
+        [cargo]
+        edition = "2021"
+
+        [cargo.dependencies]
+        rand = "0.8"
+        serde = { version = "1.0", features = ["derive"] }
+
+        [cargo.features]
+        debug_view = []
+        "#;
+        let cargo = toml::from_str::<RawWorldFile>(toml_str).unwrap().cargo.unwrap();
+        assert_eq!(cargo.edition.as_deref(), Some("2021"));
+        assert_eq!(cargo.dependencies["rand"].as_str(), Some("0.8"));
+        assert!(cargo.dependencies["serde"].is_table());
+        assert_eq!(cargo.features["debug_view"], Vec::<SmartString>::new());
+    }
+    #[test]
+    fn test_parse_world_sandbox() {
+        let toml_str = r#"
+        [world]
+        name = "MyAIWorld"
+        version = "0.1.0"
+        author = "Your Name"
+
+        [modules]
+
+        [sandbox]
+        instruction_limit = 500000
+        "#;
+        let sandbox = toml::from_str::<RawWorldFile>(toml_str).unwrap().sandbox.unwrap();
+        assert_eq!(sandbox.instruction_limit, Some(500000));
+        assert_eq!(sandbox.memory_limit_mb, None);
+    }
+    #[test]
+    fn test_parse_world_environment() {
+        let toml_str = r##"
+        [world]
+        name = "MyAIWorld"
+        version = "0.1.0"
+        author = "Your Name"
+
+        [modules]
+
+        [environment]
+        max_steps = 200
+        reward = "#survivors"
+        done = "#survivors == 0"
+
+        [[environment.scene]]
+        prototype = "explore::Enemy"
+        count = 3
+        overrides = { health = { value = 50.0 } }
+
+        [[environment.queries]]
+        name = "survivors"
+        components_include = ["combat::Health"]
+        "##;
+        let environment = toml::from_str::<RawWorldFile>(toml_str).unwrap().environment.unwrap();
+        assert_eq!(environment.max_steps, Some(200));
+        assert_eq!(environment.frames_per_step, None);
+        let scene = environment.scene.unwrap();
+        assert_eq!(scene[0].get_ref().count, Some(3));
+        assert!(scene[0].get_ref().overrides.as_ref().unwrap()["health"].is_table());
+        assert_eq!(environment.queries.unwrap()[0].get_ref().name, "survivors");
+    }
+}
@@ -0,0 +1,78 @@
+use std::ops::Range;
+use std::sync::{Arc, LazyLock};
+use miette::{NamedSource, SourceSpan};
+
+///定义所在的源文件，同一文件中的定义共享一份内容
+pub type SourceFile = Arc<NamedSource<String>>;
+
+static UNKNOWN_SOURCE: LazyLock<SourceFile> = LazyLock::new(|| source_file("<unknown>", String::new()));
+
+pub fn source_file(name: impl AsRef<str>, content: String) -> SourceFile {
+    Arc::new(NamedSource::new(name, content).with_language("toml"))
+}
+
+///定义在源文件中的位置，用于诊断信息
+#[derive(Debug, Clone)]
+pub struct SourceLocation {
+    pub source: SourceFile,
+    pub span: SourceSpan,
+}
+impl SourceLocation {
+    pub fn new(source: SourceFile, span: impl Into<SourceSpan>) -> Self {
+        Self { source, span: span.into() }
+    }
+    ///只有位置，源文件在加载时通过 Locatable::with_source 补全
+    pub fn from_span(span: Range<usize>) -> Self {
+        Self::new(UNKNOWN_SOURCE.clone(), span)
+    }
+    pub fn with_source(mut self, source: &SourceFile) -> Self {
+        self.source = source.clone();
+        self
+    }
+    pub fn file_name(&self) -> &str {
+        self.source.name()
+    }
+}
+impl Default for SourceLocation {
+    fn default() -> Self {
+        Self::from_span(0..0)
+    }
+}
+impl PartialEq for SourceLocation {
+    fn eq(&self, other: &Self) -> bool {
+        self.file_name() == other.file_name() && self.span == other.span
+    }
+}
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", display_location(&self.source, &self.span))
+    }
+}
+///以 文件:行:列 的形式显示位置，行列从1开始
+pub fn display_location(source: &SourceFile, span: &SourceSpan) -> String {
+    let content = source.inner();
+    let offset = span.offset().min(content.len());
+    let before = &content[..content.floor_char_boundary(offset)];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    format!("{}:{}:{}", source.name(), line, column)
+}
+
+///可以在加载后补全源文件的定义
+pub trait Locatable: Sized {
+    fn location(&self) -> &SourceLocation;
+    ///为自身及其子定义设置源文件
+    fn with_source(self, source: &SourceFile) -> Self;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_display_location() {
+        let source = source_file("health.toml", "[normal]\n\n[[components]]\nname = \"Health\"\n".into());
+        assert_eq!(SourceLocation::new(source.clone(), 10..24).to_string(), "health.toml:3:1");
+        assert_eq!(SourceLocation::new(source, 32..40).to_string(), "health.toml:4:8");
+        assert_eq!(SourceLocation::default().to_string(), "<unknown>:1:1");
+    }
+}
@@ -0,0 +1,188 @@
+use std::marker::PhantomData;
+use serde::de::{DeserializeOwned, IgnoredAny, MapAccess, SeqAccess, Unexpected, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+use crate::entity::LuaEntity;
+use crate::spawn::SpawnOverrides;
+
+#[derive(Debug, Error)]
+pub enum EmitError {
+    #[error("Unknown event '{0}'")]
+    UnknownEvent(String),
+    #[error("Invalid fields for event '{event}': {source}")]
+    InvalidFields {
+        event: String,
+        source: serde_json::Error,
+    },
+}
+
+///由字段表构造事件，供生成代码中的 emit_event 使用
+pub fn deserialize_event<E: DeserializeOwned>(event: &str, fields: Value) -> Result<E, EmitError> {
+    serde_json::from_value(fields).map_err(|source| EmitError::InvalidFields { event: event.to_string(), source })
+}
+
+///列表字段的反序列化，Lua 中的空表会被转换为空对象，这里将其视为空列表
+pub fn deserialize_list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct ListVisitor<T>(PhantomData<T>);
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for ListVisitor<T> {
+        type Value = Vec<T>;
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a list")
+        }
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+            while let Some(element) = seq.next_element()? {
+                elements.push(element);
+            }
+            Ok(elements)
+        }
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            match map.next_key::<IgnoredAny>()? {
+                None => Ok(Vec::new()),
+                Some(_) => Err(serde::de::Error::invalid_type(Unexpected::Map, &self)),
+            }
+        }
+    }
+    deserializer.deserialize_any(ListVisitor(PhantomData))
+}
+///将Lua值转换为JSON，连续整数键的表视为数组，实体句柄转换为实体的位表示
+pub fn lua_to_json(value: &mlua::Value) -> mlua::Result<Value> {
+    match value {
+        mlua::Value::Nil => Ok(Value::Null),
+        mlua::Value::Boolean(value) => Ok(Value::Bool(*value)),
+        mlua::Value::Integer(value) => Ok(Value::from(*value)),
+        mlua::Value::Number(value) => Number::from_f64(*value)
+            .map(Value::Number)
+            .ok_or_else(|| mlua::Error::runtime(format!("cannot convert {} to a JSON number", value))),
+        mlua::Value::String(value) => Ok(Value::String(value.to_str()?.to_string())),
+        mlua::Value::Table(table) => table_to_json(table),
+        mlua::Value::UserData(userdata) if userdata.is::<LuaEntity>() => {
+            Ok(Value::from(userdata.borrow::<LuaEntity>()?.0.to_bits()))
+        }
+        other => Err(mlua::Error::runtime(format!("cannot convert a Lua {} to a field value", other.type_name()))),
+    }
+}
+fn table_to_json(table: &mlua::Table) -> mlua::Result<Value> {
+    let pairs = table.pairs::<mlua::Value, mlua::Value>().collect::<mlua::Result<Vec<_>>>()?;
+    let length = table.raw_len();
+    if length > 0 && length == pairs.len() {
+        return (1..=length)
+            .map(|index| lua_to_json(&table.raw_get::<mlua::Value>(index)?))
+            .collect::<mlua::Result<Vec<_>>>()
+            .map(Value::Array);
+    }
+    let mut object = Map::new();
+    for (key, value) in pairs {
+        let mlua::Value::String(key) = key else {
+            return Err(mlua::Error::runtime(format!("table keys must be strings, found a {}", key.type_name())));
+        };
+        object.insert(key.to_str()?.to_string(), lua_to_json(&value)?);
+    }
+    Ok(Value::Object(object))
+}
+///将JSON转换为Lua值，数组转换为序列，实体的位表示保持为整数
+pub fn json_to_lua(lua: &mlua::Lua, value: &Value) -> mlua::Result<mlua::Value> {
+    match value {
+        Value::Null => Ok(mlua::Value::Nil),
+        Value::Bool(value) => Ok(mlua::Value::Boolean(*value)),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => Ok(mlua::Value::Integer(value)),
+            None => Ok(mlua::Value::Number(number.as_f64().unwrap_or(f64::NAN))),
+        },
+        Value::String(value) => lua.create_string(value).map(mlua::Value::String),
+        Value::Array(values) => {
+            let table = lua.create_table_with_capacity(values.len(), 0)?;
+            for value in values {
+                table.raw_push(json_to_lua(lua, value)?)?;
+            }
+            Ok(mlua::Value::Table(table))
+        }
+        Value::Object(fields) => {
+            let table = lua.create_table_with_capacity(0, fields.len())?;
+            for (name, value) in fields {
+                table.raw_set(name.as_str(), json_to_lua(lua, value)?)?;
+            }
+            Ok(mlua::Value::Table(table))
+        }
+    }
+}
+///将 { 组件名 = { 字段名 = 值 } } 形式的Lua表转换为生成实体时的覆盖
+pub fn lua_to_overrides(value: &mlua::Value) -> mlua::Result<SpawnOverrides> {
+    match lua_to_json(value)? {
+        Value::Object(overrides) => Ok(overrides),
+        _ => Err(mlua::Error::runtime("overrides must be a table of components")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::entity::Entity;
+    use bevy_ecs::world::World;
+    use serde::Deserialize;
+    use serde_json::json;
+    use super::*;
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct EntityDied {
+        entity: Entity,
+        cause: String,
+    }
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Loot {
+        #[serde(deserialize_with = "deserialize_list")]
+        items: Vec<i64>,
+    }
+    #[test]
+    fn test_lua_to_json() {
+        let lua = mlua::Lua::new();
+        let value = lua.load(r#"return { health = { value = 50 }, tags = { "a", "b" }, speed = 1.5, empty = {} }"#)
+            .eval::<mlua::Value>()
+            .unwrap();
+        assert_eq!(
+            lua_to_json(&value).unwrap(),
+            json!({ "health": { "value": 50 }, "tags": ["a", "b"], "speed": 1.5, "empty": {} })
+        );
+        let mixed = lua.load("return { 1, 2, x = 3 }").eval::<mlua::Value>().unwrap();
+        assert!(lua_to_json(&mixed).is_err());
+        let function = lua.load("return print").eval::<mlua::Value>().unwrap();
+        assert!(lua_to_json(&function).is_err());
+        let list = lua.load("return { 1, 2 }").eval::<mlua::Value>().unwrap();
+        assert!(lua_to_overrides(&list).is_err());
+    }
+    #[test]
+    fn test_deserialize_empty_list() {
+        let lua = mlua::Lua::new();
+        let fields = |source: &str| lua_to_json(&lua.load(source).eval::<mlua::Value>().unwrap()).unwrap();
+        assert_eq!(deserialize_event::<Loot>("Loot", fields("return { items = {} }")).unwrap(), Loot { items: vec![] });
+        assert_eq!(deserialize_event::<Loot>("Loot", fields("return { items = { 1, 2 } }")).unwrap(), Loot { items: vec![1, 2] });
+        // 非空对象仍然不是列表
+        assert!(deserialize_event::<Loot>("Loot", fields("return { items = { a = 1 } }")).is_err());
+        assert!(deserialize_event::<Loot>("Loot", json!({})).is_err());
+    }
+    #[test]
+    fn test_json_to_lua() {
+        let lua = mlua::Lua::new();
+        let value = json!({ "health": { "value": 50.5, "max": 100 }, "tags": ["a", "b"], "alive": true });
+        lua.globals().set("value", json_to_lua(&lua, &value).unwrap()).unwrap();
+        assert!(lua.load(r#"return value.health.value == 50.5 and math.type(value.health.max) == "integer" and #value.tags == 2 and value.alive"#).eval::<bool>().unwrap());
+        assert_eq!(lua_to_json(&lua.globals().get::<mlua::Value>("value").unwrap()).unwrap(), value);
+    }
+    #[test]
+    fn test_deserialize_event_with_entity() {
+        let mut world = World::new();
+        let entity = world.spawn_empty().id();
+        let lua = mlua::Lua::new();
+        lua.globals().set("target", LuaEntity(entity)).unwrap();
+        let fields = lua.load(r#"return { entity = target, cause = "poison" }"#).eval::<mlua::Value>().unwrap();
+        let event = deserialize_event::<EntityDied>("EntityDied", lua_to_json(&fields).unwrap()).unwrap();
+        assert_eq!(event, EntityDied { entity, cause: "poison".to_string() });
+        assert!(matches!(
+            deserialize_event::<EntityDied>("EntityDied", json!({ "cause": "poison" })),
+            Err(EmitError::InvalidFields { .. })
+        ));
+    }
+}
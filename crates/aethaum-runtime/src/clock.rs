@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+use bevy_ecs::prelude::*;
+
+///单个系统一帧内最多补跑的次数，防止长时间卡顿后出现"死亡螺旋"
+pub const MAX_RUNS_PER_TICK: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockMode {
+    ///按真实时间推进
+    Realtime { last: Option<Instant> },
+    ///每帧推进固定的时长，用于可复现的模拟与智能体步进
+    Fixed(Duration),
+}
+///世界时钟，每帧在 First 阶段推进一次
+#[derive(Resource, Debug, Clone)]
+pub struct WorldClock {
+    mode: ClockMode,
+    delta: Duration,
+    elapsed: Duration,
+}
+impl Default for WorldClock {
+    fn default() -> Self {
+        Self::realtime()
+    }
+}
+impl WorldClock {
+    pub fn realtime() -> Self {
+        Self {
+            mode: ClockMode::Realtime { last: None },
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+        }
+    }
+    pub fn fixed(step: Duration) -> Self {
+        Self {
+            mode: ClockMode::Fixed(step),
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+        }
+    }
+    pub fn mode(&self) -> ClockMode {
+        self.mode
+    }
+    ///上一帧到当前帧经过的时间
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+    ///世界启动以来经过的总时间
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+    pub fn advance_by(&mut self, delta: Duration) {
+        self.delta = delta;
+        self.elapsed += delta;
+    }
+    fn advance_to(&mut self, now: Instant) {
+        match &mut self.mode {
+            ClockMode::Realtime { last } => {
+                let delta = last.map(|last| now.saturating_duration_since(last)).unwrap_or_default();
+                *last = Some(now);
+                self.advance_by(delta);
+            }
+            ClockMode::Fixed(step) => {
+                let step = *step;
+                self.advance_by(step);
+            }
+        }
+    }
+}
+pub fn advance_world_clock(mut clock: ResMut<WorldClock>) {
+    clock.advance_to(Instant::now());
+}
+
+///固定间隔计时器，作为生成系统的 Local 状态使用
+#[derive(Debug, Default, Clone)]
+pub struct IntervalTimer {
+    accumulated: Duration,
+}
+impl IntervalTimer {
+    ///累计经过的时间，返回本帧到期的次数；间隔为零时每帧执行一次
+    pub fn tick(&mut self, delta: Duration, interval: Duration) -> u32 {
+        if interval.is_zero() {
+            return 1;
+        }
+        self.accumulated += delta;
+        let due = self.accumulated.as_nanos() / interval.as_nanos();
+        if due >= MAX_RUNS_PER_TICK as u128 {
+            // 超出补跑上限的部分直接丢弃
+            self.accumulated = Duration::ZERO;
+            return MAX_RUNS_PER_TICK;
+        }
+        let due = due as u32;
+        self.accumulated -= interval * due;
+        due
+    }
+    pub fn accumulated(&self) -> Duration {
+        self.accumulated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_interval_timer_waits_for_interval() {
+        let mut timer = IntervalTimer::default();
+        let interval = Duration::from_millis(100);
+        assert_eq!(timer.tick(Duration::from_millis(60), interval), 0);
+        assert_eq!(timer.tick(Duration::from_millis(60), interval), 1);
+        assert_eq!(timer.accumulated(), Duration::from_millis(20));
+    }
+    #[test]
+    fn test_interval_timer_catches_up() {
+        let mut timer = IntervalTimer::default();
+        assert_eq!(timer.tick(Duration::from_secs(1), Duration::from_millis(100)), 10);
+        assert_eq!(timer.tick(Duration::from_secs(60), Duration::from_millis(100)), MAX_RUNS_PER_TICK);
+        assert_eq!(timer.accumulated(), Duration::ZERO);
+    }
+    #[test]
+    fn test_fixed_clock() {
+        let mut world = World::new();
+        world.insert_resource(WorldClock::fixed(Duration::from_millis(500)));
+        let mut schedule = Schedule::default();
+        schedule.add_systems(advance_world_clock);
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        let clock = world.resource::<WorldClock>();
+        assert_eq!(clock.delta(), Duration::from_millis(500));
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt::Write;
+use bevy_ecs::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+
+///组件字段的值与其在定义中的描述
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDescription {
+    pub name: String,
+    pub description: &'static str,
+    pub value: Value,
+}
+///实体上的一个组件，name 为组件的限定名
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentDescription {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub fields: Vec<FieldDescription>,
+}
+impl ComponentDescription {
+    ///由组件的值与 Describe 中的描述构造，字段按定义中的顺序排列
+    pub fn new<T: Serialize>(name: &'static str, description: &'static str, component: &T, describe_field: impl Fn(&str) -> &'static str) -> Self {
+        // 生成的组件总是可以序列化为 JSON 对象
+        let fields = match serde_json::to_value(component) {
+            Ok(Value::Object(fields)) => fields.into_iter()
+                .map(|(name, value)| FieldDescription { description: describe_field(&name), name, value })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Self { name, description, fields }
+    }
+    ///不带模块限定的组件名
+    pub fn short_name(&self) -> &'static str {
+        self.name.rsplit("::").next().unwrap_or(self.name)
+    }
+}
+///读取实体上所有组件的描述，实体不存在时返回 None，由生成代码中的 describe_components 实现
+pub type Describer = fn(&World, Entity) -> Option<Vec<ComponentDescription>>;
+
+///将实体的组件渲染为便于智能体阅读的文本，实体以协议中使用的数字 id 表示
+pub fn render_entity(entity: Entity, components: &[ComponentDescription]) -> String {
+    let mut text = format!("Entity {}:", entity.to_bits());
+    for component in components {
+        let _ = match component.description {
+            "" => write!(text, "\n- {}", component.short_name()),
+            description => write!(text, "\n- {}: {}", component.short_name(), description),
+        };
+        for field in component.fields.iter() {
+            let _ = write!(text, "\n  - {}: {}", field.name, render_value(&field.value));
+            if !field.description.is_empty() {
+                let _ = write!(text, " ({})", field.description);
+            }
+        }
+    }
+    text
+}
+///float 字段为 f32，序列化后按 f32 显示以避免 0.100000001490116 这样的值
+fn render_value(value: &Value) -> String {
+    match value {
+        Value::Number(number) => match number.as_f64() {
+            Some(float) if number.is_f64() && f64::from(float as f32) == float => (float as f32).to_string(),
+            _ => number.to_string(),
+        },
+        Value::Array(values) => format!("[{}]", values.iter().map(render_value).collect::<Vec<_>>().join(", ")),
+        Value::Object(fields) => {
+            let fields = fields.iter().map(|(name, value)| format!("{}: {}", name, render_value(value))).collect::<Vec<_>>();
+            format!("{{{}}}", fields.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
+
+///选择要描述的实体，条件之间为且的关系
+#[derive(Debug, Clone, Default)]
+pub struct SceneFilter {
+    components: Vec<String>,
+    entities: Option<Vec<Entity>>,
+}
+impl SceneFilter {
+    ///只描述带有该组件的实体，组件名可以带模块限定，如 "combat::Health"
+    pub fn with_component(mut self, component: impl Into<String>) -> Self {
+        self.components.push(component.into());
+        self
+    }
+    ///只描述这些实体
+    pub fn with_entities(mut self, entities: impl IntoIterator<Item = Entity>) -> Self {
+        self.entities.get_or_insert_with(Vec::new).extend(entities);
+        self
+    }
+    pub fn matches(&self, entity: Entity, components: &[ComponentDescription]) -> bool {
+        let has_component = |name: &String| components.iter().any(|component| component.name == name || component.short_name() == name);
+        self.entities.as_ref().is_none_or(|entities| entities.contains(&entity)) && self.components.iter().all(has_component)
+    }
+}
+///描述世界中所有符合条件的实体，没有组件的实体被忽略，实体之间以空行分隔
+pub fn describe_scene(world: &World, describe: Describer, filter: &SceneFilter) -> String {
+    world.iter_entities()
+        .filter_map(|entity| {
+            let components = describe(world, entity.id())?;
+            (!components.is_empty() && filter.matches(entity.id(), &components)).then(|| render_entity(entity.id(), &components))
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+    use super::*;
+    #[derive(Component, Serialize)]
+    struct Health {
+        value: f32,
+        max: i64,
+    }
+    #[derive(Component, Serialize)]
+    struct Inventory {
+        items: Vec<String>,
+    }
+    fn describe_components(world: &World, entity: Entity) -> Option<Vec<ComponentDescription>> {
+        let entity_ref = world.get_entity(entity).ok()?;
+        let mut components = Vec::new();
+        if let Some(health) = entity_ref.get::<Health>() {
+            components.push(ComponentDescription::new("combat::Health", "生命值", health, |field| match field {
+                "value" => "当前生命值",
+                _ => "",
+            }));
+        }
+        if let Some(inventory) = entity_ref.get::<Inventory>() {
+            components.push(ComponentDescription::new("items::Inventory", "", inventory, |_| ""));
+        }
+        Some(components)
+    }
+    #[test]
+    fn test_render_entity() {
+        let mut world = World::new();
+        let entity = world.spawn((Health { value: 0.1, max: 100 }, Inventory { items: vec!["sword".into()] })).id();
+        let components = describe_components(&world, entity).unwrap();
+        assert_eq!(components[0].fields[1].name, "max");
+        assert_eq!(render_entity(entity, &components), format!(
+            "Entity {}:\n- Health: 生命值\n  - value: 0.1 (当前生命值)\n  - max: 100\n- Inventory\n  - items: [\"sword\"]",
+            entity.to_bits()
+        ));
+    }
+    #[test]
+    fn test_describe_scene() {
+        let mut world = World::new();
+        let wounded = world.spawn(Health { value: 5.0, max: 100 }).id();
+        let chest = world.spawn(Inventory { items: Vec::new() }).id();
+        world.spawn_empty();
+        let scene = describe_scene(&world, describe_components, &SceneFilter::default());
+        assert_eq!(scene.split("\n\n").count(), 2);
+        let health_only = describe_scene(&world, describe_components, &SceneFilter::default().with_component("combat::Health"));
+        assert!(health_only.starts_with(&format!("Entity {}:", wounded.to_bits())));
+        assert!(!health_only.contains("Inventory"));
+        let by_entity = describe_scene(&world, describe_components, &SceneFilter::default().with_component("Inventory").with_entities([chest]));
+        assert_eq!(by_entity, format!("Entity {}:\n- Inventory\n  - items: []", chest.to_bits()));
+        assert!(describe_scene(&world, describe_components, &SceneFilter::default().with_entities([wounded]).with_component("Inventory")).is_empty());
+    }
+}
@@ -0,0 +1,51 @@
+use bevy_ecs::entity::Entity;
+
+///Lua 中的实体句柄，脚本只能比较、打印和传递，不能构造或做算术运算
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LuaEntity(pub Entity);
+impl From<Entity> for LuaEntity {
+    fn from(entity: Entity) -> Self {
+        Self(entity)
+    }
+}
+impl mlua::UserData for LuaEntity {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_meta_method(mlua::MetaMethod::Eq, |_, this, other: LuaEntity| Ok(*this == other));
+        methods.add_meta_method(mlua::MetaMethod::ToString, |_, this, ()| Ok(this.0.to_string()));
+    }
+}
+impl mlua::FromLua for LuaEntity {
+    fn from_lua(value: mlua::Value, _: &mlua::Lua) -> mlua::Result<Self> {
+        match value {
+            mlua::Value::UserData(userdata) => Ok(*userdata.borrow::<Self>()?),
+            other => Err(mlua::Error::FromLuaConversionError {
+                from: other.type_name(),
+                to: "Entity".to_string(),
+                message: Some("expected an entity handle".to_string()),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::world::World;
+    use super::*;
+    #[test]
+    fn test_entity_handle_round_trip() {
+        let mut world = World::new();
+        let first = world.spawn_empty().id();
+        let second = world.spawn_empty().id();
+        let lua = mlua::Lua::new();
+        let globals = lua.globals();
+        globals.set("first", LuaEntity(first)).unwrap();
+        globals.set("first_again", LuaEntity(first)).unwrap();
+        globals.set("second", LuaEntity(second)).unwrap();
+        assert!(lua.load("return first == first_again").eval::<bool>().unwrap());
+        assert!(!lua.load("return first == second").eval::<bool>().unwrap());
+        let handle: LuaEntity = lua.load("return second").eval().unwrap();
+        assert_eq!(handle.0, second);
+        //句柄不能由数字伪造
+        assert!(lua.load("return 42").eval::<LuaEntity>().is_err());
+    }
+}
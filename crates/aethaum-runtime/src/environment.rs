@@ -0,0 +1,426 @@
+use bevy_app::App;
+use bevy_ecs::prelude::*;
+use bevy_ecs::world::CommandQueue;
+use mlua::{Function, Lua, Table};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use crate::api::{json_to_lua, EmitError};
+use crate::clock::WorldClock;
+use crate::entity::LuaEntity;
+use crate::rpc::{observations_to_json, observe_all, param, AgentServer, Observation, ObserveError, RpcError, RpcHandler, WorldBindings, INTERNAL_ERROR, METHOD_NOT_FOUND};
+use crate::sandbox::reset_instruction_budget;
+use crate::spawn::SpawnOverrides;
+
+///回合状态不允许该调用，如未 reset 就 step
+pub const EPISODE_ERROR: i64 = -32000;
+
+///初始场景中按原型生成的实体，overrides 为 JSON 形式的 SpawnOverrides
+#[derive(Debug, Clone, Copy)]
+pub struct SceneSpawn {
+    pub prototype: &'static str,
+    pub count: u32,
+    pub overrides: Option<&'static str>,
+}
+///奖励与终止条件使用的查询，包含的组件以 (限定名, Lua 中的名字) 表示
+#[derive(Debug, Clone, Copy)]
+pub struct EnvironmentQuery {
+    pub name: &'static str,
+    pub include: &'static [(&'static str, &'static str)],
+    pub exclude: &'static [&'static str],
+}
+///world.toml 中的 [environment]，由生成代码中的 ENVIRONMENT 提供
+#[derive(Debug, Clone, Copy)]
+pub struct EnvironmentConfig {
+    pub scene: &'static [SceneSpawn],
+    pub queries: &'static [EnvironmentQuery],
+    pub reward: Option<&'static str>,
+    pub done: Option<&'static str>,
+    pub max_steps: Option<u64>,
+    pub frames_per_step: u32,
+}
+
+#[derive(Debug, Error)]
+pub enum EnvironmentError {
+    #[error("The environment has not been reset")]
+    NotReset,
+    #[error("The episode is over, reset the environment to start a new one")]
+    EpisodeOver,
+    #[error("Invalid overrides for prototype '{prototype}': {source}")]
+    InvalidOverrides {
+        prototype: &'static str,
+        source: serde_json::Error,
+    },
+    #[error("Failed to set up the Lua runtime: {0}")]
+    Setup(mlua::Error),
+    #[error("Failed to evaluate the {expression} expression: {source}")]
+    Script {
+        expression: &'static str,
+        source: mlua::Error,
+    },
+    #[error(transparent)]
+    Action(#[from] EmitError),
+    #[error(transparent)]
+    Observe(#[from] ObserveError),
+}
+impl From<EnvironmentError> for RpcError {
+    fn from(error: EnvironmentError) -> Self {
+        match error {
+            EnvironmentError::NotReset | EnvironmentError::EpisodeOver => Self::new(EPISODE_ERROR, error.to_string()),
+            EnvironmentError::Action(error) => error.into(),
+            EnvironmentError::Observe(error) => error.into(),
+            _ => Self::new(INTERNAL_ERROR, error.to_string()),
+        }
+    }
+}
+
+///智能体的动作：按事件名发送一个事件
+#[derive(Debug, Clone, Deserialize)]
+pub struct Action {
+    pub event_name: String,
+    #[serde(default)]
+    pub fields: Option<Value>,
+}
+#[derive(Debug, Clone, Serialize)]
+pub struct StepInfo {
+    pub steps: u64,
+    pub elapsed: Option<f64>,
+    ///done 表达式为真
+    pub terminated: bool,
+    ///达到 max_steps
+    pub truncated: bool,
+}
+///step 的结果，done 为 terminated 或 truncated
+#[derive(Debug, Clone, Serialize)]
+pub struct Transition {
+    pub observation: Value,
+    pub reward: f64,
+    pub done: bool,
+    pub info: StepInfo,
+}
+
+struct Episode {
+    // Lua 的句柄需要先于持有虚拟机的世界释放
+    lua: Lua,
+    env: Table,
+    reward: Option<Function>,
+    done: Option<Function>,
+    steps: u64,
+    over: bool,
+    server: AgentServer,
+}
+
+///Gym 风格的环境：reset 重新构建世界并生成初始场景，step 发送动作并推进世界，返回奖励与是否结束
+///通过 JSON-RPC 2.0 提供 reset、step、observe、describe 与 list_events
+pub struct EnvironmentServer {
+    build: fn() -> App,
+    bindings: WorldBindings,
+    config: &'static EnvironmentConfig,
+    episode: Option<Episode>,
+}
+impl EnvironmentServer {
+    ///build 构建一个新的世界，需要包含 AethaumRuntimePlugin，通常使用 WorldClock::fixed
+    pub fn new(build: fn() -> App, bindings: WorldBindings, config: &'static EnvironmentConfig) -> Self {
+        Self { build, bindings, config, episode: None }
+    }
+    ///当前回合的世界
+    pub fn app(&self) -> Option<&App> {
+        self.episode.as_ref().map(|episode| episode.server.app())
+    }
+    ///开始新的回合：重新构建世界，设置 Lua 的随机数种子并生成初始场景，返回初始观察
+    ///场景中的实体在第一帧中生成
+    pub fn reset(&mut self, seed: Option<i64>) -> Result<Value, EnvironmentError> {
+        self.episode = None;
+        let mut server = AgentServer::new((self.build)(), self.bindings)
+            .map_err(EnvironmentError::Setup)?;
+        let world = server.app_mut().world_mut();
+        let lua = world.get_non_send_resource::<Lua>().cloned().expect("the environment requires AethaumRuntimePlugin");
+        if let Some(seed) = seed {
+            let seed_random = || lua.globals().get::<Table>("math")?.get::<Function>("randomseed")?.call::<()>(seed);
+            seed_random().map_err(|source| EnvironmentError::Script { expression: "seed", source })?;
+        }
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, world);
+        for spawn in self.config.scene {
+            let overrides = spawn.overrides
+                .map(serde_json::from_str::<SpawnOverrides>)
+                .transpose()
+                .map_err(|source| EnvironmentError::InvalidOverrides { prototype: spawn.prototype, source })?;
+            for _ in 0..spawn.count {
+                (self.bindings.spawn)(&mut commands, spawn.prototype, overrides.clone());
+            }
+        }
+        queue.apply(world);
+        server.app_mut().update();
+
+        // 表达式中的查询名解析到 env，其余名字解析到全局变量
+        let script_error = |expression| move |source| EnvironmentError::Script { expression, source };
+        let env = lua.create_table().map_err(script_error("environment"))?;
+        let metatable = lua.create_table().map_err(script_error("environment"))?;
+        metatable.raw_set("__index", lua.globals()).map_err(script_error("environment"))?;
+        env.set_metatable(Some(metatable)).map_err(script_error("environment"))?;
+        let reward = self.config.reward.map(|source| compile(&lua, &env, "reward", source)).transpose()?;
+        let done = self.config.done.map(|source| compile(&lua, &env, "done", source)).transpose()?;
+        let observation = observe_all(server.app().world(), self.bindings.observe)?;
+        self.episode = Some(Episode { lua, env, reward, done, steps: 0, over: false, server });
+        Ok(observations_to_json(observation))
+    }
+    ///发送动作并推进 frames_per_step 帧，动作中有无效的事件时不发送任何动作，世界也不推进
+    pub fn step(&mut self, actions: &[Action]) -> Result<Transition, EnvironmentError> {
+        let episode = self.episode.as_mut().ok_or(EnvironmentError::NotReset)?;
+        if episode.over {
+            return Err(EnvironmentError::EpisodeOver);
+        }
+        let world = episode.server.app_mut().world_mut();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, world);
+        for action in actions {
+            let fields = action.fields.clone().unwrap_or_else(|| json!({}));
+            (self.bindings.emit)(&mut commands, &action.event_name, fields)?;
+        }
+        queue.apply(world);
+        for _ in 0..self.config.frames_per_step {
+            episode.server.app_mut().update();
+        }
+        episode.steps += 1;
+
+        let world = episode.server.app().world();
+        let observation = observe_all(world, self.bindings.observe)?;
+        bind_queries(episode, self.config.queries, &observation)
+            .map_err(|source| EnvironmentError::Script { expression: "queries", source })?;
+        let reward = match &episode.reward {
+            Some(reward) => match evaluate(&episode.lua, reward, "reward")? {
+                mlua::Value::Nil => 0.0,
+                mlua::Value::Integer(reward) => reward as f64,
+                mlua::Value::Number(reward) => reward,
+                other => return Err(EnvironmentError::Script {
+                    expression: "reward",
+                    source: mlua::Error::runtime(format!("expected a number, found a {}", other.type_name())),
+                }),
+            },
+            None => 0.0,
+        };
+        let terminated = match &episode.done {
+            Some(done) => !matches!(evaluate(&episode.lua, done, "done")?, mlua::Value::Nil | mlua::Value::Boolean(false)),
+            None => false,
+        };
+        let truncated = self.config.max_steps.is_some_and(|max_steps| episode.steps >= max_steps);
+        episode.over = terminated || truncated;
+        let elapsed = world.get_resource::<WorldClock>().map(|clock| clock.elapsed().as_secs_f64());
+        Ok(Transition {
+            observation: observations_to_json(observation),
+            reward,
+            done: episode.over,
+            info: StepInfo { steps: episode.steps, elapsed, terminated, truncated },
+        })
+    }
+}
+///表达式可以直接写值，如 "#enemies == 0"，也可以是带 return 的代码块
+fn compile(lua: &Lua, env: &Table, expression: &'static str, source: &str) -> Result<Function, EnvironmentError> {
+    let load = |source: String| lua.load(source)
+        .set_name(format!("environment.{}", expression))
+        .set_environment(env.clone())
+        .into_function();
+    load(format!("return {}", source))
+        .or_else(|_| load(source.to_string()))
+        .map_err(|source| EnvironmentError::Script { expression, source })
+}
+fn evaluate(lua: &Lua, function: &Function, expression: &'static str) -> Result<mlua::Value, EnvironmentError> {
+    reset_instruction_budget(lua);
+    function.call(()).map_err(|source| EnvironmentError::Script { expression, source })
+}
+///查询结果以实体视图数组的形式设置到 env 中，视图与系统中的相同：{ id = 实体, 组件名 = 组件 }
+///组件为只读的副本，其中的实体字段为实体的位表示
+fn bind_queries(episode: &Episode, queries: &[EnvironmentQuery], observation: &[(Entity, Observation)]) -> mlua::Result<()> {
+    let lua = &episode.lua;
+    for query in queries {
+        let views = lua.create_table()?;
+        let matches = |components: &Observation| {
+            query.include.iter().all(|(name, _)| components.contains_key(*name))
+                && !query.exclude.iter().any(|name| components.contains_key(*name))
+        };
+        for (entity, components) in observation.iter().filter(|(_, components)| matches(components)) {
+            let view = lua.create_table()?;
+            view.raw_set("id", LuaEntity(*entity))?;
+            for (name, lua_name) in query.include {
+                view.raw_set(*lua_name, json_to_lua(lua, &components[*name])?)?;
+            }
+            views.raw_push(view)?;
+        }
+        episode.env.raw_set(query.name, views)?;
+    }
+    episode.env.raw_set("steps", episode.steps)
+}
+impl RpcHandler for EnvironmentServer {
+    fn call(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "reset" => {
+                let seed = match param(params, 0, "seed") {
+                    Some(seed) => Some(seed.as_i64().ok_or_else(|| RpcError::invalid_params("seed must be an integer"))?),
+                    None => None,
+                };
+                Ok(self.reset(seed)?)
+            }
+            "step" => {
+                // 动作可以省略，也可以是一个动作或动作的数组
+                let actions = match param(params, 0, "action") {
+                    None => Ok(Vec::new()),
+                    Some(Value::Array(actions)) => actions.iter().map(Action::deserialize).collect::<Result<_, _>>(),
+                    Some(action) => Action::deserialize(action).map(|action| vec![action]),
+                }.map_err(|error| RpcError::invalid_params(format!("Invalid action: {}", error)))?;
+                Ok(serde_json::to_value(self.step(&actions)?).expect("transitions serialize to JSON"))
+            }
+            "observe" | "describe" => self.episode.as_mut().ok_or(EnvironmentError::NotReset)?.server.call(method, params),
+            "list_events" => Ok(json!(self.bindings.events)),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::Update;
+    use std::time::Duration;
+    use crate::describe::ComponentDescription;
+    use crate::rpc::{component_to_json, EventInfo};
+    use crate::AethaumRuntimePlugin;
+    use crate::spawn::apply_overrides;
+    use super::*;
+    #[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Health {
+        value: i64,
+    }
+    #[derive(Event, Debug, Clone, Serialize, Deserialize)]
+    struct Damage {
+        entity: Entity,
+        amount: i64,
+    }
+    fn observe_entity(world: &World, entity: Entity) -> Result<Observation, ObserveError> {
+        let entity_ref = world.get_entity(entity).map_err(|_| ObserveError::NoSuchEntity(entity))?;
+        let mut components = Observation::new();
+        if let Some(component) = entity_ref.get::<Health>() {
+            components.insert("combat::Health".to_string(), component_to_json("combat::Health", component)?);
+        }
+        Ok(components)
+    }
+    fn emit_event(commands: &mut Commands, event_name: &str, fields: Value) -> Result<(), EmitError> {
+        match event_name {
+            "combat::Damage" | "Damage" => {
+                commands.send_event(crate::api::deserialize_event::<Damage>(event_name, fields)?);
+                Ok(())
+            }
+            _ => Err(EmitError::UnknownEvent(event_name.to_string())),
+        }
+    }
+    fn spawn_prototype(commands: &mut Commands, prototype: &str, overrides: Option<SpawnOverrides>) {
+        if prototype != "combat::Dummy" {
+            return;
+        }
+        let health = Health { value: 10 };
+        let health = match overrides.as_ref().and_then(|overrides| overrides.get("health")) {
+            Some(fields) => apply_overrides("health", &health, fields).unwrap(),
+            None => health,
+        };
+        commands.spawn(health);
+    }
+    fn describe_components(_world: &World, _entity: Entity) -> Option<Vec<ComponentDescription>> {
+        Some(Vec::new())
+    }
+    fn apply_damage(mut commands: Commands, mut events: EventReader<Damage>, mut health: Query<&mut Health>) {
+        for event in events.read() {
+            if let Ok(mut health) = health.get_mut(event.entity) {
+                health.value -= event.amount;
+                if health.value <= 0 {
+                    commands.entity(event.entity).despawn();
+                }
+            }
+        }
+    }
+    fn build() -> App {
+        let mut app = App::new();
+        app.add_plugins(AethaumRuntimePlugin::default())
+            .insert_resource(WorldClock::fixed(Duration::from_millis(100)))
+            .add_event::<Damage>()
+            .add_systems(Update, apply_damage);
+        app
+    }
+    const BINDINGS: WorldBindings = WorldBindings {
+        observe: observe_entity,
+        emit: emit_event,
+        spawn: spawn_prototype,
+        describe: describe_components,
+        events: &[] as &[EventInfo],
+    };
+    static ARENA: EnvironmentConfig = EnvironmentConfig {
+        scene: &[SceneSpawn { prototype: "combat::Dummy", count: 2, overrides: Some(r#"{ "health": { "value": 3 } }"#) }],
+        queries: &[EnvironmentQuery { name: "alive", include: &[("combat::Health", "health")], exclude: &[] }],
+        reward: Some("local total = 0 for _, dummy in ipairs(alive) do total = total + dummy.health.value end return -total"),
+        done: Some("#alive == 0"),
+        max_steps: None,
+        frames_per_step: 1,
+    };
+    static RANDOM: EnvironmentConfig = EnvironmentConfig {
+        scene: &[],
+        queries: &[],
+        reward: Some("math.random(1000000) + steps"),
+        done: None,
+        max_steps: Some(2),
+        frames_per_step: 3,
+    };
+    fn damage(entity: &Value, amount: i64) -> Action {
+        Action { event_name: "Damage".to_string(), fields: Some(json!({ "entity": entity, "amount": amount })) }
+    }
+    #[test]
+    fn test_reset_and_step() {
+        let mut environment = EnvironmentServer::new(build, BINDINGS, &ARENA);
+        assert!(matches!(environment.step(&[]), Err(EnvironmentError::NotReset)));
+        let observation = environment.reset(None).unwrap();
+        let observation = observation.as_array().unwrap();
+        assert_eq!(observation.len(), 2);
+        assert_eq!(observation[0]["components"]["combat::Health"]["value"], 3);
+        let first = &observation[0]["entity"];
+        let transition = environment.step(&[damage(first, 3)]).unwrap();
+        assert_eq!((transition.reward, transition.done), (-3.0, false));
+        assert_eq!(transition.observation.as_array().unwrap().len(), 1);
+        // 无效的动作不会推进世界
+        let second = &observation[1]["entity"];
+        let missing_amount = Action { event_name: "Damage".to_string(), fields: Some(json!({ "entity": second })) };
+        assert!(matches!(environment.step(&[damage(second, 3), missing_amount]), Err(EnvironmentError::Action(_))));
+        assert!(matches!(environment.step(&[Action { event_name: "Heal".to_string(), fields: None }]), Err(EnvironmentError::Action(_))));
+        let transition = environment.step(&[damage(second, 3)]).unwrap();
+        assert_eq!(transition.reward, 0.0);
+        assert!(transition.done && transition.info.terminated && !transition.info.truncated);
+        assert_eq!(transition.info.steps, 2);
+        assert!(matches!(environment.step(&[]), Err(EnvironmentError::EpisodeOver)));
+        assert_eq!(environment.reset(None).unwrap().as_array().unwrap().len(), 2);
+    }
+    #[test]
+    fn test_seed_and_truncation() {
+        let mut environment = EnvironmentServer::new(build, BINDINGS, &RANDOM);
+        environment.reset(Some(7)).unwrap();
+        let first = environment.step(&[]).unwrap();
+        // reset 生成场景的一帧与 step 的三帧
+        assert_eq!(first.info.elapsed, Some(0.4));
+        let second = environment.step(&[]).unwrap();
+        assert!(second.done && second.info.truncated && !second.info.terminated);
+        environment.reset(Some(7)).unwrap();
+        assert_eq!(environment.step(&[]).unwrap().reward, first.reward);
+    }
+    #[test]
+    fn test_environment_protocol() {
+        let mut environment = EnvironmentServer::new(build, BINDINGS, &ARENA);
+        let mut request = |request: Value| serde_json::from_str::<Value>(&environment.handle_line(&request.to_string()).unwrap()).unwrap();
+        let not_reset = request(json!({ "jsonrpc": "2.0", "id": 1, "method": "step" }));
+        assert_eq!(not_reset["error"]["code"], EPISODE_ERROR);
+        let observation = request(json!({ "jsonrpc": "2.0", "id": 2, "method": "reset", "params": { "seed": 1 } }))["result"].clone();
+        let entity = &observation[0]["entity"];
+        let action = json!({ "event_name": "Damage", "fields": { "entity": entity, "amount": 1 } });
+        let transition = request(json!({ "jsonrpc": "2.0", "id": 3, "method": "step", "params": [[action]] }));
+        assert_eq!(transition["result"]["reward"], -5.0);
+        assert_eq!(transition["result"]["info"]["steps"], 1);
+        let observed = request(json!({ "jsonrpc": "2.0", "id": 4, "method": "observe", "params": { "entity": entity } }));
+        assert_eq!(observed["result"]["components"]["combat::Health"]["value"], 2);
+        let invalid = request(json!({ "jsonrpc": "2.0", "id": 5, "method": "step", "params": { "action": { "fields": {} } } }));
+        assert_eq!(invalid["error"]["code"], crate::rpc::INVALID_PARAMS);
+    }
+}
@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use aethaum_core::code_generator::{AethaumTranspiler, GeneratedFile, TranspileError};
+use aethaum_core::code_generator::project::DEFAULT_OUTPUT_DIR;
+use aethaum_core::ecs::checker::{AethaumChecker, CheckerError};
+use aethaum_core::ecs::loader::{ProjectLoader, ProjectLoaderError};
+use aethaum_core::ecs::module::AethaumProject;
+use aethaum_core::toml_parser::parsed::LuaScript;
+use bevy_app::{App, First, Plugin};
+use bevy_ecs::prelude::*;
+use miette::Diagnostic;
+use mlua::Lua;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use thiserror::Error;
+use crate::script::{ScriptCache, ScriptKey, ScriptSlot};
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum HotReloadError {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Load(#[from] ProjectLoaderError),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Check(#[from] CheckerError),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Transpile(#[from] TranspileError),
+    #[error("Restart required: {} changed", .changed.join(", "))]
+    #[diagnostic(
+        code(aethaum::hot_reload::restart_required),
+        help("Only Lua scripts can be hot reloaded. Components, events, types, prototypes and system definitions are compiled into the world: rebuild it with `aethaum build` and restart it.")
+    )]
+    RestartRequired {
+        changed: Vec<String>,
+    },
+    #[error("Failed to read script {path}")]
+    ReadScript {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    ///mlua 的错误不能跨线程传递，只保留错误信息
+    #[error("Script {name} failed to compile, keeping the running version: {message}")]
+    InvalidScript {
+        name: String,
+        message: String,
+    },
+    #[error("Failed to watch {path}")]
+    Watch {
+        path: PathBuf,
+        #[source]
+        source: notify::Error,
+    },
+}
+
+///脚本的源码，文件脚本记录其在系统定义中的路径
+#[derive(Debug, Clone, PartialEq)]
+struct ScriptText {
+    name: String,
+    source: String,
+    file: Option<PathBuf>,
+}
+
+///项目在某一时刻的状态：去掉内嵌脚本后生成的 Rust 代码，以及所有脚本的源码
+struct ProjectSnapshot {
+    generated: Vec<GeneratedFile>,
+    scripts: HashMap<ScriptKey, ScriptText>,
+    output_dir: PathBuf,
+}
+impl ProjectSnapshot {
+    ///与 aethaum check 相同，先加载并检查项目，不合法的修改不会被应用
+    fn load(project_root: &Path) -> Result<Self, HotReloadError> {
+        let project = ProjectLoader::new(project_root.to_path_buf()).load()?;
+        let mut project = AethaumChecker::run_check(project)?;
+        let scripts = collect_scripts(&project)?;
+        strip_embedded_scripts(&mut project);
+        declare_runtime(&mut project);
+        let output_dir = project.world.build.as_ref()
+            .map(|build| build.output_dir.as_str())
+            .unwrap_or(DEFAULT_OUTPUT_DIR);
+        Ok(Self {
+            generated: AethaumTranspiler::run(&project)?,
+            scripts,
+            output_dir: project_root.join(output_dir),
+        })
+    }
+}
+///按生成代码中使用的 (系统, 位置) 收集所有脚本
+fn collect_scripts(project: &AethaumProject) -> Result<HashMap<ScriptKey, ScriptText>, HotReloadError> {
+    let mut scripts = HashMap::new();
+    for module in project.module_tree.get_modules() {
+        for system in module.systems.iter().flatten() {
+            let system_name = system.normal.name.as_str();
+            let system_ref = format!("{}::{}", module.name, system_name);
+            let mut slots = Vec::new();
+            if let Some(update) = &system.update {
+                if let Some(condition) = &update.condition {
+                    slots.push((ScriptSlot::UpdateCondition, condition, format!("{}::update::condition", system_name)));
+                }
+                if let Some(logic) = &update.logic {
+                    slots.push((ScriptSlot::UpdateLogic, logic, format!("{}::update", system_name)));
+                }
+            }
+            for (index, handler) in system.event_handlers.iter().enumerate() {
+                if let Some(logic) = &handler.logic {
+                    slots.push((ScriptSlot::EventHandler(index), logic, format!("{}::event_handlers[{}]", system_name, index)));
+                }
+            }
+            for (slot, script, chunk_name) in slots {
+                let text = match script {
+                    LuaScript::Embed(source) => ScriptText { name: chunk_name, source: source.to_string(), file: None },
+                    LuaScript::File(path) => {
+                        let full_path = project.root.join(path);
+                        let source = std::fs::read_to_string(&full_path)
+                            .map_err(|source| HotReloadError::ReadScript { path: full_path, source })?;
+                        // 与 mlua 加载文件时的代码块名一致
+                        ScriptText { name: format!("@{}", path.display()), source, file: Some(path.clone()) }
+                    }
+                };
+                scripts.insert(ScriptKey::new(system_ref.clone(), slot), text);
+            }
+        }
+    }
+    Ok(scripts)
+}
+fn strip_embedded_scripts(project: &mut AethaumProject) {
+    let module_names = project.module_tree.get_modules().iter().map(|module| module.name.clone()).collect::<Vec<_>>();
+    for module_name in module_names {
+        let Some(module) = project.module_tree.get_module_mut(&module_name) else {
+            continue;
+        };
+        for system in module.systems.iter_mut().flatten() {
+            let update_scripts = system.update.iter_mut().flat_map(|update| [&mut update.condition, &mut update.logic]);
+            let handler_scripts = system.event_handlers.iter_mut().map(|handler| &mut handler.logic);
+            for script in update_scripts.chain(handler_scripts) {
+                if let Some(LuaScript::Embed(source)) = script {
+                    *source = Default::default();
+                }
+            }
+        }
+    }
+}
+///运行时库的位置可能由 aethaum build --runtime 给出而没有记录在 world.toml 中，
+///快照只用于比较生成结果，此时以占位依赖代替
+fn declare_runtime(project: &mut AethaumProject) {
+    let world = &mut project.world;
+    let located = world.build.as_ref().is_some_and(|build| build.runtime.is_some());
+    let cargo = world.cargo.get_or_insert_default();
+    if !located && !cargo.dependencies.contains_key("aethaum-runtime") {
+        cargo.dependencies.insert("aethaum-runtime".into(), toml::Value::from("*"));
+    }
+}
+///生成文件对应的定义，用于提示需要重启的原因
+fn describe_generated_file(path: &Path) -> String {
+    match path.to_str() {
+        Some("Cargo.toml") | Some("src/main.rs") => "world.toml".to_string(),
+        Some("src/lib.rs") => "the module list or event set".to_string(),
+        _ => match path.file_stem() {
+            Some(module) => format!("module `{}`", module.to_string_lossy()),
+            None => path.display().to_string(),
+        },
+    }
+}
+
+///监视项目根目录，在脚本修改时重新编译并替换运行中的脚本
+pub struct HotReloader {
+    project_root: PathBuf,
+    generated: Vec<GeneratedFile>,
+    scripts: HashMap<ScriptKey, ScriptText>,
+    output_dir: PathBuf,
+    watcher: Option<(RecommendedWatcher, Receiver<notify::Result<notify::Event>>)>,
+}
+impl HotReloader {
+    ///以项目当前的状态作为基准，应与运行中的世界构建时一致
+    pub fn new(project_root: impl Into<PathBuf>) -> Result<Self, HotReloadError> {
+        let project_root = project_root.into();
+        let snapshot = ProjectSnapshot::load(&project_root)?;
+        Ok(Self {
+            project_root,
+            generated: snapshot.generated,
+            scripts: snapshot.scripts,
+            output_dir: snapshot.output_dir,
+            watcher: None,
+        })
+    }
+    pub fn watch(mut self) -> Result<Self, HotReloadError> {
+        let watch_error = |source| HotReloadError::Watch { path: self.project_root.clone(), source };
+        let (sender, receiver) = channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+        watcher.watch(&self.project_root, RecursiveMode::Recursive).map_err(watch_error)?;
+        self.watcher = Some((watcher, receiver));
+        Ok(self)
+    }
+    ///取出所有文件事件，返回其中是否有项目中的 toml 或 lua 文件发生了变化
+    pub fn poll_changes(&self) -> bool {
+        let Some((_, receiver)) = &self.watcher else {
+            return false;
+        };
+        let mut changed = false;
+        for event in receiver.try_iter().flatten() {
+            if event.kind.is_access() {
+                continue;
+            }
+            changed |= event.paths.iter().any(|path| self.is_watched(path));
+        }
+        changed
+    }
+    fn is_watched(&self, path: &Path) -> bool {
+        let is_source = path.extension().is_some_and(|extension| extension == "toml" || extension == "lua");
+        let is_build_output = path.starts_with(&self.output_dir)
+            || path.components().any(|component| component == Component::Normal("target".as_ref()));
+        is_source && !is_build_output
+    }
+    ///重新加载项目并替换修改过的脚本，返回被替换的脚本
+    ///项目无法通过检查、脚本无法编译或需要重新生成代码时不替换任何脚本
+    pub fn reload(&mut self, lua: &Lua) -> Result<Vec<ScriptKey>, HotReloadError> {
+        let snapshot = ProjectSnapshot::load(&self.project_root)?;
+        let mut changed = Vec::new();
+        for file in snapshot.generated.iter() {
+            let previous = self.generated.iter().find(|previous| previous.path == file.path);
+            if previous.is_none_or(|previous| previous.content != file.content) {
+                changed.push(describe_generated_file(&file.path));
+            }
+        }
+        for previous in self.generated.iter() {
+            if !snapshot.generated.iter().any(|file| file.path == previous.path) {
+                changed.push(describe_generated_file(&previous.path));
+            }
+        }
+        if !changed.is_empty() {
+            return Err(HotReloadError::RestartRequired { changed });
+        }
+
+        // 先编译所有修改过的脚本，全部成功后再替换
+        let mut compiled = Vec::new();
+        for (key, text) in snapshot.scripts.iter() {
+            if self.scripts.get(key) == Some(text) {
+                continue;
+            }
+            let function = lua.load(text.source.as_str())
+                .set_name(text.name.as_str())
+                .into_function()
+                .map_err(|error| HotReloadError::InvalidScript { name: text.name.clone(), message: error.to_string() })?;
+            compiled.push((key.clone(), text.file.clone(), function));
+        }
+        let mut cache = lua.remove_app_data::<ScriptCache>().unwrap_or_default();
+        let mut reloaded = Vec::with_capacity(compiled.len());
+        for (key, file, function) in compiled {
+            if let Some(file) = file {
+                cache.insert_file(file, function.clone());
+            }
+            cache.insert(key.clone(), function);
+            reloaded.push(key);
+        }
+        lua.set_app_data(cache);
+        self.scripts = snapshot.scripts;
+        Ok(reloaded)
+    }
+}
+
+///在开发时热重载 Lua 脚本，project_root 为 world.toml 所在的目录
+pub struct HotReloadPlugin {
+    pub project_root: PathBuf,
+}
+impl HotReloadPlugin {
+    pub fn new(project_root: impl Into<PathBuf>) -> Self {
+        Self { project_root: project_root.into() }
+    }
+}
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        match HotReloader::new(&self.project_root).and_then(HotReloader::watch) {
+            Ok(reloader) => {
+                app.insert_non_send_resource(reloader)
+                    .add_systems(First, hot_reload_scripts);
+            }
+            // 找不到项目（如发布后的程序）时不影响世界运行
+            Err(error) => eprintln!("Hot reload disabled: {}", describe_error(&error)),
+        }
+    }
+}
+fn hot_reload_scripts(lua: NonSend<Lua>, mut reloader: NonSendMut<HotReloader>) {
+    if !reloader.poll_changes() {
+        return;
+    }
+    match reloader.reload(&lua) {
+        Ok(reloaded) if reloaded.is_empty() => {}
+        Ok(reloaded) => {
+            let names = reloaded.iter().map(|key| format!("{} ({:?})", key.system, key.slot)).collect::<Vec<_>>();
+            eprintln!("Hot reloaded {}", names.join(", "));
+        }
+        Err(error) => eprintln!("{}", describe_error(&error)),
+    }
+}
+///运行时没有 miette 的图形输出，只打印错误信息与帮助
+fn describe_error(error: &HotReloadError) -> String {
+    match error.help() {
+        Some(help) => format!("{}\n  help: {}", error, help),
+        None => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::{load_script, ScriptSource};
+    fn copy_dir(from: &Path, to: &Path) {
+        std::fs::create_dir_all(to).unwrap();
+        for entry in std::fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            let target = to.join(entry.file_name());
+            if entry.file_type().unwrap().is_dir() {
+                copy_dir(&entry.path(), &target);
+            } else {
+                std::fs::copy(entry.path(), target).unwrap();
+            }
+        }
+    }
+    fn template_project(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&root);
+        copy_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("../../templates/default"), &root);
+        std::fs::write(root.join("world.toml"), std::fs::read_to_string(root.join("world.toml")).unwrap().replace("{{name}}", "demo")).unwrap();
+        root
+    }
+    fn edit(path: &Path, from: &str, to: &str) {
+        let content = std::fs::read_to_string(path).unwrap();
+        assert!(content.contains(from), "{} does not contain {}", path.display(), from);
+        std::fs::write(path, content.replace(from, to)).unwrap();
+    }
+    #[test]
+    fn test_reload_changed_script() {
+        let root = template_project("aethaum_hot_reload_script");
+        let systems = root.join("modules/game/systems/movement.toml");
+        let lua = Lua::new();
+        let mut reloader = HotReloader::new(&root).unwrap();
+        let key = ScriptKey::new("game::MovementSystem", ScriptSlot::EventHandler(0));
+        let running = ScriptSource::Embed { name: "MovementSystem::event_handlers[0]", source: "return 'old'" };
+        assert_eq!(load_script(&lua, key.clone(), running).unwrap().call::<String>(()).unwrap(), "old");
+        assert!(reloader.reload(&lua).unwrap().is_empty());
+
+        edit(&systems, "entity.position.y = event.y\n", "entity.position.y = event.y\nreturn 'new'\n");
+        assert_eq!(reloader.reload(&lua).unwrap(), vec![key.clone()]);
+        let swapped = load_script(&lua, key.clone(), running).unwrap();
+        lua.load("entity = { position = {} } event = { x = 1, y = 2 }").exec().unwrap();
+        assert_eq!(swapped.call::<String>(()).unwrap(), "new");
+
+        // 语法错误的修改不会替换正在运行的脚本
+        edit(&systems, "return 'new'\n", "return 'broken\n");
+        assert!(matches!(reloader.reload(&lua), Err(HotReloadError::InvalidScript { .. })));
+        assert_eq!(load_script(&lua, key, running).unwrap(), swapped);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+    #[test]
+    fn test_reload_requires_restart() {
+        let root = template_project("aethaum_hot_reload_restart");
+        let lua = Lua::new();
+        let mut reloader = HotReloader::new(&root).unwrap();
+        edit(&root.join("modules/game/components/movement.toml"), "default = 1.0", "default = 2.0");
+        let error = reloader.reload(&lua).unwrap_err();
+        assert!(matches!(&error, HotReloadError::RestartRequired { changed } if changed == &vec!["module `game`".to_string()]), "{:?}", error);
+
+        // 无法通过检查的项目同样不会被应用
+        edit(&root.join("modules/game/systems/movement.toml"), "\"Velocity\"]", "\"Missing\"]");
+        assert!(matches!(reloader.reload(&lua), Err(HotReloadError::Check(_))));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
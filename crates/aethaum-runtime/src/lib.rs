@@ -1,14 +1,64 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+pub mod api;
+pub mod clock;
+pub mod describe;
+pub mod entity;
+pub mod environment;
+pub mod hot_reload;
+pub mod rpc;
+pub mod sandbox;
+pub mod script;
+pub mod schedule;
+pub mod spawn;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+use bevy_app::{App, First, Plugin};
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+///运行生成世界所需的基础设施：受限的 Lua 虚拟机与世界时钟
+#[derive(Default)]
+pub struct AethaumRuntimePlugin {
+    pub sandbox: sandbox::SandboxConfig,
+    ///logic_file 等文件脚本相对于此目录加载，未设置时相对于运行目录
+    pub project_root: Option<std::path::PathBuf>,
+}
+impl AethaumRuntimePlugin {
+    pub fn with_sandbox(mut self, sandbox: sandbox::SandboxConfig) -> Self {
+        self.sandbox = sandbox;
+        self
     }
+    pub fn with_project_root(mut self, project_root: impl Into<std::path::PathBuf>) -> Self {
+        self.project_root = Some(project_root.into());
+        self
+    }
+}
+impl Plugin for AethaumRuntimePlugin {
+    fn build(&self, app: &mut App) {
+        // 插件无法返回错误，报告后退出，而不是在构建 App 时 panic
+        let lua = match sandbox::create_sandboxed_lua(&self.sandbox) {
+            Ok(lua) => lua,
+            Err(error) => {
+                eprintln!("Failed to create the Lua sandbox: {}", error);
+                std::process::exit(1);
+            }
+        };
+        if let Some(project_root) = &self.project_root {
+            lua.set_app_data(script::ScriptCache::default().with_project_root(project_root));
+        }
+        app.init_resource::<clock::WorldClock>()
+            .insert_non_send_resource(lua)
+            .add_systems(First, clock::advance_world_clock);
+    }
+}
+
+pub mod prelude {
+    pub use crate::AethaumRuntimePlugin;
+    pub use crate::api::{deserialize_event, lua_to_json, lua_to_overrides, EmitError};
+    pub use crate::clock::{IntervalTimer, WorldClock};
+    pub use crate::describe::{ComponentDescription, SceneFilter};
+    pub use crate::entity::LuaEntity;
+    pub use crate::environment::{Action, EnvironmentConfig, EnvironmentError, EnvironmentQuery, EnvironmentServer, SceneSpawn, Transition};
+    pub use crate::hot_reload::{HotReloadError, HotReloadPlugin, HotReloader};
+    pub use crate::rpc::{component_to_json, AgentServer, EventInfo, FieldInfo, ObserveError, Observation, RpcHandler, WorldBindings};
+    pub use crate::sandbox::{reset_instruction_budget, SandboxConfig, ScriptError};
+    pub use crate::script::{load_script, ScriptKey, ScriptSlot, ScriptSource};
+    pub use crate::schedule::{AethaumAppExt, AethaumSystemSet};
+    pub use crate::spawn::{apply_overrides, OverrideError, SpawnOverrides};
 }
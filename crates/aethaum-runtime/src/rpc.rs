@@ -0,0 +1,455 @@
+use std::io::{BufRead, Write};
+use bevy_app::{App, PluginsState};
+use bevy_ecs::prelude::*;
+use mlua::Lua;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+use crate::api::EmitError;
+use crate::clock::WorldClock;
+use crate::describe::{describe_scene, render_entity, Describer, SceneFilter};
+use crate::spawn::SpawnOverrides;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+
+///事件字段的说明，type 为定义中的 Aethaum 类型，如 "float"、"list<int>"、"combat::Faction"
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub type_name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<&'static str>,
+}
+///事件的说明，name 为带模块限定的事件名
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct EventInfo {
+    pub name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<&'static str>,
+    pub fields: &'static [FieldInfo],
+}
+
+#[derive(Debug, Error)]
+pub enum ObserveError {
+    #[error("Entity {0} does not exist")]
+    NoSuchEntity(Entity),
+    #[error("Failed to serialize component '{component}': {source}")]
+    Serialize {
+        component: &'static str,
+        source: serde_json::Error,
+    },
+}
+///将组件序列化为 JSON，供生成代码中的 observe_entity 使用
+pub fn component_to_json<T: Serialize>(component: &'static str, value: &T) -> Result<Value, ObserveError> {
+    serde_json::to_value(value).map_err(|source| ObserveError::Serialize { component, source })
+}
+
+///实体上的所有组件：组件的限定名 -> 组件的值
+pub type Observation = Map<String, Value>;
+///观察所有带有组件的实体
+pub fn observe_all(world: &World, observe: fn(&World, Entity) -> Result<Observation, ObserveError>) -> Result<Vec<(Entity, Observation)>, ObserveError> {
+    let mut entities = Vec::new();
+    for entity in world.iter_entities() {
+        let components = observe(world, entity.id())?;
+        if !components.is_empty() {
+            entities.push((entity.id(), components));
+        }
+    }
+    Ok(entities)
+}
+///observe_all 的结果在协议中的表示
+pub fn observations_to_json(observations: Vec<(Entity, Observation)>) -> Value {
+    Value::Array(observations.into_iter().map(|(entity, components)| json!({ "entity": entity, "components": components })).collect())
+}
+
+///生成世界提供给智能体协议的接口，由生成的 agent_bindings 构造
+#[derive(Clone, Copy)]
+pub struct WorldBindings {
+    pub observe: fn(&World, Entity) -> Result<Observation, ObserveError>,
+    ///按事件名发送事件
+    pub emit: fn(&mut Commands, &str, Value) -> Result<(), EmitError>,
+    ///按原型名生成实体，实体在下一帧中生成
+    pub spawn: fn(&mut Commands, &str, Option<SpawnOverrides>),
+    pub describe: Describer,
+    pub events: &'static [EventInfo],
+}
+
+///JSON-RPC 2.0 的错误对象
+#[derive(Debug, Clone, PartialEq, Serialize, Error)]
+#[error("{message} ({code})")]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), data: None }
+    }
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+impl From<EmitError> for RpcError {
+    fn from(error: EmitError) -> Self {
+        Self::invalid_params(error.to_string())
+    }
+}
+impl From<ObserveError> for RpcError {
+    fn from(error: ObserveError) -> Self {
+        match error {
+            ObserveError::NoSuchEntity(_) => Self::invalid_params(error.to_string()),
+            ObserveError::Serialize { .. } => Self::new(INTERNAL_ERROR, error.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    ///没有 id 的请求为通知，不返回响应；id 为 null 的请求仍然需要响应
+    #[serde(default, deserialize_with = "present_id")]
+    id: Option<Value>,
+}
+///字段存在时即使为 null 也视为 Some，缺省时由 serde(default) 得到 None
+fn present_id<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+#[derive(Debug, Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+    id: Value,
+}
+impl Response {
+    fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self { jsonrpc: "2.0", result, error, id }
+    }
+}
+
+///按名字或位置读取参数，params 可以是对象或数组
+pub(crate) fn param<'a>(params: &'a Value, index: usize, name: &str) -> Option<&'a Value> {
+    match params {
+        Value::Object(params) => params.get(name),
+        Value::Array(params) => params.get(index),
+        _ => None,
+    }.filter(|value| !value.is_null())
+}
+///实体以其位表示传递，与 Lua 中的 entity.id 序列化后的值相同
+fn entity_param(value: &Value) -> Result<Entity, RpcError> {
+    value.as_u64()
+        .and_then(|bits| Entity::try_from_bits(bits).ok())
+        .ok_or_else(|| RpcError::invalid_params(format!("{} is not an entity id", value)))
+}
+
+///通过 JSON-RPC 2.0 驱动世界的智能体协议服务，每行一个请求或批量请求，每行一个响应
+///世界只在 step 时推进，通常与 WorldClock::fixed 一同使用以获得可复现的结果
+pub struct AgentServer {
+    app: App,
+    bindings: WorldBindings,
+}
+impl AgentServer {
+    ///Lua 的 print 无法重定向到标准错误时返回错误
+    pub fn new(mut app: App, bindings: WorldBindings) -> mlua::Result<Self> {
+        // 不通过 App::run 运行时需要手动完成插件的初始化
+        while app.plugins_state() == PluginsState::Adding {
+            std::thread::yield_now();
+        }
+        app.finish();
+        app.cleanup();
+        if let Some(lua) = app.world().get_non_send_resource::<Lua>() {
+            redirect_print(lua)?;
+        }
+        Ok(Self { app, bindings })
+    }
+    pub fn app(&self) -> &App {
+        &self.app
+    }
+    pub fn app_mut(&mut self) -> &mut App {
+        &mut self.app
+    }
+    ///观察一个实体的所有组件，不指定实体时观察所有带有组件的实体
+    fn observe(&mut self, entity: Option<&Value>) -> Result<Value, RpcError> {
+        let world = self.app.world();
+        if let Some(entity) = entity {
+            let entity = entity_param(entity)?;
+            let components = (self.bindings.observe)(world, entity)?;
+            return Ok(json!({ "entity": entity, "components": components }));
+        }
+        Ok(observations_to_json(observe_all(world, self.bindings.observe)?))
+    }
+    ///以文本描述一个实体，不指定实体时描述所有带有 components 中全部组件的实体
+    fn describe(&mut self, entity: Option<&Value>, components: Option<&Value>) -> Result<Value, RpcError> {
+        let world = self.app.world();
+        if let Some(entity) = entity {
+            let entity = entity_param(entity)?;
+            let components = (self.bindings.describe)(world, entity).ok_or(ObserveError::NoSuchEntity(entity))?;
+            return Ok(Value::from(render_entity(entity, &components)));
+        }
+        let mut filter = SceneFilter::default();
+        for component in components.and_then(Value::as_array).into_iter().flatten() {
+            let component = component.as_str().ok_or_else(|| RpcError::invalid_params("components must be a list of component names"))?;
+            filter = filter.with_component(component);
+        }
+        Ok(Value::from(describe_scene(world, self.bindings.describe, &filter)))
+    }
+    ///发送事件，事件在下一次 step 时被处理
+    pub fn act(&mut self, event_name: &str, fields: Value) -> Result<(), RpcError> {
+        let world = self.app.world_mut();
+        let mut commands = world.commands();
+        let result = (self.bindings.emit)(&mut commands, event_name, fields);
+        world.flush();
+        Ok(result?)
+    }
+    ///按原型生成实体，实体在下一次 step 时生成
+    pub fn spawn(&mut self, prototype: &str, overrides: Option<SpawnOverrides>) {
+        let world = self.app.world_mut();
+        let mut commands = world.commands();
+        (self.bindings.spawn)(&mut commands, prototype, overrides);
+        world.flush();
+    }
+    ///推进世界 n 帧，返回世界时钟
+    pub fn step(&mut self, n: u64) -> Value {
+        for _ in 0..n {
+            self.app.update();
+        }
+        let elapsed = self.app.world().get_resource::<WorldClock>().map(|clock| clock.elapsed().as_secs_f64());
+        json!({ "steps": n, "elapsed": elapsed })
+    }
+}
+///按行读写的 JSON-RPC 2.0 服务，实现者只需要提供方法调用
+pub trait RpcHandler {
+    ///执行一个方法调用
+    fn call(&mut self, method: &str, params: &Value) -> Result<Value, RpcError>;
+    ///在标准输入输出上提供服务，直到输入结束
+    fn serve_stdio(&mut self) -> std::io::Result<()> {
+        self.serve(std::io::stdin().lock(), std::io::stdout().lock())
+    }
+    fn serve(&mut self, input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_line(&line) {
+                writeln!(output, "{}", response)?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+    ///处理一行请求，返回需要写回的响应，请求全部为通知时返回 None
+    fn handle_line(&mut self, line: &str) -> Option<String> {
+        let response = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Array(requests)) if !requests.is_empty() => {
+                let responses = requests.into_iter().filter_map(|request| handle_request(self, request)).collect::<Vec<_>>();
+                if responses.is_empty() {
+                    return None;
+                }
+                serde_json::to_value(responses)
+            }
+            Ok(request) => serde_json::to_value(handle_request(self, request)?),
+            Err(error) => serde_json::to_value(Response::new(Value::Null, Err(RpcError::new(PARSE_ERROR, error.to_string())))),
+        };
+        Some(response.expect("responses serialize to JSON").to_string())
+    }
+}
+fn handle_request(handler: &mut (impl RpcHandler + ?Sized), request: Value) -> Option<Response> {
+    let request = match serde_json::from_value::<Request>(request) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        Ok(_) => return Some(Response::new(Value::Null, Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"")))),
+        Err(error) => return Some(Response::new(Value::Null, Err(RpcError::new(INVALID_REQUEST, error.to_string())))),
+    };
+    let result = handler.call(&request.method, &request.params);
+    request.id.map(|id| Response::new(id, result))
+}
+impl RpcHandler for AgentServer {
+    fn call(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "observe" => self.observe(param(params, 0, "entity")),
+            "act" => {
+                let event_name = param(params, 0, "event_name")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| RpcError::invalid_params("act requires an event_name"))?;
+                let fields = param(params, 1, "fields").cloned().unwrap_or_else(|| json!({}));
+                self.act(event_name, fields).map(|()| Value::Null)
+            }
+            "spawn" => {
+                let prototype = param(params, 0, "prototype")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| RpcError::invalid_params("spawn requires a prototype"))?;
+                let overrides = match param(params, 1, "overrides") {
+                    Some(Value::Object(overrides)) => Some(overrides.clone()),
+                    Some(_) => return Err(RpcError::invalid_params("overrides must be an object")),
+                    None => None,
+                };
+                self.spawn(prototype, overrides);
+                Ok(Value::Null)
+            }
+            "step" => {
+                let n = match param(params, 0, "n") {
+                    Some(n) => n.as_u64().ok_or_else(|| RpcError::invalid_params("n must be a non-negative integer"))?,
+                    None => 1,
+                };
+                Ok(self.step(n))
+            }
+            "describe" => self.describe(param(params, 0, "entity"), param(params, 1, "components")),
+            "list_events" => Ok(json!(self.bindings.events)),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        }
+    }
+}
+///标准输出用于协议，Lua 中的 print 改为输出到标准错误
+fn redirect_print(lua: &Lua) -> mlua::Result<()> {
+    let print = lua.create_function(|_, values: mlua::Variadic<mlua::Value>| {
+        let values = values.iter().map(|value| value.to_string()).collect::<mlua::Result<Vec<_>>>()?;
+        eprintln!("{}", values.join("\t"));
+        Ok(())
+    })?;
+    lua.globals().set("print", print)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::Update;
+    use crate::describe::ComponentDescription;
+    use super::*;
+    #[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Health {
+        value: i64,
+    }
+    #[derive(Event, Debug, Clone, Serialize, Deserialize)]
+    struct Damage {
+        entity: Entity,
+        amount: i64,
+    }
+    fn observe_entity(world: &World, entity: Entity) -> Result<Observation, ObserveError> {
+        let entity_ref = world.get_entity(entity).map_err(|_| ObserveError::NoSuchEntity(entity))?;
+        let mut components = Observation::new();
+        if let Some(component) = entity_ref.get::<Health>() {
+            components.insert("combat::Health".to_string(), component_to_json("combat::Health", component)?);
+        }
+        Ok(components)
+    }
+    fn emit_event(commands: &mut Commands, event_name: &str, fields: Value) -> Result<(), EmitError> {
+        match event_name {
+            "combat::Damage" | "Damage" => {
+                commands.send_event(crate::api::deserialize_event::<Damage>(event_name, fields)?);
+                Ok(())
+            }
+            _ => Err(EmitError::UnknownEvent(event_name.to_string())),
+        }
+    }
+    fn spawn_prototype(commands: &mut Commands, prototype: &str, overrides: Option<SpawnOverrides>) {
+        if prototype != "combat::Dummy" {
+            return;
+        }
+        let health = match overrides.as_ref().and_then(|overrides| overrides.get("health")) {
+            Some(fields) => crate::spawn::apply_overrides("health", &Health { value: 10 }, fields).unwrap(),
+            None => Health { value: 10 },
+        };
+        commands.spawn(health);
+    }
+    fn describe_components(world: &World, entity: Entity) -> Option<Vec<ComponentDescription>> {
+        let health = world.get_entity(entity).ok()?.get::<Health>().map(|health| ComponentDescription::new("combat::Health", "生命值", health, |_| ""));
+        Some(health.into_iter().collect())
+    }
+    const EVENTS: &[EventInfo] = &[EventInfo {
+        name: "combat::Damage",
+        description: Some("造成伤害"),
+        fields: &[
+            FieldInfo { name: "entity", type_name: "EntityId", description: None },
+            FieldInfo { name: "amount", type_name: "int", description: None },
+        ],
+    }];
+    fn apply_damage(mut events: EventReader<Damage>, mut health: Query<&mut Health>) {
+        for event in events.read() {
+            if let Ok(mut health) = health.get_mut(event.entity) {
+                health.value -= event.amount;
+            }
+        }
+    }
+    fn server() -> (AgentServer, Entity) {
+        let mut app = App::new();
+        app.add_event::<Damage>().add_systems(Update, apply_damage);
+        let entity = app.world_mut().spawn(Health { value: 10 }).id();
+        let bindings = WorldBindings { observe: observe_entity, emit: emit_event, spawn: spawn_prototype, describe: describe_components, events: EVENTS };
+        (AgentServer::new(app, bindings).unwrap(), entity)
+    }
+    fn request(server: &mut AgentServer, request: Value) -> Value {
+        serde_json::from_str(&server.handle_line(&request.to_string()).unwrap()).unwrap()
+    }
+    #[test]
+    fn test_observe_act_step() {
+        let (mut server, entity) = server();
+        let observed = request(&mut server, json!({ "jsonrpc": "2.0", "id": 1, "method": "observe", "params": { "entity": entity } }));
+        assert_eq!(observed, json!({ "jsonrpc": "2.0", "id": 1, "result": { "entity": entity.to_bits(), "components": { "combat::Health": { "value": 10 } } } }));
+        let acted = request(&mut server, json!({ "jsonrpc": "2.0", "id": 2, "method": "act", "params": ["Damage", { "entity": entity, "amount": 3 }] }));
+        assert_eq!(acted["result"], Value::Null);
+        // 事件在 step 时才被处理
+        assert_eq!(server.app().world().get::<Health>(entity).unwrap().value, 10);
+        let stepped = request(&mut server, json!({ "jsonrpc": "2.0", "id": 3, "method": "step", "params": { "n": 2 } }));
+        assert_eq!(stepped["result"]["steps"], 2);
+        let observed = request(&mut server, json!({ "jsonrpc": "2.0", "id": 4, "method": "observe" }));
+        assert_eq!(observed["result"], json!([{ "entity": entity.to_bits(), "components": { "combat::Health": { "value": 7 } } }]));
+        let described = request(&mut server, json!({ "jsonrpc": "2.0", "id": 5, "method": "describe", "params": { "components": ["Health"] } }));
+        assert_eq!(described["result"], format!("Entity {}:\n- Health: 生命值\n  - value: 7", entity.to_bits()));
+        let events = request(&mut server, json!({ "jsonrpc": "2.0", "id": 6, "method": "list_events" }));
+        assert_eq!(events["result"][0]["fields"][1], json!({ "name": "amount", "type": "int" }));
+    }
+    #[test]
+    fn test_spawn() {
+        let (mut server, _) = server();
+        let spawned = request(&mut server, json!({ "jsonrpc": "2.0", "id": 1, "method": "spawn", "params": ["combat::Dummy", { "health": { "value": 4 } }] }));
+        assert_eq!(spawned["result"], Value::Null);
+        request(&mut server, json!({ "jsonrpc": "2.0", "id": 2, "method": "spawn", "params": { "prototype": "combat::Dummy" } }));
+        let observed = request(&mut server, json!({ "jsonrpc": "2.0", "id": 3, "method": "observe" }));
+        let values = observed["result"].as_array().unwrap().iter().map(|entity| entity["components"]["combat::Health"]["value"].clone()).collect::<Vec<_>>();
+        assert_eq!(values, vec![json!(10), json!(4), json!(10)]);
+        let invalid = request(&mut server, json!({ "jsonrpc": "2.0", "id": 4, "method": "spawn", "params": { "prototype": "combat::Dummy", "overrides": [] } }));
+        assert_eq!(invalid["error"]["code"], INVALID_PARAMS);
+        let missing = request(&mut server, json!({ "jsonrpc": "2.0", "id": 5, "method": "spawn" }));
+        assert_eq!(missing["error"]["code"], INVALID_PARAMS);
+    }
+    #[test]
+    fn test_protocol_errors() {
+        let (mut server, entity) = server();
+        let error_code = |response: Value| response["error"]["code"].as_i64().unwrap();
+        assert_eq!(error_code(serde_json::from_str(&server.handle_line("{not json").unwrap()).unwrap()), PARSE_ERROR);
+        assert_eq!(error_code(request(&mut server, json!({ "jsonrpc": "1.0", "id": 1, "method": "step" }))), INVALID_REQUEST);
+        assert_eq!(error_code(request(&mut server, json!({ "jsonrpc": "2.0", "id": 1, "method": "fly" }))), METHOD_NOT_FOUND);
+        let unknown_event = request(&mut server, json!({ "jsonrpc": "2.0", "id": 2, "method": "act", "params": { "event_name": "Heal" } }));
+        assert_eq!(error_code(unknown_event), INVALID_PARAMS);
+        let missing_field = request(&mut server, json!({ "jsonrpc": "2.0", "id": 3, "method": "act", "params": { "event_name": "Damage", "fields": { "entity": entity } } }));
+        assert_eq!(error_code(missing_field), INVALID_PARAMS);
+        // 通知不返回响应，批量请求中只返回有 id 的请求的响应
+        assert_eq!(server.handle_line(r#"{"jsonrpc": "2.0", "method": "step"}"#), None);
+        let batch = server.handle_line(r#"[{"jsonrpc": "2.0", "method": "step"}, {"jsonrpc": "2.0", "id": 7, "method": "step", "params": [0]}]"#).unwrap();
+        let batch = serde_json::from_str::<Value>(&batch).unwrap();
+        assert_eq!(batch.as_array().unwrap().len(), 1);
+        assert_eq!(batch[0]["id"], 7);
+        // id 为 null 的请求不是通知
+        let response = server.handle_line(r#"{"jsonrpc": "2.0", "id": null, "method": "step", "params": [0]}"#).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&response).unwrap(), json!({ "jsonrpc": "2.0", "id": null, "result": { "steps": 0, "elapsed": null } }));
+    }
+}
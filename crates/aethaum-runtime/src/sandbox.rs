@@ -0,0 +1,117 @@
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib, VmState};
+use thiserror::Error;
+
+pub const DEFAULT_INSTRUCTION_LIMIT: u64 = 1_000_000;
+pub const DEFAULT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+///每执行这么多条指令检查一次预算
+const HOOK_INTERVAL: u32 = 1_000;
+
+///脚本沙箱的限制，0 表示不限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandboxConfig {
+    ///单次脚本调用（一个实体执行一次 update 或事件处理器）最多执行的指令数
+    pub instruction_limit: u64,
+    ///Lua 虚拟机可以使用的内存（字节）
+    pub memory_limit: usize,
+}
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            instruction_limit: DEFAULT_INSTRUCTION_LIMIT,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        }
+    }
+}
+impl SandboxConfig {
+    pub fn with_instruction_limit(mut self, instruction_limit: u64) -> Self {
+        self.instruction_limit = instruction_limit;
+        self
+    }
+    pub fn with_memory_limit(mut self, memory_limit: usize) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+}
+
+///当前调用已经消耗的指令数，保存在 Lua 的 app data 中
+struct InstructionBudget {
+    limit: u64,
+    used: u64,
+}
+
+///创建受限的 Lua 虚拟机：不加载 os、io、package 与 debug 库，不能读取文件
+pub fn create_sandboxed_lua(config: &SandboxConfig) -> mlua::Result<Lua> {
+    let libs = StdLib::COROUTINE | StdLib::TABLE | StdLib::STRING | StdLib::UTF8 | StdLib::MATH;
+    let lua = Lua::new_with(libs, LuaOptions::default())?;
+    let globals = lua.globals();
+    for name in ["dofile", "loadfile", "require"] {
+        globals.raw_set(name, mlua::Value::Nil)?;
+    }
+    lua.set_memory_limit(config.memory_limit)?;
+    if config.instruction_limit > 0 {
+        lua.set_app_data(InstructionBudget { limit: config.instruction_limit, used: 0 });
+        lua.set_global_hook(HookTriggers::new().every_nth_instruction(HOOK_INTERVAL), |lua, _| {
+            let Some(mut budget) = lua.app_data_mut::<InstructionBudget>() else {
+                return Ok(VmState::Continue);
+            };
+            budget.used += u64::from(HOOK_INTERVAL);
+            if budget.used > budget.limit {
+                return Err(mlua::Error::runtime(format!("instruction budget of {} exceeded", budget.limit)));
+            }
+            Ok(VmState::Continue)
+        })?;
+    }
+    Ok(lua)
+}
+///每次脚本调用前重置指令预算
+pub fn reset_instruction_budget(lua: &Lua) {
+    if let Some(mut budget) = lua.app_data_mut::<InstructionBudget>() {
+        budget.used = 0;
+    }
+}
+
+///脚本执行失败，标明出错的系统与处理器（"update" 或 "event_handlers[i]"）
+#[derive(Debug, Error)]
+#[error("System \"{system}\" failed in {handler}: {source}")]
+pub struct ScriptError {
+    pub system: String,
+    pub handler: String,
+    pub source: mlua::Error,
+}
+impl ScriptError {
+    pub fn new(system: impl Into<String>, handler: impl Into<String>, source: mlua::Error) -> Self {
+        Self {
+            system: system.into(),
+            handler: handler.into(),
+            source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_restricted_stdlib() {
+        let lua = create_sandboxed_lua(&SandboxConfig::default()).unwrap();
+        for name in ["os", "io", "require", "dofile", "loadfile", "package", "debug"] {
+            assert!(lua.globals().get::<mlua::Value>(name).unwrap().is_nil(), "{} should not be available", name);
+        }
+        assert_eq!(lua.load("return math.max(1, 2) .. string.rep('a', 2)").eval::<String>().unwrap(), "2aa");
+    }
+    #[test]
+    fn test_instruction_budget() {
+        let lua = create_sandboxed_lua(&SandboxConfig::default().with_instruction_limit(10_000)).unwrap();
+        let error = lua.load("while true do end").exec().unwrap_err();
+        assert!(error.to_string().contains("instruction budget of 10000 exceeded"));
+        // 预算按调用计算，重置后可以继续执行
+        reset_instruction_budget(&lua);
+        assert_eq!(lua.load("local n = 0 for i = 1, 100 do n = n + i end return n").eval::<i64>().unwrap(), 5050);
+    }
+    #[test]
+    fn test_memory_limit() {
+        let lua = create_sandboxed_lua(&SandboxConfig::default().with_instruction_limit(0).with_memory_limit(4 * 1024 * 1024)).unwrap();
+        let error = lua.load("local t = {} for i = 1, 1e7 do t[i] = string.rep('x', 64) .. i end").exec().unwrap_err();
+        assert!(matches!(error, mlua::Error::MemoryError(_)), "{}", error);
+    }
+}
@@ -0,0 +1,80 @@
+use std::cmp::Ordering;
+use bevy_app::{App, Update};
+use bevy_ecs::prelude::*;
+use bevy_ecs::schedule::ScheduleConfigs;
+use bevy_ecs::system::ScheduleSystem;
+
+///每个生成系统独占的 SystemSet，用于在 Update 中按优先级排序
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AethaumSystemSet {
+    pub priority: u32,
+    pub name: &'static str, //模块限定的系统名，如 "combat::HealthSystem"
+}
+impl AethaumSystemSet {
+    pub fn new(priority: u32, name: &'static str) -> Self {
+        Self { priority, name }
+    }
+    ///执行顺序：优先级高的先执行，优先级相同时按系统名排序，保证顺序确定
+    pub fn run_order(&self, other: &Self) -> Ordering {
+        other.priority.cmp(&self.priority).then_with(|| self.name.cmp(other.name))
+    }
+}
+///已注册的系统集合，新注册的系统据此与已有系统建立先后关系
+#[derive(Resource, Debug, Default)]
+pub struct AethaumSystemOrder {
+    sets: Vec<AethaumSystemSet>,
+}
+impl AethaumSystemOrder {
+    pub fn sets(&self) -> &[AethaumSystemSet] {
+        &self.sets
+    }
+}
+pub trait AethaumAppExt {
+    ///将生成系统加入 Update，并按优先级与其他已注册的生成系统排序
+    fn add_aethaum_system<M>(&mut self, set: AethaumSystemSet, system: impl IntoScheduleConfigs<ScheduleSystem, M>) -> &mut Self;
+}
+impl AethaumAppExt for App {
+    fn add_aethaum_system<M>(&mut self, set: AethaumSystemSet, system: impl IntoScheduleConfigs<ScheduleSystem, M>) -> &mut Self {
+        let registered = self.world_mut()
+            .get_resource_or_init::<AethaumSystemOrder>()
+            .sets
+            .clone();
+        for other in registered.iter() {
+            let config: ScheduleConfigs<_> = match set.run_order(other) {
+                Ordering::Greater => set.clone().after(other.clone()),
+                _ => set.clone().before(other.clone()),
+            };
+            self.configure_sets(Update, config);
+        }
+        self.world_mut().resource_mut::<AethaumSystemOrder>().sets.push(set.clone());
+        self.add_systems(Update, system.in_set(set))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[derive(Resource, Default)]
+    struct Trace(Vec<&'static str>);
+
+    #[test]
+    fn test_run_order() {
+        let high = AethaumSystemSet::new(100, "combat::HealthSystem");
+        let low = AethaumSystemSet::new(10, "combat::AISystem");
+        let tie = AethaumSystemSet::new(100, "explore::MoveSystem");
+        assert_eq!(high.run_order(&low), Ordering::Less);
+        assert_eq!(high.run_order(&tie), Ordering::Less);
+        assert_eq!(low.run_order(&tie), Ordering::Greater);
+    }
+    #[test]
+    fn test_systems_run_by_priority() {
+        let mut app = App::new();
+        app.init_resource::<Trace>()
+            .add_aethaum_system(AethaumSystemSet::new(1, "a::Low"), |mut trace: ResMut<Trace>| trace.0.push("low"))
+            .add_aethaum_system(AethaumSystemSet::new(50, "b::Middle"), |mut trace: ResMut<Trace>| trace.0.push("middle"))
+            .add_aethaum_system(AethaumSystemSet::new(100, "c::High"), |mut trace: ResMut<Trace>| trace.0.push("high"));
+        app.update();
+        assert_eq!(app.world().resource::<Trace>().0, vec!["high", "middle", "low"]);
+        assert_eq!(app.world().resource::<AethaumSystemOrder>().sets().len(), 3);
+    }
+}
@@ -0,0 +1,159 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use mlua::{Function, Lua};
+
+///脚本在系统中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScriptSlot {
+    UpdateCondition,
+    UpdateLogic,
+    ///按声明顺序的事件处理器下标
+    EventHandler(usize),
+}
+///编译后脚本的键：系统的限定名与脚本位置
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ScriptKey {
+    pub system: Cow<'static, str>,
+    pub slot: ScriptSlot,
+}
+impl ScriptKey {
+    pub fn new(system: impl Into<Cow<'static, str>>, slot: ScriptSlot) -> Self {
+        Self { system: system.into(), slot }
+    }
+}
+///脚本来源，内嵌脚本以 name 作为代码块名，文件脚本相对于项目根目录
+#[derive(Debug, Clone, Copy)]
+pub enum ScriptSource<'a> {
+    Embed { name: &'a str, source: &'a str },
+    File(&'a Path),
+}
+
+///编译后的脚本，引用同一文件的脚本共享同一个函数
+#[derive(Debug, Default)]
+pub struct ScriptCache {
+    scripts: HashMap<ScriptKey, Function>,
+    files: HashMap<PathBuf, Function>,
+    ///文件脚本的路径相对于此目录，未设置时相对于运行目录
+    project_root: Option<PathBuf>,
+}
+impl ScriptCache {
+    pub fn with_project_root(mut self, project_root: impl Into<PathBuf>) -> Self {
+        self.project_root = Some(project_root.into());
+        self
+    }
+    ///文件脚本在磁盘上的位置
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        match &self.project_root {
+            Some(project_root) => project_root.join(path),
+            None => path.to_path_buf(),
+        }
+    }
+    pub fn get(&self, key: &ScriptKey) -> Option<&Function> {
+        self.scripts.get(key)
+    }
+    pub fn len(&self) -> usize {
+        self.scripts.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+    ///已编译的不同脚本文件数
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+    ///编译脚本，已经编译过的直接返回
+    pub fn load(&mut self, lua: &Lua, key: ScriptKey, source: ScriptSource) -> mlua::Result<Function> {
+        if let Some(function) = self.scripts.get(&key) {
+            return Ok(function.clone());
+        }
+        let function = match source {
+            ScriptSource::Embed { name, source } => lua.load(source).set_name(name).into_function()?,
+            ScriptSource::File(path) => match self.files.get(path) {
+                Some(function) => function.clone(),
+                None => {
+                    let full_path = self.resolve(path);
+                    let source = std::fs::read(&full_path)
+                        .map_err(|error| mlua::Error::runtime(format!("cannot read script {}: {}", full_path.display(), error)))?;
+                    // 代码块名使用系统定义中的路径，与热重载时一致
+                    let function = lua.load(source).set_name(format!("@{}", path.display())).into_function()?;
+                    self.files.insert(path.to_path_buf(), function.clone());
+                    function
+                }
+            },
+        };
+        self.scripts.insert(key, function.clone());
+        Ok(function)
+    }
+    ///替换编译后的脚本，用于热重载
+    pub fn insert(&mut self, key: ScriptKey, function: Function) {
+        self.scripts.insert(key, function);
+    }
+    ///替换编译后的脚本文件，之后首次加载该文件的脚本使用新的函数
+    pub fn insert_file(&mut self, path: PathBuf, function: Function) {
+        self.files.insert(path, function);
+    }
+    ///清空编译后的脚本，保留项目根目录
+    pub fn clear(&mut self) {
+        self.scripts.clear();
+        self.files.clear();
+    }
+}
+
+///从 Lua 虚拟机的脚本缓存中获取编译后的脚本，缓存随虚拟机一同创建与销毁
+pub fn load_script(lua: &Lua, key: ScriptKey, source: ScriptSource) -> mlua::Result<Function> {
+    if let Some(function) = lua.app_data_ref::<ScriptCache>().and_then(|cache| cache.get(&key).cloned()) {
+        return Ok(function);
+    }
+    let mut cache = lua.remove_app_data::<ScriptCache>().unwrap_or_default();
+    let result = cache.load(lua, key, source);
+    lua.set_app_data(cache);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_embedded_scripts_are_compiled_once() {
+        let lua = Lua::new();
+        let source = ScriptSource::Embed { name: "Counter::update", source: "counter = (counter or 0) + 1" };
+        let first = load_script(&lua, ScriptKey::new("game::Counter", ScriptSlot::UpdateLogic), source).unwrap();
+        // 第二次获取时不再使用传入的源码
+        let ignored = ScriptSource::Embed { name: "Counter::update", source: "error('recompiled')" };
+        let second = load_script(&lua, ScriptKey::new("game::Counter", ScriptSlot::UpdateLogic), ignored).unwrap();
+        first.call::<()>(()).unwrap();
+        second.call::<()>(()).unwrap();
+        assert_eq!(lua.globals().get::<i64>("counter").unwrap(), 2);
+        assert_eq!(lua.app_data_ref::<ScriptCache>().unwrap().len(), 1);
+    }
+    #[test]
+    fn test_file_scripts_are_shared() {
+        let path = std::env::temp_dir().join(format!("aethaum_script_cache_{}.lua", std::process::id()));
+        std::fs::write(&path, "return 42").unwrap();
+        let lua = Lua::new();
+        let mut cache = ScriptCache::default();
+        let first = cache.load(&lua, ScriptKey::new("a::Heal", ScriptSlot::EventHandler(0)), ScriptSource::File(&path)).unwrap();
+        let second = cache.load(&lua, ScriptKey::new("b::Regen", ScriptSlot::UpdateLogic), ScriptSource::File(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(second.call::<i64>(()).unwrap(), 42);
+        assert_eq!((cache.len(), cache.file_count()), (2, 1));
+        assert!(cache.load(&lua, ScriptKey::new("c::Broken", ScriptSlot::UpdateLogic), ScriptSource::Embed { name: "broken", source: "end" }).is_err());
+    }
+    #[test]
+    fn test_file_scripts_resolve_against_project_root() {
+        let root = std::env::temp_dir().join(format!("aethaum_script_root_{}", std::process::id()));
+        std::fs::create_dir_all(root.join("scripts")).unwrap();
+        std::fs::write(root.join("scripts/heal.lua"), "error('healed')").unwrap();
+        let lua = Lua::new();
+        lua.set_app_data(ScriptCache::default().with_project_root(&root));
+        let path = Path::new("scripts/heal.lua");
+        let function = load_script(&lua, ScriptKey::new("a::Heal", ScriptSlot::EventHandler(0)), ScriptSource::File(path)).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        // 错误信息中的代码块名是系统定义中的相对路径
+        assert!(function.call::<()>(()).unwrap_err().to_string().contains("scripts/heal.lua:1: healed"));
+        let missing = load_script(&lua, ScriptKey::new("a::Regen", ScriptSlot::UpdateLogic), ScriptSource::File(Path::new("scripts/regen.lua")));
+        assert!(missing.unwrap_err().to_string().contains(&root.join("scripts/regen.lua").display().to_string()));
+    }
+}
@@ -0,0 +1,73 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+///生成实体时对组件字段的覆盖：组件名（与 Lua 中相同的蛇形名）-> { 字段名: 值 }
+pub type SpawnOverrides = Map<String, Value>;
+
+#[derive(Debug, Error)]
+pub enum OverrideError {
+    #[error("Prototype '{prototype}' has no component '{component}'")]
+    UnknownComponent {
+        prototype: String,
+        component: String,
+    },
+    #[error("Overrides of component '{0}' must be a table of fields")]
+    NotATable(String),
+    #[error("Component '{component}' has no field '{field}'")]
+    UnknownField {
+        component: String,
+        field: String,
+    },
+    #[error("Invalid overrides for component '{component}': {source}")]
+    InvalidValue {
+        component: String,
+        source: serde_json::Error,
+    },
+}
+
+///在组件当前值的基础上覆盖部分字段，未出现的字段保持不变
+pub fn apply_overrides<T: Serialize + DeserializeOwned>(component: &str, value: &T, fields: &Value) -> Result<T, OverrideError> {
+    let invalid = |source| OverrideError::InvalidValue { component: component.to_string(), source };
+    let Value::Object(fields) = fields else {
+        return Err(OverrideError::NotATable(component.to_string()));
+    };
+    let Value::Object(mut current) = serde_json::to_value(value).map_err(invalid)? else {
+        return Err(OverrideError::NotATable(component.to_string()));
+    };
+    for (field, field_value) in fields {
+        match current.get_mut(field) {
+            Some(slot) => *slot = field_value.clone(),
+            None => return Err(OverrideError::UnknownField { component: component.to_string(), field: field.clone() }),
+        }
+    }
+    serde_json::from_value(Value::Object(current)).map_err(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+    use super::*;
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Health {
+        value: f32,
+        max_value: f32,
+    }
+    #[test]
+    fn test_apply_overrides() {
+        let health = Health { value: 100.0, max_value: 100.0 };
+        let health = apply_overrides("health", &health, &json!({ "value": 50.0 })).unwrap();
+        assert_eq!(health, Health { value: 50.0, max_value: 100.0 });
+        assert!(matches!(
+            apply_overrides("health", &health, &json!({ "shield": 1.0 })),
+            Err(OverrideError::UnknownField { .. })
+        ));
+        assert!(matches!(
+            apply_overrides("health", &health, &json!({ "value": "full" })),
+            Err(OverrideError::InvalidValue { .. })
+        ));
+        assert!(matches!(apply_overrides("health", &health, &json!(1.0)), Err(OverrideError::NotATable(_))));
+    }
+}